@@ -0,0 +1,479 @@
+//! Streaming compression of encoded metrics
+//!
+//! Large expositions compress very well. Rather than compressing the output of
+//! [`BufferedTextEncoder::finish`](crate::text::BufferedTextEncoder::finish) after the fact,
+//! a [`CompressWriter`] can be given to a [`TextEncoder`](crate::text::TextEncoder) to compress while encoding,
+//! or a [`CompressedTextEncoder`](crate::text::CompressedTextEncoder) can be used.
+//!
+//! [`Compressor`]s are reset after each finished stream, so they should ideally be cached and re-used between collections.
+//!
+//! Enable the `gzip` or `zstd` features for the [`Gzip`] and [`Zstd`] compressors.
+//!
+//! ```
+//! # #[cfg(feature = "gzip")] {
+//! use measured::{
+//!     Counter,
+//!     compress::Gzip,
+//!     metric::{MetricFamilyEncoding, name::MetricName},
+//!     text::CompressedTextEncoder,
+//! };
+//!
+//! let counter = Counter::new();
+//!
+//! let mut enc = CompressedTextEncoder::new(Gzip::default());
+//!
+//! counter
+//!     .collect_family_into(MetricName::from_str("requests_total"), &mut enc)
+//!     .unwrap();
+//! let body = enc.finish().unwrap();
+//! // send `body` with `Content-Encoding: gzip`
+//! # }
+//! ```
+
+use std::io::{self, Write};
+
+use bytes::BytesMut;
+
+/// The number of bytes buffered before compressing, and reserved in the output buffer when compressing
+const CHUNK: usize = 8 * 1024;
+
+/// A streaming compression algorithm, which can be re-used for multiple streams.
+pub trait Compressor {
+    /// The value of the `Content-Encoding` header for this compression
+    const CONTENT_ENCODING: &'static str;
+
+    /// Compress the input, appending any compressed output to `out`
+    ///
+    /// # Errors
+    /// Will error if the compressor encountered an internal error.
+    fn compress(&mut self, input: &[u8], out: &mut BytesMut) -> io::Result<()>;
+
+    /// Append all pending compressed output to `out`, without ending the stream.
+    ///
+    /// # Errors
+    /// Will error if the compressor encountered an internal error.
+    fn flush(&mut self, out: &mut BytesMut) -> io::Result<()>;
+
+    /// End the current stream, appending the remaining compressed output to `out`.
+    ///
+    /// The compressor is then reset, ready to start a new stream.
+    ///
+    /// # Errors
+    /// Will error if the compressor encountered an internal error.
+    /// The compressor should not be re-used after an error.
+    fn finish(&mut self, out: &mut BytesMut) -> io::Result<()>;
+}
+
+/// An [`io::Write`] adapter that compresses all data written to it.
+///
+/// Small writes are buffered, so the compressor runs over larger chunks of input.
+/// The stream must be ended with [`CompressWriter::finish`].
+/// [`Write::flush`] only flushes the data written so far.
+pub struct CompressWriter<W, C> {
+    writer: W,
+    compressor: C,
+    /// Input not yet given to the compressor
+    input: Vec<u8>,
+    /// Compressed output not yet written to the inner writer
+    buf: BytesMut,
+}
+
+impl<W: Write, C: Compressor> CompressWriter<W, C> {
+    /// Create a new writer which compresses into `writer`.
+    pub fn new(writer: W, compressor: C) -> Self {
+        Self {
+            writer,
+            compressor,
+            input: Vec::with_capacity(CHUNK),
+            buf: BytesMut::new(),
+        }
+    }
+
+    /// End the current compressed stream and flush it to the inner writer.
+    ///
+    /// The writer can then be re-used to write a new compressed stream.
+    ///
+    /// # Errors
+    /// Will error if the compressor or the inner writer errors.
+    pub fn finish(&mut self) -> io::Result<()> {
+        self.compress_input()?;
+        self.compressor.finish(&mut self.buf)?;
+        self.write_buf()?;
+        self.writer.flush()
+    }
+
+    /// Get a reference to the inner writer
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// Get a mutable reference to the inner writer
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    /// Extract the inner writer and compressor.
+    ///
+    /// Any data not yet flushed is lost.
+    pub fn into_inner(self) -> (W, C) {
+        (self.writer, self.compressor)
+    }
+
+    fn compress_input(&mut self) -> io::Result<()> {
+        if !self.input.is_empty() {
+            self.compressor.compress(&self.input, &mut self.buf)?;
+            self.input.clear();
+        }
+        Ok(())
+    }
+
+    fn write_buf(&mut self) -> io::Result<()> {
+        let res = self.writer.write_all(&self.buf);
+        self.buf.clear();
+        res
+    }
+}
+
+impl<W: Write, C: Compressor> Write for CompressWriter<W, C> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.input.len() + buf.len() > CHUNK {
+            self.compress_input()?;
+        }
+        if buf.len() >= CHUNK {
+            self.compressor.compress(buf, &mut self.buf)?;
+        } else {
+            self.input.extend_from_slice(buf);
+        }
+        if self.buf.len() >= CHUNK {
+            self.write_buf()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.compress_input()?;
+        self.compressor.flush(&mut self.buf)?;
+        self.write_buf()?;
+        self.writer.flush()
+    }
+}
+
+#[cfg(feature = "gzip")]
+pub use self::gzip::Gzip;
+
+#[cfg(feature = "gzip")]
+mod gzip {
+    use std::{io, mem::MaybeUninit};
+
+    use bytes::{BufMut, BytesMut};
+    use flate2::{Compress, Compression, Crc, FlushCompress};
+
+    use super::{CHUNK, Compressor};
+
+    /// Minimal gzip member header: no flags, no mtime, unknown OS
+    const HEADER: [u8; 10] = [0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff];
+
+    /// A [`Compressor`] producing gzip streams
+    pub struct Gzip {
+        compress: Compress,
+        crc: Crc,
+        started: bool,
+    }
+
+    impl Default for Gzip {
+        fn default() -> Self {
+            Self::new(Compression::default().level())
+        }
+    }
+
+    impl Gzip {
+        /// Create a new gzip compressor with the given compression level between 0 and 9
+        pub fn new(level: u32) -> Self {
+            Self {
+                compress: Compress::new(Compression::new(level), false),
+                crc: Crc::new(),
+                started: false,
+            }
+        }
+
+        fn deflate(
+            &mut self,
+            mut input: &[u8],
+            out: &mut BytesMut,
+            flush: FlushCompress,
+        ) -> io::Result<()> {
+            if !self.started {
+                self.started = true;
+                out.put_slice(&HEADER);
+            }
+            self.crc.update(input);
+
+            loop {
+                let before_in = self.compress.total_in();
+                let filled = with_spare_capacity(out, |buf| {
+                    let before_out = self.compress.total_out();
+                    self.compress.compress_uninit(input, buf, flush)?;
+                    Ok((self.compress.total_out() - before_out) as usize)
+                })?;
+                input = &input[(self.compress.total_in() - before_in) as usize..];
+
+                // the output was not filled, so all input was consumed and all output was flushed
+                if input.is_empty() && !filled {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Run a compression step over the spare capacity at the end of `out`, without initializing it first.
+    /// `f` returns the number of bytes it wrote. Returns whether the spare capacity was filled.
+    fn with_spare_capacity(
+        out: &mut BytesMut,
+        f: impl FnOnce(&mut [MaybeUninit<u8>]) -> io::Result<usize>,
+    ) -> io::Result<bool> {
+        out.reserve(CHUNK);
+        let spare = out.spare_capacity_mut();
+        let capacity = spare.len();
+        let written = f(spare)?;
+        assert!(written <= capacity, "wrote past the spare capacity");
+        // SAFETY: the compressor initialized the first `written` bytes of the spare capacity
+        unsafe { out.set_len(out.len() + written) };
+        Ok(written == capacity)
+    }
+
+    impl Compressor for Gzip {
+        const CONTENT_ENCODING: &'static str = "gzip";
+
+        fn compress(&mut self, input: &[u8], out: &mut BytesMut) -> io::Result<()> {
+            self.deflate(input, out, FlushCompress::None)
+        }
+
+        fn flush(&mut self, out: &mut BytesMut) -> io::Result<()> {
+            self.deflate(&[], out, FlushCompress::Sync)
+        }
+
+        fn finish(&mut self, out: &mut BytesMut) -> io::Result<()> {
+            self.deflate(&[], out, FlushCompress::Finish)?;
+            out.put_u32_le(self.crc.sum());
+            out.put_u32_le(self.crc.amount());
+
+            self.compress.reset();
+            self.crc.reset();
+            self.started = false;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(feature = "zstd")]
+pub use self::zstd::Zstd;
+
+#[cfg(feature = "zstd")]
+mod zstd {
+    use std::io;
+
+    use bytes::BytesMut;
+    use zstd::{
+        stream::raw::{Encoder, InBuffer, Operation, OutBuffer},
+        zstd_safe::WriteBuf,
+    };
+
+    use super::{CHUNK, Compressor};
+
+    /// Lets zstd write directly into the spare capacity of a [`BytesMut`], like it does for a `Vec<u8>`
+    struct Output<'a>(&'a mut BytesMut);
+
+    // SAFETY: the capacity and pointer describe the allocation of the `BytesMut`,
+    // and `filled_until` is only called once zstd has initialized that prefix.
+    unsafe impl WriteBuf for Output<'_> {
+        fn as_slice(&self) -> &[u8] {
+            self.0
+        }
+
+        fn capacity(&self) -> usize {
+            self.0.capacity()
+        }
+
+        fn as_mut_ptr(&mut self) -> *mut u8 {
+            self.0.as_mut_ptr()
+        }
+
+        unsafe fn filled_until(&mut self, n: usize) {
+            // SAFETY: guaranteed by the caller
+            unsafe { self.0.set_len(n) }
+        }
+    }
+
+    /// Run a zstd operation, appending its output to the spare capacity of `out`
+    fn with_output<T>(
+        out: &mut BytesMut,
+        f: impl FnOnce(&mut OutBuffer<'_, Output<'_>>) -> io::Result<T>,
+    ) -> io::Result<T> {
+        out.reserve(CHUNK);
+        let pos = out.len();
+        f(&mut OutBuffer::around_pos(&mut Output(out), pos))
+    }
+
+    /// A [`Compressor`] producing zstd frames
+    pub struct Zstd {
+        encoder: Encoder<'static>,
+    }
+
+    impl Zstd {
+        /// Create a new zstd compressor with the given compression level.
+        ///
+        /// A level of 0 uses zstd's default level.
+        ///
+        /// # Errors
+        /// Will error if the zstd context could not be created.
+        pub fn new(level: i32) -> io::Result<Self> {
+            Ok(Self {
+                encoder: Encoder::new(level)?,
+            })
+        }
+
+        /// Repeatedly call `op` until it reports no more pending output
+        fn drain(
+            &mut self,
+            out: &mut BytesMut,
+            mut op: impl FnMut(
+                &mut Encoder<'static>,
+                &mut OutBuffer<'_, Output<'_>>,
+            ) -> io::Result<usize>,
+        ) -> io::Result<()> {
+            loop {
+                let remaining = with_output(out, |output| op(&mut self.encoder, output))?;
+                if remaining == 0 {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    impl Compressor for Zstd {
+        const CONTENT_ENCODING: &'static str = "zstd";
+
+        fn compress(&mut self, mut input: &[u8], out: &mut BytesMut) -> io::Result<()> {
+            while !input.is_empty() {
+                let read = with_output(out, |output| {
+                    let mut input = InBuffer::around(input);
+                    self.encoder.run(&mut input, output)?;
+                    Ok(input.pos())
+                })?;
+                input = &input[read..];
+            }
+            Ok(())
+        }
+
+        fn flush(&mut self, out: &mut BytesMut) -> io::Result<()> {
+            self.drain(out, |encoder, output| encoder.flush(output))
+        }
+
+        fn finish(&mut self, out: &mut BytesMut) -> io::Result<()> {
+            self.drain(out, |encoder, output| encoder.finish(output, true))?;
+            self.encoder.reinit()
+        }
+    }
+}
+
+#[cfg(all(test, any(feature = "gzip", feature = "zstd")))]
+mod tests {
+    use bytes::{BufMut, BytesMut};
+
+    use crate::{
+        CounterVec,
+        label::StaticLabelSet,
+        metric::{MetricFamilyEncoding, name::MetricName},
+        text::{BufferedTextEncoder, CompressedTextEncoder, TextEncoder},
+    };
+
+    use super::{CompressWriter, Compressor};
+
+    #[derive(Clone, Copy, PartialEq, Debug, measured_derive::FixedCardinalityLabel)]
+    #[label(crate = crate, singleton = "id")]
+    enum Id {
+        A,
+        B,
+        C,
+    }
+
+    fn counters() -> CounterVec<StaticLabelSet<Id>> {
+        let counters = CounterVec::new();
+        counters.inc_by(Id::A, 1);
+        counters.inc_by(Id::B, 100);
+        counters.inc_by(Id::C, 10000);
+        counters
+    }
+
+    #[cfg(feature = "gzip")]
+    fn gunzip(b: &[u8]) -> String {
+        use std::io::Read;
+
+        let mut s = String::new();
+        flate2::read::MultiGzDecoder::new(b)
+            .read_to_string(&mut s)
+            .unwrap();
+        s
+    }
+
+    #[cfg(feature = "zstd")]
+    fn unzstd(b: &[u8]) -> String {
+        String::from_utf8(zstd::decode_all(b).unwrap()).unwrap()
+    }
+
+    fn round_trip<C: Compressor>(compressor: impl Fn() -> C, decompress: fn(&[u8]) -> String) {
+        let counters = counters();
+        let name = MetricName::from_str("requests_total");
+
+        let mut enc = BufferedTextEncoder::new();
+        counters.collect_family_into(name, &mut enc).unwrap();
+        let expected = String::from_utf8(enc.finish().to_vec()).unwrap();
+
+        // the compressor is re-used between collections
+        let mut enc = CompressedTextEncoder::new(compressor());
+        for _ in 0..2 {
+            counters.collect_family_into(name, &mut enc).unwrap();
+            let compressed = enc.finish().unwrap();
+            assert_eq!(decompress(&compressed), expected);
+        }
+
+        let mut enc = TextEncoder::new(CompressWriter::new(BytesMut::new().writer(), compressor()));
+        for _ in 0..2 {
+            counters.collect_family_into(name, &mut enc).unwrap();
+            enc.flush().unwrap();
+            enc.writer.finish().unwrap();
+            let compressed = enc.writer.get_mut().get_mut().split();
+            assert_eq!(decompress(&compressed), expected);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "gzip")]
+    fn gzip() {
+        round_trip(super::Gzip::default, gunzip);
+    }
+
+    #[test]
+    #[cfg(feature = "zstd")]
+    fn zstd() {
+        round_trip(|| super::Zstd::new(0).unwrap(), unzstd);
+    }
+
+    #[test]
+    #[cfg(feature = "gzip")]
+    fn large_input() {
+        use std::io::Write;
+
+        let input: String = (0..100_000).map(|i| format!("line {i}\n")).collect();
+
+        let mut w = CompressWriter::new(BytesMut::new().writer(), super::Gzip::new(1));
+        w.write_all(input.as_bytes()).unwrap();
+        w.flush().unwrap();
+        w.write_all(input.as_bytes()).unwrap();
+        w.finish().unwrap();
+
+        let compressed = w.into_inner().0.into_inner();
+        assert!(compressed.len() < input.len());
+        assert_eq!(gunzip(&compressed), input.repeat(2));
+    }
+}
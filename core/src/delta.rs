@@ -0,0 +1,487 @@
+//! Reporting the changes in metrics between collections
+//!
+//! A [`DeltaEncoder`] wraps any other [`Encoding`] and remembers the values of every series
+//! from the previous collection. Counters and histograms are written as the increase since the
+//! previous collection, and gauges are written as their current value. Series that did not change are skipped,
+//! as are metric families with no changed series.
+//!
+//! This is useful for periodic log lines, such as "in the last 10s: 1234 requests, 5 errors".
+//!
+//! ```
+//! use measured::{Counter, MetricGroup, delta::DeltaEncoder, text::BufferedTextEncoder};
+//!
+//! #[derive(MetricGroup)]
+//! struct MyMetrics {
+//!     /// total requests served
+//!     requests_total: Counter,
+//!     /// total errors
+//!     errors_total: Counter,
+//! }
+//!
+//! let metrics = MyMetrics {
+//!     requests_total: Counter::new(),
+//!     errors_total: Counter::new(),
+//! };
+//!
+//! let mut enc = DeltaEncoder::new(BufferedTextEncoder::new());
+//!
+//! metrics.requests_total.inc_by(10);
+//! metrics.collect_group_into(&mut enc).unwrap();
+//! enc.end_collection();
+//! assert_eq!(
+//!     enc.inner.finish(),
+//!     "# HELP requests_total total requests served\n# TYPE requests_total counter\nrequests_total 10\n"
+//! );
+//!
+//! metrics.requests_total.inc_by(5);
+//! metrics.collect_group_into(&mut enc).unwrap();
+//! enc.end_collection();
+//! assert_eq!(
+//!     enc.inner.finish(),
+//!     "# HELP requests_total total requests served\n# TYPE requests_total counter\nrequests_total 5\n"
+//! );
+//! ```
+
+use std::{collections::HashMap, sync::atomic::Ordering};
+
+use bytes::Bytes;
+
+use crate::{
+    label::{LabelGroup, LabelGroupVisitor, LabelName, LabelValue, RenderValue},
+    metric::{
+        MetricEncoding,
+        counter::CounterState,
+        gauge::{FloatGaugeState, GaugeState},
+        group::{DynMetricFamily, DynamicEncoding, DynamicFamilies, EncoderList, Encoding},
+        histogram::HistogramState,
+        name::MetricNameEncoder,
+    },
+    negotiate::BufferedEncoding,
+};
+
+/// An [`Encoding`] adapter which only writes the changes since the previous collection.
+///
+/// Call [`DeltaEncoder::end_collection`] after each collection, so that the next collection reports the
+/// changes since this one, and series which no longer exist are forgotten.
+/// [`BufferedEncoding::finish`] does this automatically.
+pub struct DeltaEncoder<E> {
+    /// The inner encoder for this delta encoder.
+    pub inner: E,
+    series: HashMap<String, Series>,
+    /// The family that has not yet been written to the inner encoder
+    header: Option<Header>,
+    key: String,
+}
+
+struct Header {
+    name: String,
+    help: Option<String>,
+}
+
+struct Series {
+    /// The value at the previous collection, or `None` if the series is new
+    previous: Option<Value>,
+    /// The value in the current collection, applied by `end_collection`
+    current: Option<Value>,
+}
+
+/// The value of a series at a collection
+enum Value {
+    Counter(u64),
+    Gauge(i64),
+    FloatGauge(u64),
+    /// The bucket counts, including the `+Inf` bucket, and the sum
+    Histogram(Box<[u64]>, f64),
+}
+
+impl<E: Encoding> DeltaEncoder<E> {
+    /// Create a new delta encoder, with no previous values.
+    pub fn new(inner: E) -> Self {
+        Self {
+            inner,
+            series: HashMap::new(),
+            header: None,
+            key: String::new(),
+        }
+    }
+
+    /// Remember the values of the current collection, to report the changes since then in the next collection.
+    ///
+    /// Forgets any series that were not collected since the last call to `end_collection`.
+    pub fn end_collection(&mut self) {
+        self.header = None;
+        self.series.retain(|_, series| match series.current.take() {
+            Some(value) => {
+                series.previous = Some(value);
+                true
+            }
+            None => false,
+        });
+    }
+
+    fn start_family(&mut self, name: &impl MetricNameEncoder, help: Option<&str>) {
+        let name = encode_name(name);
+        match &mut self.header {
+            Some(header) if header.name == name => {
+                if let Some(help) = help {
+                    header.help = Some(help.to_owned());
+                }
+            }
+            header => {
+                *header = Some(Header {
+                    name,
+                    help: help.map(str::to_owned),
+                });
+            }
+        }
+    }
+
+    /// Write the help and type of the current family, if it has not already been written.
+    fn write_header<M: MetricEncoding<E>>(
+        &mut self,
+        name: &impl MetricNameEncoder,
+    ) -> Result<(), E::Err> {
+        if let Some(header) = self.header.take() {
+            if let Some(help) = &header.help {
+                self.inner.write_help(name, help)?;
+            }
+            M::write_type(name, &mut self.inner)?;
+        }
+        Ok(())
+    }
+
+    /// Stage the new value of the series, returning the value at the previous collection
+    fn stage(
+        &mut self,
+        name: &impl MetricNameEncoder,
+        labels: &impl LabelGroup,
+        value: Value,
+    ) -> Option<&Value> {
+        struct Key<'a>(&'a mut String);
+        impl LabelGroupVisitor for Key<'_> {
+            type Output = ();
+            fn write_value(&mut self, name: &LabelName, x: &impl LabelValue) {
+                self.0.push_str(name.as_str());
+                self.0.push('\0');
+                self.0.push_str(&x.visit(RenderValue));
+                self.0.push('\0');
+            }
+        }
+
+        self.key.clear();
+        self.key.push_str(&encode_name(name));
+        self.key.push('\0');
+        labels.visit_values(&mut Key(&mut self.key));
+
+        if !self.series.contains_key(&self.key) {
+            let series = Series {
+                previous: None,
+                current: None,
+            };
+            self.series.insert(self.key.clone(), series);
+        }
+        let series = self.series.get_mut(&self.key).expect("series was inserted");
+        series.current = Some(value);
+        series.previous.as_ref()
+    }
+}
+
+fn encode_name(name: &impl MetricNameEncoder) -> String {
+    let mut buf = Vec::new();
+    // writing into a vec cannot fail
+    let _ = name.encode_utf8(&mut buf);
+    String::from_utf8_lossy(&buf).into_owned()
+}
+
+impl<E: Encoding> Encoding for DeltaEncoder<E> {
+    type Err = E::Err;
+
+    const MIME_TYPE: &'static str = E::MIME_TYPE;
+
+    fn write_help(&mut self, name: impl MetricNameEncoder, help: &str) -> Result<(), Self::Err> {
+        self.start_family(&name, Some(help));
+        Ok(())
+    }
+}
+
+impl<L: EncoderList, E: Encoding> DynamicEncoding<L> for DeltaEncoder<E>
+where
+    L::Family: DynMetricFamily<Self>,
+{
+    fn collect_dynamic(&mut self, families: DynamicFamilies<'_, L>) -> Result<(), Self::Err> {
+        families.collect_into(self)
+    }
+}
+
+impl<E: BufferedEncoding> BufferedEncoding for DeltaEncoder<E> {
+    fn finish(&mut self) -> Result<Bytes, Self::Err> {
+        self.end_collection();
+        self.inner.finish()
+    }
+
+    /// The values of the failed collection are discarded,
+    /// so the next collection reports all the changes since the previous successful collection.
+    fn reset(&mut self) {
+        self.header = None;
+        self.series.retain(|_, series| {
+            series.current = None;
+            series.previous.is_some()
+        });
+        self.inner.reset();
+    }
+}
+
+impl<E: Encoding> MetricEncoding<DeltaEncoder<E>> for CounterState
+where
+    CounterState: MetricEncoding<E>,
+{
+    fn write_type(name: impl MetricNameEncoder, enc: &mut DeltaEncoder<E>) -> Result<(), E::Err> {
+        enc.start_family(&name, None);
+        Ok(())
+    }
+    fn collect_into(
+        &self,
+        metadata: &(),
+        labels: impl LabelGroup,
+        name: impl MetricNameEncoder,
+        enc: &mut DeltaEncoder<E>,
+    ) -> Result<(), E::Err> {
+        let current = self.count.load(Ordering::Relaxed);
+        let delta = match enc.stage(&name, &labels, Value::Counter(current)) {
+            Some(&Value::Counter(prev)) if prev <= current => current - prev,
+            // the counter is new, or was reset.
+            _ => current,
+        };
+        if delta == 0 {
+            return Ok(());
+        }
+
+        enc.write_header::<Self>(&name)?;
+        CounterState::new(delta).collect_into(metadata, labels, name, &mut enc.inner)
+    }
+}
+
+impl<E: Encoding> MetricEncoding<DeltaEncoder<E>> for GaugeState
+where
+    GaugeState: MetricEncoding<E>,
+{
+    fn write_type(name: impl MetricNameEncoder, enc: &mut DeltaEncoder<E>) -> Result<(), E::Err> {
+        enc.start_family(&name, None);
+        Ok(())
+    }
+    fn collect_into(
+        &self,
+        metadata: &(),
+        labels: impl LabelGroup,
+        name: impl MetricNameEncoder,
+        enc: &mut DeltaEncoder<E>,
+    ) -> Result<(), E::Err> {
+        let current = self.count.load(Ordering::Relaxed);
+        if let Some(&Value::Gauge(prev)) = enc.stage(&name, &labels, Value::Gauge(current))
+            && prev == current
+        {
+            return Ok(());
+        }
+
+        enc.write_header::<Self>(&name)?;
+        self.collect_into(metadata, labels, name, &mut enc.inner)
+    }
+}
+
+impl<E: Encoding> MetricEncoding<DeltaEncoder<E>> for FloatGaugeState
+where
+    FloatGaugeState: MetricEncoding<E>,
+{
+    fn write_type(name: impl MetricNameEncoder, enc: &mut DeltaEncoder<E>) -> Result<(), E::Err> {
+        enc.start_family(&name, None);
+        Ok(())
+    }
+    fn collect_into(
+        &self,
+        metadata: &(),
+        labels: impl LabelGroup,
+        name: impl MetricNameEncoder,
+        enc: &mut DeltaEncoder<E>,
+    ) -> Result<(), E::Err> {
+        let current = self.count.get().to_bits();
+        if let Some(&Value::FloatGauge(prev)) =
+            enc.stage(&name, &labels, Value::FloatGauge(current))
+            && prev == current
+        {
+            return Ok(());
+        }
+
+        enc.write_header::<Self>(&name)?;
+        self.collect_into(metadata, labels, name, &mut enc.inner)
+    }
+}
+
+impl<E: Encoding, const N: usize> MetricEncoding<DeltaEncoder<E>> for HistogramState<N>
+where
+    HistogramState<N>: MetricEncoding<E>,
+{
+    fn write_type(name: impl MetricNameEncoder, enc: &mut DeltaEncoder<E>) -> Result<(), E::Err> {
+        enc.start_family(&name, None);
+        Ok(())
+    }
+    fn collect_into(
+        &self,
+        metadata: &Self::Metadata,
+        labels: impl LabelGroup,
+        name: impl MetricNameEncoder,
+        enc: &mut DeltaEncoder<E>,
+    ) -> Result<(), E::Err> {
+        let (buckets, inf, sum) = self.inner.write().sample();
+        let current: Box<[u64]> = buckets.iter().copied().chain([inf]).collect();
+
+        let mut delta = HistogramState::<N>::default();
+        let d = delta.inner.get_mut();
+        match enc.stage(&name, &labels, Value::Histogram(current.clone(), sum)) {
+            Some(&Value::Histogram(ref prev, prev_sum))
+                if prev.len() == current.len()
+                    && prev.iter().zip(&*current).all(|(p, c)| p <= c) =>
+            {
+                for i in 0..N {
+                    *d.buckets[i].get_mut() = current[i] - prev[i];
+                }
+                *d.inf.get_mut() = current[N] - prev[N];
+                d.sum.set_mut(sum - prev_sum);
+            }
+            // the histogram is new, or was reset.
+            _ => {
+                for i in 0..N {
+                    *d.buckets[i].get_mut() = current[i];
+                }
+                *d.inf.get_mut() = current[N];
+                d.sum.set_mut(sum);
+            }
+        }
+
+        let count = d.buckets.iter_mut().map(|b| *b.get_mut()).sum::<u64>() + *d.inf.get_mut();
+        if count == 0 {
+            return Ok(());
+        }
+
+        enc.write_header::<Self>(&name)?;
+        delta.collect_into(metadata, labels, name, &mut enc.inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use measured_derive::{FixedCardinalityLabel, LabelGroup, MetricGroup};
+
+    use super::DeltaEncoder;
+    use crate::{
+        CounterVec, Gauge, Histogram,
+        metric::{group::MetricGroup, histogram::Thresholds},
+        negotiate::BufferedEncoding,
+        text::BufferedTextEncoder,
+    };
+
+    #[derive(FixedCardinalityLabel, Clone, Copy)]
+    #[label(crate = crate)]
+    enum Method {
+        Get,
+        Post,
+    }
+
+    #[derive(LabelGroup, Clone, Copy)]
+    #[label(crate = crate, set = RequestLabelSet)]
+    struct RequestLabels {
+        method: Method,
+    }
+
+    #[derive(MetricGroup)]
+    #[metric(crate = crate)]
+    struct Metrics {
+        /// total requests
+        requests_total: CounterVec<RequestLabelSet>,
+        /// request latency
+        request_duration_seconds: Histogram<2>,
+        /// open connections
+        connections: Gauge,
+    }
+
+    #[test]
+    fn deltas() {
+        let metrics = Metrics {
+            requests_total: CounterVec::with_label_set(RequestLabelSet::new()),
+            request_duration_seconds: Histogram::with_metadata(Thresholds::with_buckets([
+                0.5, 1.0,
+            ])),
+            connections: Gauge::new(),
+        };
+        let get = RequestLabels {
+            method: Method::Get,
+        };
+        let post = RequestLabels {
+            method: Method::Post,
+        };
+
+        let mut enc = DeltaEncoder::new(BufferedTextEncoder::new());
+        let collect = |enc: &mut DeltaEncoder<BufferedTextEncoder>| {
+            metrics.collect_group_into(enc).unwrap();
+            String::from_utf8(enc.finish().unwrap().to_vec()).unwrap()
+        };
+
+        metrics.requests_total.inc_by(get, 3);
+        metrics.request_duration_seconds.get_metric().observe(0.25);
+        metrics.connections.get_metric().set(2);
+        assert_eq!(
+            collect(&mut enc),
+            r#"# HELP requests_total total requests
+# TYPE requests_total counter
+requests_total{method="get"} 3
+
+# HELP request_duration_seconds request latency
+# TYPE request_duration_seconds histogram
+request_duration_seconds_bucket{le="0.5"} 1
+request_duration_seconds_bucket{le="1.0"} 1
+request_duration_seconds_bucket{le="+Inf"} 1
+request_duration_seconds_sum 0.25
+request_duration_seconds_count 1
+
+# HELP connections open connections
+# TYPE connections gauge
+connections 2
+"#
+        );
+
+        // nothing changed
+        assert_eq!(collect(&mut enc), "");
+
+        metrics.requests_total.inc_by(get, 2);
+        metrics.requests_total.inc(post);
+        metrics.request_duration_seconds.get_metric().observe(2.0);
+        assert_eq!(
+            collect(&mut enc),
+            r#"# HELP requests_total total requests
+# TYPE requests_total counter
+requests_total{method="get"} 2
+requests_total{method="post"} 1
+
+# HELP request_duration_seconds request latency
+# TYPE request_duration_seconds histogram
+request_duration_seconds_bucket{le="0.5"} 0
+request_duration_seconds_bucket{le="1.0"} 0
+request_duration_seconds_bucket{le="+Inf"} 1
+request_duration_seconds_sum 2.0
+request_duration_seconds_count 1
+"#
+        );
+
+        // a failed collection does not lose the changes
+        metrics.requests_total.inc_by(get, 4);
+        metrics.collect_group_into(&mut enc).unwrap();
+        enc.reset();
+        metrics.requests_total.inc(get);
+        assert_eq!(
+            collect(&mut enc),
+            r#"# HELP requests_total total requests
+# TYPE requests_total counter
+requests_total{method="get"} 5
+"#
+        );
+    }
+}
@@ -0,0 +1,245 @@
+//! Filtering of metric families by name
+//!
+//! A [`FilterEncoder`] wraps any other [`Encoding`] and only forwards the metric families
+//! whose full name matches a [`NameFilter`]. Since the filter sees the names after any
+//! [`WithNamespace`](crate::metric::name::WithNamespace) prefixes have been applied, nested
+//! `#[metric(namespace = ...)]` groups are matched by their namespaced names.
+//!
+//! ```
+//! use measured::{
+//!     Counter, MetricGroup,
+//!     filter::{FilterEncoder, NameFilter},
+//!     text::BufferedTextEncoder,
+//! };
+//!
+//! #[derive(MetricGroup)]
+//! struct MyMetrics {
+//!     #[metric(namespace = "http")]
+//!     http: HttpMetrics,
+//!     /// total jobs processed
+//!     jobs: Counter,
+//! }
+//!
+//! #[derive(MetricGroup)]
+//! struct HttpMetrics {
+//!     /// total requests served
+//!     requests: Counter,
+//! }
+//!
+//! let metrics = MyMetrics {
+//!     http: HttpMetrics { requests: Counter::new() },
+//!     jobs: Counter::new(),
+//! };
+//!
+//! let mut enc = FilterEncoder::new(
+//!     BufferedTextEncoder::new(),
+//!     NameFilter::new().with_name("http_requests"),
+//! );
+//! metrics.collect_group_into(&mut enc).unwrap();
+//!
+//! assert_eq!(
+//!     enc.inner.finish(),
+//!     "# HELP http_requests total requests served\n# TYPE http_requests counter\nhttp_requests 0\n"
+//! );
+//! ```
+
+use std::collections::HashSet;
+
+use bytes::Bytes;
+
+use crate::{
+    label::{LabelGroup, LabelGroupSet},
+    metric::{
+        MetricEncoding,
+        group::{DynamicEncoding, DynamicFamilies, EncoderList, Encoding},
+        name::MetricNameEncoder,
+    },
+    negotiate::BufferedEncoding,
+};
+
+/// A set of rules to match metric family names against.
+///
+/// A name matches the filter if it matches any of the rules.
+/// An empty filter matches no names.
+#[derive(Clone, Debug, Default)]
+pub struct NameFilter {
+    names: HashSet<String>,
+    prefixes: Vec<String>,
+    #[cfg(feature = "regex")]
+    regexes: Vec<regex::Regex>,
+}
+
+impl NameFilter {
+    /// Create a new filter which matches no names
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Match metric families with exactly this name
+    #[must_use]
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.names.insert(name.into());
+        self
+    }
+
+    /// Match metric families with names starting with this prefix
+    #[must_use]
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefixes.push(prefix.into());
+        self
+    }
+
+    /// Match metric families with names that match this regex.
+    ///
+    /// The regex is not anchored, use `^` and `$` to match the entire name.
+    #[cfg(feature = "regex")]
+    #[must_use]
+    pub fn with_regex(mut self, regex: regex::Regex) -> Self {
+        self.regexes.push(regex);
+        self
+    }
+
+    /// Test whether the metric family name matches this filter
+    pub fn matches(&self, name: &str) -> bool {
+        if self.names.contains(name) || self.prefixes.iter().any(|p| name.starts_with(&**p)) {
+            return true;
+        }
+
+        #[cfg(feature = "regex")]
+        if self.regexes.iter().any(|r| r.is_match(name)) {
+            return true;
+        }
+
+        false
+    }
+}
+
+/// An [`Encoding`] adapter which skips all metric families that do not match the [`NameFilter`].
+///
+/// This should ideally be cached and re-used between collections to reduce re-allocating
+pub struct FilterEncoder<E> {
+    /// The inner encoder for this filter encoder.
+    pub inner: E,
+    filter: NameFilter,
+    name_buf: Vec<u8>,
+}
+
+impl<E: Encoding> FilterEncoder<E> {
+    /// Create a new filter encoder.
+    pub fn new(inner: E, filter: NameFilter) -> Self {
+        Self {
+            inner,
+            filter,
+            name_buf: Vec::new(),
+        }
+    }
+
+    /// Get the filter
+    pub fn filter(&self) -> &NameFilter {
+        &self.filter
+    }
+
+    /// Replace the filter, eg for a new request.
+    pub fn set_filter(&mut self, filter: NameFilter) {
+        self.filter = filter;
+    }
+
+    fn matches(&mut self, name: &impl MetricNameEncoder) -> bool {
+        self.name_buf.clear();
+        if name.encode_utf8(&mut self.name_buf).is_err() {
+            return false;
+        }
+        std::str::from_utf8(&self.name_buf).is_ok_and(|name| self.filter.matches(name))
+    }
+}
+
+impl<E: Encoding> Encoding for FilterEncoder<E> {
+    type Err = E::Err;
+
+    const MIME_TYPE: &'static str = E::MIME_TYPE;
+
+    fn write_help(&mut self, name: impl MetricNameEncoder, help: &str) -> Result<(), Self::Err> {
+        if self.matches(&name) {
+            self.inner.write_help(name, help)?;
+        }
+        Ok(())
+    }
+}
+
+impl<E: BufferedEncoding> BufferedEncoding for FilterEncoder<E> {
+    fn finish(&mut self) -> Result<Bytes, Self::Err> {
+        self.inner.finish()
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset();
+    }
+}
+
+impl<L: EncoderList, E: DynamicEncoding<L>> DynamicEncoding<L> for FilterEncoder<E> {
+    fn collect_dynamic(&mut self, families: DynamicFamilies<'_, L>) -> Result<(), Self::Err> {
+        self.inner.collect_dynamic(families.filtered(&self.filter))
+    }
+}
+
+impl<M: MetricEncoding<E>, E: Encoding> MetricEncoding<FilterEncoder<E>> for M {
+    fn write_type(name: impl MetricNameEncoder, enc: &mut FilterEncoder<E>) -> Result<(), E::Err> {
+        if enc.matches(&name) {
+            M::write_type(name, &mut enc.inner)?;
+        }
+        Ok(())
+    }
+
+    fn write_metadata(
+        name: impl MetricNameEncoder,
+        metadata: &M::Metadata,
+        labels: &impl LabelGroupSet,
+        enc: &mut FilterEncoder<E>,
+    ) -> Result<(), E::Err> {
+        if enc.matches(&name) {
+            M::write_metadata(name, metadata, labels, &mut enc.inner)?;
+        }
+        Ok(())
+    }
+
+    fn collect_into(
+        &self,
+        metadata: &M::Metadata,
+        labels: impl LabelGroup,
+        name: impl MetricNameEncoder,
+        enc: &mut FilterEncoder<E>,
+    ) -> Result<(), E::Err> {
+        if enc.matches(&name) {
+            self.collect_into(metadata, labels, name, &mut enc.inner)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NameFilter;
+
+    #[test]
+    fn matches() {
+        let filter = NameFilter::new()
+            .with_name("http_requests_total")
+            .with_prefix("tokio_");
+
+        assert!(filter.matches("http_requests_total"));
+        assert!(!filter.matches("http_requests"));
+        assert!(filter.matches("tokio_workers"));
+        assert!(!filter.matches("process_cpu_seconds_total"));
+
+        assert!(!NameFilter::new().matches("http_requests_total"));
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn regex() {
+        let filter = NameFilter::new().with_regex(regex::Regex::new("^http_.*_total$").unwrap());
+
+        assert!(filter.matches("http_requests_total"));
+        assert!(!filter.matches("http_requests"));
+    }
+}
@@ -0,0 +1,326 @@
+//! Generation of a starter Grafana dashboard from the [`Schema`] of a metric group
+//!
+//! Each metric family gets a panel:
+//! * counters are graphed with `rate()`
+//! * gauges are graphed directly
+//! * histograms are graphed with `histogram_quantile()` for the 50th, 90th and 99th percentiles
+//!
+//! Every label gets a template variable, which filters all panels that use that label.
+//!
+//! ```
+//! use measured::{Counter, MetricGroup, grafana::Dashboard, schema::schema};
+//!
+//! #[derive(MetricGroup)]
+//! struct MyMetrics {
+//!     /// total requests served
+//!     requests_total: Counter,
+//! }
+//!
+//! let metrics = MyMetrics { requests_total: Counter::new() };
+//!
+//! let json = Dashboard::new("My Service").render(&schema(&metrics));
+//! assert!(json.contains(r#""expr":"sum(rate(requests_total[$__rate_interval]))""#));
+//! ```
+
+use crate::{
+    schema::{FamilySchema, Schema},
+    text::{MetricType, write_str_literal},
+};
+
+/// The quantiles graphed for histograms
+const QUANTILES: [(&str, &str); 3] = [("0.5", "p50"), ("0.9", "p90"), ("0.99", "p99")];
+
+/// Builder for a Grafana dashboard.
+///
+/// The dashboard is rendered as JSON which can be imported into Grafana,
+/// and then customised as needed.
+#[derive(Clone, Debug)]
+pub struct Dashboard {
+    title: String,
+    uid: Option<String>,
+    datasource: String,
+}
+
+impl Dashboard {
+    /// Create a new dashboard with this title
+    pub fn new(title: impl Into<String>) -> Self {
+        Self {
+            title: title.into(),
+            uid: None,
+            datasource: "prometheus".to_owned(),
+        }
+    }
+
+    /// Set the uid of the dashboard, so that re-importing it replaces the existing dashboard
+    #[must_use]
+    pub fn with_uid(mut self, uid: impl Into<String>) -> Self {
+        self.uid = Some(uid.into());
+        self
+    }
+
+    /// Set the default prometheus datasource. Defaults to `prometheus`.
+    ///
+    /// The datasource can still be changed with the `datasource` template variable.
+    #[must_use]
+    pub fn with_datasource(mut self, datasource: impl Into<String>) -> Self {
+        self.datasource = datasource.into();
+        self
+    }
+
+    /// Render the dashboard JSON, with a panel for each metric family in the schema.
+    pub fn render(&self, schema: &Schema) -> String {
+        let mut out = String::new();
+        out.push_str("{\"title\":");
+        write_str_literal(&mut out, &self.title, true);
+        if let Some(uid) = &self.uid {
+            out.push_str(",\"uid\":");
+            write_str_literal(&mut out, uid, true);
+        }
+        out.push_str(concat!(
+            ",\"editable\":true",
+            ",\"schemaVersion\":39",
+            ",\"refresh\":\"1m\"",
+            ",\"time\":{\"from\":\"now-6h\",\"to\":\"now\"}",
+        ));
+
+        out.push_str(",\"templating\":{\"list\":[{\"name\":\"datasource\",\"type\":\"datasource\",\"query\":\"prometheus\",\"current\":{\"text\":");
+        write_str_literal(&mut out, &self.datasource, true);
+        out.push_str(",\"value\":");
+        write_str_literal(&mut out, &self.datasource, true);
+        out.push_str("}}");
+
+        // one variable for each label, taking the values from the first family which uses it.
+        let mut labels: Vec<&str> = Vec::new();
+        for family in schema.families() {
+            for label in family.labels() {
+                if labels.contains(&&**label) {
+                    continue;
+                }
+                labels.push(label);
+
+                let query = format!("label_values({}, {label})", series_name(family));
+                out.push_str(",{\"name\":");
+                write_str_literal(&mut out, label, true);
+                out.push_str(",\"type\":\"query\",\"datasource\":");
+                write_datasource(&mut out);
+                out.push_str(",\"definition\":");
+                write_str_literal(&mut out, &query, true);
+                out.push_str(",\"query\":{\"query\":");
+                write_str_literal(&mut out, &query, true);
+                out.push_str(",\"refId\":\"PrometheusVariableQueryEditor-VariableQuery\"}");
+                out.push_str(concat!(
+                    ",\"refresh\":2",
+                    ",\"includeAll\":true",
+                    ",\"multi\":true",
+                    ",\"allValue\":\".*\"",
+                    ",\"current\":{\"text\":\"All\",\"value\":\"$__all\"}}",
+                ));
+            }
+        }
+        out.push_str("]}");
+
+        out.push_str(",\"panels\":[");
+        for (i, family) in schema.families().iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            write_panel(&mut out, i, family);
+        }
+        out.push_str("]}");
+        out
+    }
+}
+
+fn write_datasource(out: &mut String) {
+    out.push_str("{\"type\":\"prometheus\",\"uid\":\"${datasource}\"}");
+}
+
+/// The name of a series in the family that has all the labels
+fn series_name(family: &FamilySchema) -> String {
+    match family.metric_type() {
+        MetricType::Histogram => format!("{}_bucket", family.name()),
+        _ => family.name().to_owned(),
+    }
+}
+
+fn write_panel(out: &mut String, i: usize, family: &FamilySchema) {
+    let labels = family.labels();
+    let selector = labels
+        .iter()
+        .map(|label| format!("{label}=~\"${label}\""))
+        .collect::<Vec<_>>()
+        .join(",");
+    let series = if selector.is_empty() {
+        series_name(family)
+    } else {
+        format!("{}{{{selector}}}", series_name(family))
+    };
+    let by = labels.join(", ");
+    let legend = labels
+        .iter()
+        .map(|label| format!("{{{{{label}}}}}"))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let targets: Vec<(String, String)> = match family.metric_type() {
+        MetricType::Counter if by.is_empty() => vec![(
+            format!("sum(rate({series}[$__rate_interval]))"),
+            family.name().to_owned(),
+        )],
+        MetricType::Counter => vec![(
+            format!("sum by ({by}) (rate({series}[$__rate_interval]))"),
+            legend,
+        )],
+        MetricType::Histogram => QUANTILES
+            .iter()
+            .map(|(q, p)| {
+                let by = if by.is_empty() {
+                    "le".to_owned()
+                } else {
+                    format!("le, {by}")
+                };
+                (
+                    format!(
+                        "histogram_quantile({q}, sum by ({by}) (rate({series}[$__rate_interval])))"
+                    ),
+                    format!("{p} {legend}").trim_end().to_owned(),
+                )
+            })
+            .collect(),
+        _ if by.is_empty() => vec![(series, family.name().to_owned())],
+        _ => vec![(series, legend)],
+    };
+
+    out.push_str("{\"id\":");
+    out.push_str(itoa::Buffer::new().format(i + 1));
+    out.push_str(",\"type\":\"timeseries\",\"title\":");
+    write_str_literal(out, family.name(), true);
+    if let Some(help) = family.help() {
+        out.push_str(",\"description\":");
+        write_str_literal(out, help, true);
+    }
+    out.push_str(",\"datasource\":");
+    write_datasource(out);
+    out.push_str(",\"gridPos\":{\"h\":8,\"w\":12,\"x\":");
+    out.push_str(if i.is_multiple_of(2) { "0" } else { "12" });
+    out.push_str(",\"y\":");
+    out.push_str(itoa::Buffer::new().format(i / 2 * 8));
+    out.push('}');
+
+    if let Some(unit) = unit(family.name()) {
+        out.push_str(",\"fieldConfig\":{\"defaults\":{\"unit\":\"");
+        out.push_str(unit);
+        out.push_str("\"},\"overrides\":[]}");
+    }
+
+    out.push_str(",\"targets\":[");
+    for (j, (expr, legend)) in targets.iter().enumerate() {
+        if j > 0 {
+            out.push(',');
+        }
+        out.push_str("{\"refId\":\"");
+        out.push(char::from(b'A' + j as u8));
+        out.push_str("\",\"datasource\":");
+        write_datasource(out);
+        out.push_str(",\"expr\":");
+        write_str_literal(out, expr, true);
+        out.push_str(",\"legendFormat\":");
+        write_str_literal(out, legend, true);
+        out.push('}');
+    }
+    out.push_str("]}");
+}
+
+/// Pick a grafana unit from the base unit suffix of the metric name
+fn unit(name: &str) -> Option<&'static str> {
+    let name = name.strip_suffix("_total").unwrap_or(name);
+    if name.ends_with("_seconds") {
+        Some("s")
+    } else if name.ends_with("_bytes") {
+        Some("bytes")
+    } else if name.ends_with("_ratio") {
+        Some("percentunit")
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use measured_derive::{FixedCardinalityLabel, LabelGroup, MetricGroup};
+
+    use super::Dashboard;
+    use crate::{CounterVec, Gauge, HistogramVec, metric::histogram::Thresholds, schema::schema};
+
+    #[derive(FixedCardinalityLabel, Clone, Copy)]
+    #[label(crate = crate)]
+    enum Method {
+        Get,
+        Post,
+    }
+
+    #[derive(LabelGroup)]
+    #[label(crate = crate, set = RequestLabelSet)]
+    struct RequestLabels {
+        method: Method,
+    }
+
+    #[derive(MetricGroup)]
+    #[metric(crate = crate)]
+    struct Metrics {
+        /// total requests "served"
+        requests_total: CounterVec<RequestLabelSet>,
+        /// request latency
+        request_duration_seconds: HistogramVec<RequestLabelSet, 2>,
+        /// open connections
+        connections: Gauge,
+    }
+
+    #[test]
+    fn render() {
+        let metrics = Metrics {
+            requests_total: CounterVec::with_label_set(RequestLabelSet::new()),
+            request_duration_seconds: HistogramVec::with_label_set_and_metadata(
+                RequestLabelSet::new(),
+                Thresholds::with_buckets([0.5, 1.0]),
+            ),
+            connections: Gauge::new(),
+        };
+
+        let json = Dashboard::new("Test")
+            .with_uid("test")
+            .render(&schema(&metrics));
+        let dashboard: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(dashboard["uid"], "test");
+
+        let variables = dashboard["templating"]["list"].as_array().unwrap();
+        assert_eq!(variables.len(), 2);
+        assert_eq!(variables[1]["name"], "method");
+        assert_eq!(
+            variables[1]["query"]["query"],
+            "label_values(request_duration_seconds_bucket, method)"
+        );
+
+        let panels = dashboard["panels"].as_array().unwrap();
+        assert_eq!(panels.len(), 3);
+
+        assert_eq!(panels[0]["title"], "connections");
+        assert_eq!(panels[0]["targets"][0]["expr"], "connections");
+
+        assert_eq!(panels[1]["title"], "request_duration_seconds");
+        assert_eq!(panels[1]["fieldConfig"]["defaults"]["unit"], "s");
+        assert_eq!(
+            panels[1]["targets"][2]["expr"],
+            r#"histogram_quantile(0.99, sum by (le, method) (rate(request_duration_seconds_bucket{method=~"$method"}[$__rate_interval])))"#
+        );
+        assert_eq!(panels[1]["targets"][2]["legendFormat"], "p99 {{method}}");
+
+        assert_eq!(panels[2]["description"], r#"total requests "served""#);
+        assert_eq!(
+            panels[2]["targets"][0]["expr"],
+            r#"sum by (method) (rate(requests_total{method=~"$method"}[$__rate_interval]))"#
+        );
+        assert_eq!(panels[2]["gridPos"]["y"], 8);
+    }
+}
@@ -2,15 +2,33 @@
 
 mod impls;
 
+pub(crate) mod bounded;
+pub(crate) mod dynamic;
 pub(crate) mod group;
 pub(crate) mod name;
+pub(crate) mod number;
+#[cfg(feature = "sha2")]
+pub(crate) mod redact;
+pub(crate) mod top_k;
 pub(crate) mod value;
 
-pub use group::{ComposedGroup, LabelGroup, LabelGroupSet, LabelGroupVisitor, NoLabels};
-pub use name::LabelName;
+pub use bounded::BoundedLabelSet;
+pub use dynamic::{DynamicLabelGroup, DynamicLabelGroupSet};
+#[doc(hidden)]
+pub use group::__labels_for;
+pub use group::{
+    ComposedGroup, FixedCardinalityGroupSet, LabelGroup, LabelGroupSet, LabelGroupVisitor, NoLabels,
+};
+pub use name::{InvalidLabelName, LabelName, LabelNameBuf};
+pub use number::{BoundedInt, Bucketed};
+#[cfg(feature = "sha2")]
+pub use redact::{Redacted, RedactedBuckets, RedactedLabelSet, RedactionKey};
+pub use top_k::TopKLabelSet;
+#[doc(hidden)]
+pub use value::__PrefixVisitor;
 pub use value::{
     DynamicLabelSet, FixedCardinalityLabel, FixedCardinalitySet, LabelSet, LabelTestVisitor,
-    LabelValue, LabelVisitor, StaticLabelSet,
+    LabelValue, LabelVisitor, ParseLabelValueError, RenderValue, StaticLabelSet,
 };
 
 #[cfg(all(test, feature = "lasso"))]
@@ -19,6 +37,7 @@ mod tests {
     use lasso::{Rodeo, RodeoReader, ThreadedRodeo};
 
     use super::LabelGroupSet;
+    use crate::{CounterVec, metric::MetricFamilyEncoding, text::BufferedTextEncoder};
 
     #[derive(Clone, Copy, PartialEq, Debug, measured_derive::LabelGroup)]
     #[label(crate = crate, set = ErrorsSet)]
@@ -97,4 +116,115 @@ mod tests {
             }
         }
     }
+
+    #[derive(Clone, Copy, PartialEq, Debug, measured_derive::LabelGroup)]
+    #[label(crate = crate, set = RequestSet)]
+    struct Request<'a> {
+        kind: Option<ErrorKind>,
+        #[label(fixed_with = RodeoReader, omit_none)]
+        route: Option<&'a str>,
+        #[label(dynamic_with = ThreadedRodeo, default)]
+        tenant: Option<&'a str>,
+    }
+
+    #[test]
+    fn optional_labels() {
+        let routes = Rodeo::from_iter(["/home", "/about"]).into_reader();
+        let set = RequestSet::new(routes);
+
+        let mut indices = std::collections::HashSet::new();
+        for kind in [None, Some(ErrorKind::User), Some(ErrorKind::Network)] {
+            for route in [None, Some("/home"), Some("/about")] {
+                for tenant in [None, Some("acme"), Some("globex")] {
+                    let request = Request {
+                        kind,
+                        route,
+                        tenant,
+                    };
+                    let index = set.encode(request).unwrap();
+                    assert!(indices.insert(index));
+                    assert_eq!(set.decode(&index), request);
+                }
+            }
+        }
+        assert_eq!(indices.iter().map(|i| i.0).max(), Some(4 * 3 - 1));
+
+        let counters = CounterVec::with_label_set(set);
+        counters.inc(Request {
+            kind: None,
+            route: None,
+            tenant: Some("acme"),
+        });
+        counters.inc(Request {
+            kind: Some(ErrorKind::User),
+            route: Some("/home"),
+            tenant: None,
+        });
+
+        let mut enc = BufferedTextEncoder::new();
+        counters
+            .collect_family_into(
+                crate::metric::name::MetricName::from_str("requests"),
+                &mut enc,
+            )
+            .unwrap();
+        let output = enc.finish();
+        let mut lines: Vec<&str> = std::str::from_utf8(&output).unwrap().lines().collect();
+        lines.sort_unstable();
+        assert_eq!(
+            lines,
+            [
+                "# TYPE requests counter",
+                r#"requests{kind="",tenant="acme"} 1"#,
+                r#"requests{kind="user",route="/home",tenant=""} 1"#,
+            ]
+        );
+    }
+
+    #[derive(Clone, Copy, PartialEq, Debug, measured_derive::LabelGroup)]
+    #[label(crate = crate, set = RetrySet)]
+    struct Retry<'a> {
+        #[label(flatten = ErrorsSet)]
+        error: Error<'a>,
+        attempt: crate::label::BoundedInt<1, 3>,
+    }
+
+    #[test]
+    fn flattened_labels() {
+        let routes = Rodeo::from_iter(["/home", "/about"]).into_reader();
+        let set = RetrySet::new(ErrorsSet::new(routes));
+        assert_eq!(set.cardinality(), Some(3 * 2 * 3));
+
+        let mut names = vec![];
+        set.visit_label_names(&mut |name| names.push(name.as_str().to_owned()));
+        assert_eq!(names, ["kind", "route", "attempt"]);
+
+        for i in 0..set.cardinality().unwrap() {
+            let retry = set.decode_dense(i);
+            assert_eq!(set.encode(retry), Some(i));
+        }
+
+        let counters = CounterVec::with_label_set(set);
+        counters.inc(Retry {
+            error: Error {
+                kind: ErrorKind::Network,
+                route: "/about",
+            },
+            attempt: crate::label::BoundedInt::new(2).unwrap(),
+        });
+
+        let mut enc = BufferedTextEncoder::new();
+        counters
+            .collect_family_into(
+                crate::metric::name::MetricName::from_str("retries"),
+                &mut enc,
+            )
+            .unwrap();
+        let output = enc.finish();
+        assert!(
+            std::str::from_utf8(&output)
+                .unwrap()
+                .contains(r#"retries{kind="network",route="/about",attempt="2"} 1"#)
+        );
+    }
 }
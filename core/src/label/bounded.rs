@@ -0,0 +1,289 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+use std::{hash::BuildHasher, hash::RandomState, sync::OnceLock};
+
+use hashbrown::HashTable;
+use parking_lot::RwLock;
+
+use super::{DynamicLabelSet, LabelSet};
+
+/// A [`DynamicLabelSet`] that interns at most `capacity` distinct values.
+///
+/// Once the set is full, new values are encoded as the configured "other" value,
+/// or fail to encode if there is none (see [`MetricVec::try_with_labels`](crate::MetricVec::try_with_labels)).
+/// This keeps the cardinality of labels like user agents or customer IDs bounded.
+///
+/// To make room for new values, the set can be [`reset`](Self::reset), or the values that were not used
+/// since the previous eviction can be removed with [`evict_unused`](Self::evict_unused).
+/// The metrics for removed values would be invalid, so this can only be done through
+/// [`MetricVec::reset_label_set`](crate::MetricVec::reset_label_set), which also removes those metrics.
+///
+/// ```
+/// use measured::{CounterVec, LabelGroup};
+/// use measured::label::BoundedLabelSet;
+/// use std::sync::atomic::Ordering;
+///
+/// #[derive(LabelGroup)]
+/// #[label(set = RequestSet)]
+/// struct Request<'a> {
+///     #[label(dynamic_with = BoundedLabelSet)]
+///     user_agent: &'a str,
+/// }
+///
+/// let mut requests = CounterVec::with_label_set(RequestSet::new(
+///     BoundedLabelSet::new(2).with_other("other"),
+/// ));
+///
+/// for user_agent in ["curl", "firefox", "chrome", "curl"] {
+///     requests.inc(Request { user_agent });
+/// }
+/// assert_eq!(requests.get_label_set().user_agent.len(), 2);
+///
+/// // chrome was counted as "other"
+/// let other = requests.with_labels(Request { user_agent: "other" });
+/// assert_eq!(requests.get_metric(other).count.load(Ordering::Relaxed), 1);
+///
+/// // forget about the user agents that haven't been seen recently
+/// requests.reset_label_set(|set| {
+///     set.user_agent.evict_unused();
+/// });
+/// ```
+pub struct BoundedLabelSet {
+    /// Values are only removed with `&mut self`, so they can be borrowed while interning
+    values: Box<[OnceLock<Box<str>>]>,
+    used: Box<[AtomicBool]>,
+    index: RwLock<Index>,
+    hasher: RandomState,
+    other: Option<Box<str>>,
+}
+
+struct Index {
+    table: HashTable<usize>,
+    free: Vec<usize>,
+    /// Slots that were removed and not yet reused. Their metrics are no longer valid.
+    evicted: Box<[bool]>,
+}
+
+impl BoundedLabelSet {
+    /// Create a new set that interns at most `capacity` values
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            values: (0..capacity).map(|_| OnceLock::new()).collect(),
+            used: (0..capacity).map(|_| AtomicBool::new(false)).collect(),
+            index: RwLock::new(Index {
+                table: HashTable::with_capacity(capacity),
+                free: (0..capacity).rev().collect(),
+                evicted: vec![false; capacity].into_boxed_slice(),
+            }),
+            hasher: RandomState::new(),
+            other: None,
+        }
+    }
+
+    /// Encode all values seen after the set is full as this value
+    #[must_use]
+    pub fn with_other(mut self, other: impl Into<String>) -> Self {
+        self.other = Some(other.into().into_boxed_str());
+        self
+    }
+
+    /// The maximum number of values this set interns
+    pub fn capacity(&self) -> usize {
+        self.values.len()
+    }
+
+    /// The number of values currently interned
+    pub fn len(&self) -> usize {
+        self.index.read().table.len()
+    }
+
+    /// Whether no values are currently interned
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Remove all the interned values
+    pub fn reset(&mut self) {
+        for (value, used) in self.values.iter_mut().zip(&mut self.used) {
+            value.take();
+            *used.get_mut() = false;
+        }
+        let index = self.index.get_mut();
+        for &i in &index.table {
+            index.evicted[i] = true;
+        }
+        index.table.clear();
+        index.free = (0..self.values.len()).rev().collect();
+    }
+
+    /// Remove the values that were not encoded since the previous eviction or reset.
+    /// Returns the number of values removed.
+    pub fn evict_unused(&mut self) -> usize {
+        let index = self.index.get_mut();
+        let mut evicted = 0;
+        for (i, (value, used)) in self.values.iter_mut().zip(&mut self.used).enumerate() {
+            if !core::mem::take(used.get_mut()) && value.take().is_some() {
+                index.free.push(i);
+                index.evicted[i] = true;
+                evicted += 1;
+            }
+        }
+
+        let values = &self.values;
+        index.table.retain(|&mut i| values[i].get().is_some());
+        evicted
+    }
+
+    fn find(&self, index: &Index, hash: u64, value: &str) -> Option<usize> {
+        index
+            .table
+            .find(hash, |&i| {
+                self.values[i].get().is_some_and(|v| **v == *value)
+            })
+            .copied()
+    }
+
+    fn mark_used(&self, i: usize) -> usize {
+        // avoid writing to the shared cache line if we can
+        if !self.used[i].load(Ordering::Relaxed) {
+            self.used[i].store(true, Ordering::Relaxed);
+        }
+        i
+    }
+}
+
+impl DynamicLabelSet for BoundedLabelSet {}
+
+impl LabelSet for BoundedLabelSet {
+    type Value<'a> = &'a str;
+
+    fn dynamic_cardinality(&self) -> Option<usize> {
+        Some(self.capacity() + usize::from(self.other.is_some()))
+    }
+
+    fn encode(&self, value: Self::Value<'_>) -> Option<usize> {
+        // the "other" value takes the slot after all the interned values
+        if self.other.as_deref() == Some(value) {
+            return Some(self.capacity());
+        }
+
+        let hash = self.hasher.hash_one(value);
+        if let Some(i) = self.find(&self.index.read(), hash, value) {
+            return Some(self.mark_used(i));
+        }
+
+        let mut index = self.index.write();
+        if let Some(i) = self.find(&index, hash, value) {
+            return Some(self.mark_used(i));
+        }
+
+        let Some(i) = index.free.pop() else {
+            return self.other.is_some().then_some(self.capacity());
+        };
+
+        let values = &self.values;
+        let hasher = &self.hasher;
+        assert!(values[i].set(value.into()).is_ok(), "slot should be free");
+        index.evicted[i] = false;
+        index.table.insert_unique(hash, i, |&i| {
+            hasher.hash_one(&**values[i].get().expect("interned values are set"))
+        });
+        Some(self.mark_used(i))
+    }
+
+    fn decode(&self, value: usize) -> Self::Value<'_> {
+        match self.values.get(value) {
+            Some(v) => v.get().expect("value should be interned"),
+            None => self.other.as_deref().expect("value should be interned"),
+        }
+    }
+
+    fn evicted(&self, value: usize) -> bool {
+        self.index
+            .read()
+            .evicted
+            .get(value)
+            .copied()
+            .unwrap_or(false)
+    }
+
+    fn parse(&self, value: &str) -> Option<usize> {
+        if self.other.as_deref() == Some(value) {
+            return Some(self.capacity());
+        }
+        self.find(&self.index.read(), self.hasher.hash_one(value), value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::label::LabelSet;
+
+    use super::BoundedLabelSet;
+
+    #[test]
+    fn bounded() {
+        let mut set = BoundedLabelSet::new(2);
+        let a = set.encode("a").unwrap();
+        let b = set.encode("b").unwrap();
+        assert_eq!(set.encode("a"), Some(a));
+        assert_eq!(set.encode("c"), None);
+        assert_eq!(set.decode(b), "b");
+
+        // `a` and `b` were both used
+        assert_eq!(set.evict_unused(), 0);
+        assert_eq!(set.encode("b"), Some(b));
+        assert_eq!(set.evict_unused(), 1);
+        assert_eq!(set.len(), 1);
+        assert_eq!(set.encode("b"), Some(b));
+
+        let c = set.encode("c").unwrap();
+        assert_eq!(c, a, "the slot of `a` is reused");
+        assert_eq!(set.decode(c), "c");
+
+        set.reset();
+        assert!(set.is_empty());
+
+        let mut set = set.with_other("other");
+        assert_eq!(set.dynamic_cardinality(), Some(3));
+        set.encode("a").unwrap();
+        set.encode("b").unwrap();
+        let other = set.encode("c").unwrap();
+        assert_eq!(set.decode(other), "other");
+        assert_eq!(set.encode("d"), Some(other));
+        set.reset();
+        assert_eq!(set.decode(set.encode("d").unwrap()), "d");
+    }
+
+    #[test]
+    fn other_is_not_interned() {
+        let set = BoundedLabelSet::new(2).with_other("other");
+        assert_eq!(set.encode("other"), Some(2));
+        assert!(set.is_empty());
+
+        set.encode("a").unwrap();
+        set.encode("b").unwrap();
+        assert_eq!(set.encode("other"), Some(2));
+        assert_eq!(set.encode("c"), Some(2));
+    }
+
+    #[test]
+    fn evicted() {
+        let mut set = BoundedLabelSet::new(2).with_other("other");
+        let a = set.encode("a").unwrap();
+        let b = set.encode("b").unwrap();
+        set.evict_unused();
+        set.encode("b").unwrap();
+        assert_eq!(set.evict_unused(), 1);
+        assert!(set.evicted(a));
+        assert!(!set.evicted(b));
+        assert!(!set.evicted(set.capacity()));
+
+        // the evicted slot is reused
+        assert_eq!(set.encode("c"), Some(a));
+        assert!(!set.evicted(a));
+
+        set.reset();
+        assert!(set.evicted(a));
+        assert!(set.evicted(b));
+    }
+}
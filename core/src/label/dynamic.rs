@@ -0,0 +1,372 @@
+use std::{borrow::Cow, cell::RefCell, iter};
+
+use super::{LabelGroup, LabelGroupSet, LabelGroupVisitor, LabelName, LabelNameBuf, LabelSet};
+
+/// Separates the values of a group when they are interned together
+const SEPARATOR: &str = "\0\x02";
+/// NUL bytes within a value are escaped, so they can't be confused with the separator
+const NUL: &str = "\0";
+const ESCAPED_NUL: &str = "\0\x01";
+
+thread_local! {
+    /// Reused to join the values of a group, to avoid allocating on every encode
+    static JOINED: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+/// A [`LabelGroup`] with label names that are only known at runtime.
+///
+/// Created with [`DynamicLabelGroupSet::group`].
+#[derive(Clone, Copy)]
+pub struct DynamicLabelGroup<'a> {
+    names: &'a [LabelNameBuf],
+    values: Values<'a>,
+}
+
+#[derive(Clone, Copy)]
+enum Values<'a> {
+    Slice(&'a [&'a str]),
+    /// Decoded from the set, where all the escaped values are interned together.
+    Joined(&'a str),
+}
+
+impl<'a> DynamicLabelGroup<'a> {
+    /// The label names of this group
+    pub fn names(&self) -> &'a [LabelNameBuf] {
+        self.names
+    }
+
+    /// The label values of this group, in the same order as the names.
+    ///
+    /// Decoded values are only copied if they contain a NUL byte.
+    /// If the set decoded a fallback value instead of joined values, such as the "other" value of a
+    /// [`BoundedLabelSet`](super::BoundedLabelSet), that value is used for every label.
+    pub fn values(&self) -> impl Iterator<Item = Cow<'a, str>> + use<'a> {
+        let (slice, joined, fallback) = match self.values {
+            Values::Slice(values) => (Some(values.iter().map(|&v| Cow::Borrowed(v))), None, None),
+            // joined values always have a separator between each label
+            Values::Joined(values) if values.matches(SEPARATOR).count() + 1 == self.names.len() => {
+                (None, Some(values.split(SEPARATOR).map(unescape)), None)
+            }
+            Values::Joined(values) => (
+                None,
+                None,
+                Some(iter::repeat_n(Cow::Borrowed(values), self.names.len())),
+            ),
+        };
+        slice
+            .into_iter()
+            .flatten()
+            .chain(joined.into_iter().flatten())
+            .chain(fallback.into_iter().flatten())
+    }
+
+    /// Write the escaped values, separated by [`SEPARATOR`]
+    fn join_into(&self, buf: &mut String) {
+        match self.values {
+            Values::Slice(values) => {
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        buf.push_str(SEPARATOR);
+                    }
+                    escape_into(value, buf);
+                }
+            }
+            Values::Joined(values) => buf.push_str(values),
+        }
+    }
+}
+
+fn escape_into(value: &str, buf: &mut String) {
+    let mut parts = value.split(NUL);
+    buf.push_str(parts.next().unwrap_or_default());
+    for part in parts {
+        buf.push_str(ESCAPED_NUL);
+        buf.push_str(part);
+    }
+}
+
+fn unescape(value: &str) -> Cow<'_, str> {
+    if value.contains(ESCAPED_NUL) {
+        Cow::Owned(value.replace(ESCAPED_NUL, NUL))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+impl LabelGroup for DynamicLabelGroup<'_> {
+    fn visit_values(&self, v: &mut impl LabelGroupVisitor) {
+        for (name, value) in self.names.iter().zip(self.values()) {
+            v.write_value(name, &&*value);
+        }
+    }
+}
+
+/// A [`LabelGroupSet`] for label names that are only known at runtime, such as from a config file.
+///
+/// All the values of a group are interned together in a dynamic [`LabelSet`], eg `lasso::ThreadedRodeo`.
+/// The values are joined with a separator that can't appear in the escaped values,
+/// so any string can be used as a value.
+/// Fallback values of the inner set, such as the "other" value of a [`BoundedLabelSet`](super::BoundedLabelSet),
+/// are rendered as the value of every label.
+///
+/// ```
+/// # #[cfg(feature = "lasso")] {
+/// use measured::CounterVec;
+/// use measured::label::{DynamicLabelGroupSet, LabelNameBuf};
+/// use measured::lasso::ThreadedRodeo;
+///
+/// let names = ["plugin", "region"].map(|name| LabelNameBuf::try_from(name).unwrap());
+/// let values: ThreadedRodeo = ThreadedRodeo::new();
+/// let set = DynamicLabelGroupSet::new(names, values);
+///
+/// let counters = CounterVec::with_label_set(set);
+/// let labels = ["auth", "eu-west-1"];
+/// counters.inc(counters.get_label_set().group(&labels));
+/// # }
+/// ```
+pub struct DynamicLabelGroupSet<S> {
+    names: Box<[LabelNameBuf]>,
+    values: S,
+}
+
+impl<S> DynamicLabelGroupSet<S> {
+    /// Create a new set with the given label names, interning the values into `values`
+    pub fn new(names: impl IntoIterator<Item = LabelNameBuf>, values: S) -> Self {
+        Self {
+            names: names.into_iter().collect(),
+            values,
+        }
+    }
+
+    /// The label names of this set
+    pub fn names(&self) -> &[LabelNameBuf] {
+        &self.names
+    }
+
+    /// Create a label group with these values, in the same order as the names.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of values does not match the number of names.
+    pub fn group<'a>(&'a self, values: &'a [&'a str]) -> DynamicLabelGroup<'a> {
+        assert_eq!(
+            values.len(),
+            self.names.len(),
+            "there should be a value for each label name"
+        );
+        DynamicLabelGroup {
+            names: &self.names,
+            values: Values::Slice(values),
+        }
+    }
+}
+
+impl<S> LabelGroupSet for DynamicLabelGroupSet<S>
+where
+    S: for<'a> LabelSet<Value<'a> = &'a str>,
+{
+    type Group<'a> = DynamicLabelGroup<'a>;
+
+    fn cardinality(&self) -> Option<usize> {
+        None
+    }
+
+    fn encode_dense(&self, _value: Self::Unique) -> Option<usize> {
+        None
+    }
+
+    fn decode_dense(&self, _value: usize) -> Self::Group<'_> {
+        unreachable!("does not have a dense encoding")
+    }
+
+    type Unique = usize;
+
+    fn encode(&self, value: Self::Group<'_>) -> Option<Self::Unique> {
+        if let Values::Joined(values) = value.values {
+            return self.values.encode(values);
+        }
+        JOINED.with_borrow_mut(|buf| {
+            buf.clear();
+            value.join_into(buf);
+            self.values.encode(buf)
+        })
+    }
+
+    fn decode(&self, value: &Self::Unique) -> Self::Group<'_> {
+        DynamicLabelGroup {
+            names: &self.names,
+            values: Values::Joined(self.values.decode(*value)),
+        }
+    }
+
+    fn folded_into(&self, value: &Self::Unique) -> Option<Self::Unique> {
+        self.values.folded_into(*value)
+    }
+
+    fn evicted(&self, value: &Self::Unique) -> bool {
+        self.values.evicted(*value)
+    }
+
+    fn visit_label_names(&self, v: &mut impl FnMut(&LabelName)) {
+        for name in &self.names {
+            v(name);
+        }
+    }
+
+    fn try_parse(&self, labels: &[(&str, &str)]) -> Option<Self::Group<'_>> {
+        if labels.len() != self.names.len() {
+            return None;
+        }
+        let index = JOINED.with_borrow_mut(|buf| {
+            buf.clear();
+            for (i, name) in self.names.iter().enumerate() {
+                let (_, value) = labels.iter().find(|(n, _)| *n == name.as_str())?;
+                if i > 0 {
+                    buf.push_str(SEPARATOR);
+                }
+                escape_into(value, buf);
+            }
+            self.values.parse(buf)
+        });
+        let index = match (index, labels.first()) {
+            (Some(index), _) => index,
+            // a fallback value is rendered for every label
+            (None, Some((_, value))) if labels.iter().all(|(_, v)| v == value) => {
+                self.values.parse(value)?
+            }
+            (None, _) => return None,
+        };
+        Some(self.decode(&index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::label::BoundedLabelSet;
+
+    use super::{DynamicLabelGroupSet, LabelGroupSet, LabelNameBuf};
+
+    fn names() -> [LabelNameBuf; 2] {
+        ["plugin", "region"].map(|name| LabelNameBuf::try_from(name).unwrap())
+    }
+
+    #[test]
+    fn escaped_values() {
+        let set = DynamicLabelGroupSet::new(names(), BoundedLabelSet::new(4));
+
+        // these would be ambiguous if the values were joined without escaping
+        let a = set.encode(set.group(&["auth\0", "\x02eu"])).unwrap();
+        let b = set.encode(set.group(&["auth", "\0\x02eu"])).unwrap();
+        let c = set.encode(set.group(&["auth\0\x01", "eu"])).unwrap();
+        assert_ne!(a, b);
+        assert_ne!(a, c);
+        assert_ne!(b, c);
+
+        assert_eq!(
+            set.decode(&a).values().collect::<Vec<_>>(),
+            ["auth\0", "\x02eu"]
+        );
+        assert_eq!(
+            set.decode(&b).values().collect::<Vec<_>>(),
+            ["auth", "\0\x02eu"]
+        );
+        assert_eq!(
+            set.decode(&c).values().collect::<Vec<_>>(),
+            ["auth\0\x01", "eu"]
+        );
+        assert_eq!(set.encode(set.decode(&c)), Some(c));
+
+        assert_eq!(
+            set.try_parse(&[("region", "\x02eu"), ("plugin", "auth\0")])
+                .map(|group| set.encode(group)),
+            Some(Some(a))
+        );
+        assert!(
+            set.try_parse(&[("region", "eu"), ("plugin", "auth")])
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn fallback_value() {
+        use crate::{CounterVec, metric::MetricFamilyEncoding, text::BufferedTextEncoder};
+
+        let set = DynamicLabelGroupSet::new(names(), BoundedLabelSet::new(1).with_other("other"));
+        let counters = CounterVec::with_label_set(set);
+        counters.inc(counters.get_label_set().group(&["auth", "eu-west-1"]));
+        counters.inc(counters.get_label_set().group(&["billing", "us-east-1"]));
+        counters.inc(counters.get_label_set().group(&["search", "eu-west-1"]));
+
+        let set = counters.get_label_set();
+        let other = set.try_parse(&[("plugin", "other"), ("region", "other")]);
+        let other = set.encode(other.unwrap()).unwrap();
+        assert_eq!(
+            set.decode(&other).values().collect::<Vec<_>>(),
+            ["other", "other"]
+        );
+
+        let mut enc = BufferedTextEncoder::new();
+        counters
+            .collect_family_into(
+                crate::metric::name::MetricName::from_str("plugin_calls"),
+                &mut enc,
+            )
+            .unwrap();
+        let output = enc.finish();
+        let mut lines: Vec<&str> = std::str::from_utf8(&output).unwrap().lines().collect();
+        lines.sort_unstable();
+        assert_eq!(
+            lines,
+            [
+                "# TYPE plugin_calls counter",
+                r#"plugin_calls{plugin="auth",region="eu-west-1"} 1"#,
+                r#"plugin_calls{plugin="other",region="other"} 2"#,
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic = "there should be a value for each label name"]
+    fn missing_value() {
+        let set = DynamicLabelGroupSet::new(names(), BoundedLabelSet::new(4));
+        set.group(&["auth"]);
+    }
+
+    #[cfg(feature = "lasso")]
+    #[test]
+    fn dynamic_label_group() {
+        use lasso::{Spur, ThreadedRodeo};
+
+        use crate::{CounterVec, metric::MetricFamilyEncoding, text::BufferedTextEncoder};
+
+        let set = DynamicLabelGroupSet::new(names(), ThreadedRodeo::<Spur>::new());
+
+        let index = set.encode(set.group(&["auth", "eu-west-1"])).unwrap();
+        let group = set.decode(&index);
+        assert_eq!(group.values().collect::<Vec<_>>(), ["auth", "eu-west-1"]);
+        assert_eq!(set.encode(group), Some(index));
+
+        let counters = CounterVec::with_label_set(set);
+        counters.inc(counters.get_label_set().group(&["auth", "eu-west-1"]));
+        counters.inc(counters.get_label_set().group(&["auth", ""]));
+
+        let mut enc = BufferedTextEncoder::new();
+        counters
+            .collect_family_into(
+                crate::metric::name::MetricName::from_str("plugin_calls"),
+                &mut enc,
+            )
+            .unwrap();
+        let output = enc.finish();
+        let mut lines: Vec<&str> = std::str::from_utf8(&output).unwrap().lines().collect();
+        lines.sort_unstable();
+        assert_eq!(
+            lines,
+            [
+                "# TYPE plugin_calls counter",
+                r#"plugin_calls{plugin="auth",region=""} 1"#,
+                r#"plugin_calls{plugin="auth",region="eu-west-1"} 1"#,
+            ]
+        );
+    }
+}
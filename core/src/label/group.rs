@@ -52,6 +52,123 @@ pub trait LabelGroupSet {
     fn encode(&self, value: Self::Group<'_>) -> Option<Self::Unique>;
     /// Decodes the compressed representation into the label values
     fn decode(&self, value: &Self::Unique) -> Self::Group<'_>;
+
+    /// Visit the label names of this set, in the same order as [`LabelGroup::visit_values`].
+    ///
+    /// The default implementation decodes the first label group of a fixed cardinality set,
+    /// and visits no names for sets without a fixed cardinality.
+    fn visit_label_names(&self, v: &mut impl FnMut(&super::LabelName)) {
+        struct Names<'a, F>(&'a mut F);
+        impl<F: FnMut(&super::LabelName)> LabelGroupVisitor for Names<'_, F> {
+            type Output = ();
+            fn write_value(&mut self, name: &super::LabelName, _x: &impl super::LabelValue) {
+                (self.0)(name);
+            }
+        }
+
+        if self.cardinality().is_some_and(|c| c > 0) {
+            self.decode_dense(0).visit_values(&mut Names(v));
+        }
+    }
+
+    /// If the set has folded this label group into another, returns the encoding of that label group.
+    ///
+    /// See [`MetricVec::rebalance_label_set`](crate::MetricVec::rebalance_label_set).
+    fn folded_into(&self, value: &Self::Unique) -> Option<Self::Unique> {
+        let _ = value;
+        None
+    }
+
+    /// Whether the set has evicted a value of this label group, so its metrics are no longer valid.
+    ///
+    /// See [`MetricVec::reset_label_set`](crate::MetricVec::reset_label_set).
+    fn evicted(&self, value: &Self::Unique) -> bool {
+        let _ = value;
+        false
+    }
+
+    /// Find the label group with exactly these label-pairs, in any order.
+    ///
+    /// Each value is parsed from its rendered form with [`LabelSet::parse`](super::LabelSet::parse),
+    /// so values that are not already in a dynamic set are not found.
+    /// The [`LabelGroup`](macro@crate::LabelGroup) derive implements this for the generated set.
+    /// The default implementation never finds a label group.
+    ///
+    /// ```
+    /// use measured::{FixedCardinalityLabel, LabelGroup, label::LabelGroupSet};
+    ///
+    /// #[derive(FixedCardinalityLabel, Clone, Copy, Debug, PartialEq)]
+    /// enum Method { Get, Post }
+    ///
+    /// #[derive(LabelGroup, Debug, PartialEq)]
+    /// #[label(set = RequestSet)]
+    /// struct Request {
+    ///     method: Method,
+    ///     ok: bool,
+    /// }
+    ///
+    /// let set = RequestSet::new();
+    /// assert_eq!(
+    ///     set.try_parse(&[("ok", "true"), ("method", "post")]),
+    ///     Some(Request { method: Method::Post, ok: true }),
+    /// );
+    /// assert_eq!(set.try_parse(&[("method", "post")]), None);
+    /// assert_eq!(set.try_parse(&[("method", "put"), ("ok", "true")]), None);
+    /// ```
+    fn try_parse(&self, labels: &[(&str, &str)]) -> Option<Self::Group<'_>> {
+        let _ = labels;
+        None
+    }
+}
+
+/// `FixedCardinalityGroupSet` is a [`LabelGroupSet`] with a known fixed number of label groups.
+///
+/// [`LabelGroupSet::cardinality`] must return a value, which must never change due to some interior mutation.
+/// The sets of nested groups in the [`LabelGroup`](macro@crate::LabelGroup) derive must implement this,
+/// so they can be densely encoded into the outer set.
+///
+/// ```compile_fail
+/// use measured::LabelGroup;
+/// use measured::label::BoundedLabelSet;
+///
+/// #[derive(LabelGroup)]
+/// #[label(set = UserSet)]
+/// struct User<'a> {
+///     #[label(dynamic_with = BoundedLabelSet)]
+///     name: &'a str,
+/// }
+///
+/// // `UserSet` does not have a fixed cardinality
+/// #[derive(LabelGroup)]
+/// #[label(set = RequestSet)]
+/// struct Request<'a> {
+///     #[label(flatten = UserSet)]
+///     user: User<'a>,
+///     ok: bool,
+/// }
+/// ```
+pub trait FixedCardinalityGroupSet: LabelGroupSet {
+    #[doc(hidden)]
+    fn __private_check_fixed() {}
+}
+
+/// The label-pairs with the label names of this set.
+///
+/// Used to split the label-pairs between nested sets in [`LabelGroupSet::try_parse`].
+#[doc(hidden)]
+pub fn __labels_for<'a>(
+    set: &(impl LabelGroupSet + ?Sized),
+    labels: &[(&'a str, &'a str)],
+) -> Vec<(&'a str, &'a str)> {
+    labels
+        .iter()
+        .copied()
+        .filter(|(name, _)| {
+            let mut found = false;
+            set.visit_label_names(&mut |n| found |= n.as_str() == *name);
+            found
+        })
+        .collect()
 }
 
 /// A [`LabelGroup`] with no label pairs
@@ -61,6 +178,40 @@ impl LabelGroup for NoLabels {
     fn visit_values(&self, _v: &mut impl LabelGroupVisitor) {}
 }
 
+impl LabelGroupSet for NoLabels {
+    type Group<'a> = NoLabels;
+
+    fn cardinality(&self) -> Option<usize> {
+        Some(1)
+    }
+
+    fn encode_dense(&self, _value: Self::Unique) -> Option<usize> {
+        Some(0)
+    }
+
+    fn decode_dense(&self, _value: usize) -> Self::Group<'_> {
+        NoLabels
+    }
+
+    type Unique = ();
+
+    fn encode(&self, _value: Self::Group<'_>) -> Option<Self::Unique> {
+        Some(())
+    }
+
+    fn decode(&self, _value: &Self::Unique) -> Self::Group<'_> {
+        NoLabels
+    }
+
+    fn visit_label_names(&self, _v: &mut impl FnMut(&super::LabelName)) {}
+
+    fn try_parse(&self, labels: &[(&str, &str)]) -> Option<Self::Group<'_>> {
+        labels.is_empty().then_some(NoLabels)
+    }
+}
+
+impl FixedCardinalityGroupSet for NoLabels {}
+
 /// `ComposedGroup` represents either a combine [`LabelGroup`] or a [`LabelGroupSet`]. See [`LabelGroup::compose_with`]
 #[derive(Hash, PartialEq, Eq, Clone, Copy, Debug)]
 pub struct ComposedGroup<A, B>(pub A, pub B);
@@ -115,6 +266,35 @@ impl<A: LabelGroupSet, B: LabelGroupSet> LabelGroupSet for ComposedGroup<A, B> {
     fn decode(&self, value: &Self::Unique) -> Self::Group<'_> {
         ComposedGroup(self.0.decode(&value.0), self.1.decode(&value.1))
     }
+
+    fn folded_into(&self, value: &Self::Unique) -> Option<Self::Unique> {
+        match (self.0.folded_into(&value.0), self.1.folded_into(&value.1)) {
+            (None, None) => None,
+            (a, b) => Some(ComposedGroup(a.unwrap_or(value.0), b.unwrap_or(value.1))),
+        }
+    }
+
+    fn evicted(&self, value: &Self::Unique) -> bool {
+        self.0.evicted(&value.0) || self.1.evicted(&value.1)
+    }
+
+    fn visit_label_names(&self, v: &mut impl FnMut(&super::LabelName)) {
+        self.0.visit_label_names(v);
+        self.1.visit_label_names(v);
+    }
+
+    fn try_parse(&self, labels: &[(&str, &str)]) -> Option<Self::Group<'_>> {
+        let (a, b) = (__labels_for(&self.0, labels), __labels_for(&self.1, labels));
+        if a.len() + b.len() != labels.len() {
+            return None;
+        }
+        Some(ComposedGroup(self.0.try_parse(&a)?, self.1.try_parse(&b)?))
+    }
+}
+
+impl<A: FixedCardinalityGroupSet, B: FixedCardinalityGroupSet> FixedCardinalityGroupSet
+    for ComposedGroup<A, B>
+{
 }
 
 impl<A: LabelGroup, B: LabelGroup> LabelGroup for ComposedGroup<A, B> {
@@ -154,8 +334,26 @@ impl<T: LabelGroupSet + ?Sized> LabelGroupSet for &'static T {
     fn decode(&self, value: &Self::Unique) -> Self::Group<'_> {
         T::decode(self, value)
     }
+
+    fn folded_into(&self, value: &Self::Unique) -> Option<Self::Unique> {
+        T::folded_into(self, value)
+    }
+
+    fn evicted(&self, value: &Self::Unique) -> bool {
+        T::evicted(self, value)
+    }
+
+    fn visit_label_names(&self, v: &mut impl FnMut(&super::LabelName)) {
+        T::visit_label_names(self, v);
+    }
+
+    fn try_parse(&self, labels: &[(&str, &str)]) -> Option<Self::Group<'_>> {
+        T::try_parse(self, labels)
+    }
 }
 
+impl<T: FixedCardinalityGroupSet + ?Sized> FixedCardinalityGroupSet for &'static T {}
+
 impl<T: LabelGroupSet + ?Sized> LabelGroupSet for Arc<T> {
     type Group<'a> = T::Group<'a>;
 
@@ -180,8 +378,105 @@ impl<T: LabelGroupSet + ?Sized> LabelGroupSet for Arc<T> {
     fn decode(&self, value: &Self::Unique) -> Self::Group<'_> {
         T::decode(self, value)
     }
+
+    fn folded_into(&self, value: &Self::Unique) -> Option<Self::Unique> {
+        T::folded_into(self, value)
+    }
+
+    fn evicted(&self, value: &Self::Unique) -> bool {
+        T::evicted(self, value)
+    }
+
+    fn visit_label_names(&self, v: &mut impl FnMut(&super::LabelName)) {
+        T::visit_label_names(self, v);
+    }
+
+    fn try_parse(&self, labels: &[(&str, &str)]) -> Option<Self::Group<'_>> {
+        T::try_parse(self, labels)
+    }
+}
+
+impl<T: FixedCardinalityGroupSet + ?Sized> FixedCardinalityGroupSet for Arc<T> {}
+
+/// Tuples of label groups are visited in order.
+/// Tuples of label sets encode each element and densely encode as a mixed-radix number,
+/// with the last element being the least significant, the same as [`ComposedGroup`].
+macro_rules! tuple_label_group {
+    ($($T:ident $i:tt),*) => {
+        impl<$($T: LabelGroup),*> LabelGroup for ($($T,)*) {
+            fn visit_values(&self, v: &mut impl LabelGroupVisitor) {
+                $(self.$i.visit_values(v);)*
+            }
+        }
+
+        impl<$($T: LabelGroupSet),*> LabelGroupSet for ($($T,)*) {
+            type Group<'a> = ($($T::Group<'a>,)*);
+
+            fn cardinality(&self) -> Option<usize> {
+                Some(1usize)
+                    $(.and_then(|x| x.checked_mul(self.$i.cardinality()?)))*
+            }
+
+            fn encode_dense(&self, values: Self::Unique) -> Option<usize> {
+                let mut index = 0usize;
+                $(index = index * self.$i.cardinality()? + self.$i.encode_dense(values.$i)?;)*
+                Some(index)
+            }
+
+            fn decode_dense(&self, value: usize) -> Self::Group<'_> {
+                let cardinalities = [$(self.$i.cardinality().unwrap()),*];
+                ($(
+                    self.$i.decode_dense(
+                        value / cardinalities[$i + 1..].iter().product::<usize>() % cardinalities[$i],
+                    ),
+                )*)
+            }
+
+            type Unique = ($($T::Unique,)*);
+
+            fn encode(&self, value: Self::Group<'_>) -> Option<Self::Unique> {
+                Some(($(self.$i.encode(value.$i)?,)*))
+            }
+
+            fn decode(&self, value: &Self::Unique) -> Self::Group<'_> {
+                ($(self.$i.decode(&value.$i),)*)
+            }
+
+            fn folded_into(&self, value: &Self::Unique) -> Option<Self::Unique> {
+                let folded = ($(self.$i.folded_into(&value.$i),)*);
+                if $(folded.$i.is_none())&&* {
+                    return None;
+                }
+                Some(($(folded.$i.unwrap_or(value.$i),)*))
+            }
+
+            fn evicted(&self, value: &Self::Unique) -> bool {
+                $(self.$i.evicted(&value.$i))||*
+            }
+
+            fn visit_label_names(&self, v: &mut impl FnMut(&super::LabelName)) {
+                $(self.$i.visit_label_names(v);)*
+            }
+
+            fn try_parse(&self, labels: &[(&str, &str)]) -> Option<Self::Group<'_>> {
+                let split = ($(__labels_for(&self.$i, labels),)*);
+                if 0 $(+ split.$i.len())* != labels.len() {
+                    return None;
+                }
+                Some(($(self.$i.try_parse(&split.$i)?,)*))
+            }
+        }
+
+        impl<$($T: FixedCardinalityGroupSet),*> FixedCardinalityGroupSet for ($($T,)*) {}
+    };
 }
 
+tuple_label_group!(A 0, B 1);
+tuple_label_group!(A 0, B 1, C 2);
+tuple_label_group!(A 0, B 1, C 2, D 3);
+tuple_label_group!(A 0, B 1, C 2, D 3, E 4);
+tuple_label_group!(A 0, B 1, C 2, D 3, E 4, F 5);
+
 #[cfg(test)]
 mod tests {
     use crate::{FixedCardinalityLabel, LabelGroup};
@@ -249,4 +544,145 @@ mod tests {
         assert_eq!(composed.decode_dense(4), post_internal);
         assert_eq!(composed.decode(&ComposedGroup(1, 1)), post_internal);
     }
+
+    #[derive(Clone, Copy, PartialEq, Debug, LabelGroup)]
+    #[label(crate = crate, set = AttemptSet)]
+    struct Attempt {
+        retried: bool,
+        #[label(rename = "attempt")]
+        number: crate::label::BoundedInt<1, 3>,
+    }
+
+    #[test]
+    fn tuple_label_set() {
+        let set = (OperationsSet::new(), ErrorsSet::new(), AttemptSet::new());
+        assert_eq!(set.cardinality(), Some(2 * 3 * 6));
+
+        let mut names = vec![];
+        set.visit_label_names(&mut |name| names.push(name.as_str().to_owned()));
+        assert_eq!(names, ["kind", "kind", "retried", "attempt"]);
+
+        for i in 0..set.cardinality().unwrap() {
+            let group = set.decode_dense(i);
+            let unique = set.encode(group).unwrap();
+            assert_eq!(set.encode_dense(unique), Some(i));
+            assert_eq!(set.decode(&unique), group);
+        }
+
+        // pairs encode the same as a composed group
+        let pair = (OperationsSet::new(), ErrorsSet::new());
+        let composed = ComposedGroup(OperationsSet::new(), ErrorsSet::new());
+        for i in 0..6 {
+            let (a, b) = pair.decode_dense(i);
+            assert_eq!(ComposedGroup(a, b), composed.decode_dense(i));
+        }
+    }
+
+    #[derive(Clone, Copy, PartialEq, Debug, LabelGroup)]
+    #[label(crate = crate, set = RequestSet)]
+    struct Request<'a> {
+        #[label(flatten = AttemptSet)]
+        attempt: Attempt,
+        #[label(dynamic_with = crate::label::BoundedLabelSet)]
+        route: &'a str,
+        #[label(omit_none)]
+        error: Option<ErrorKind>,
+        #[label(dynamic_with = crate::label::BoundedLabelSet, omit_none)]
+        region: Option<&'a str>,
+    }
+
+    #[test]
+    fn try_parse() {
+        let set = RequestSet {
+            attempt: AttemptSet::new(),
+            route: crate::label::BoundedLabelSet::new(2).with_other("other"),
+            error: crate::label::StaticLabelSet::new(),
+            region: crate::label::BoundedLabelSet::new(1),
+        };
+        set.encode(Request {
+            attempt: Attempt {
+                retried: false,
+                number: crate::label::BoundedInt::new(1).unwrap(),
+            },
+            route: "/home",
+            error: None,
+            region: Some("eu"),
+        })
+        .unwrap();
+
+        let request = set
+            .try_parse(&[
+                ("route", "/home"),
+                ("retried", "true"),
+                ("attempt", "2"),
+                ("error", "network"),
+            ])
+            .unwrap();
+        assert_eq!(request.route, "/home");
+        assert!(request.attempt.retried);
+        assert_eq!(request.attempt.number.get(), 2);
+        assert_eq!(request.error, Some(ErrorKind::Network));
+        assert_eq!(request.region, None);
+
+        let labels = [
+            ("region", "eu"),
+            ("route", "other"),
+            ("attempt", "3"),
+            ("retried", "false"),
+        ];
+        let request = set.try_parse(&labels).unwrap();
+        assert_eq!(request.route, "other");
+        assert_eq!(request.region, Some("eu"));
+        assert_eq!(request.error, None);
+
+        // values that were never encoded are not interned
+        assert!(
+            set.try_parse(&[("route", "/new"), ("retried", "true"), ("attempt", "1")])
+                .is_none()
+        );
+        assert_eq!(set.route.len(), 1);
+
+        // missing, unknown, duplicate and out of range labels
+        assert!(
+            set.try_parse(&[("route", "/home"), ("retried", "true")])
+                .is_none()
+        );
+        assert!(
+            set.try_parse(&[
+                ("route", "/home"),
+                ("retried", "true"),
+                ("attempt", "1"),
+                ("path", "/")
+            ])
+            .is_none()
+        );
+        assert!(
+            set.try_parse(&[
+                ("route", "/home"),
+                ("retried", "true"),
+                ("attempt", "1"),
+                ("route", "/home")
+            ])
+            .is_none()
+        );
+        assert!(
+            set.try_parse(&[("route", "/home"), ("retried", "true"), ("attempt", "4")])
+                .is_none()
+        );
+
+        let composed = ComposedGroup(OperationsSet::new(), AttemptSet::new());
+        assert_eq!(
+            composed.try_parse(&[("attempt", "1"), ("kind", "post"), ("retried", "false")]),
+            Some(ComposedGroup(
+                Operation {
+                    kind: OperationKind::Post
+                },
+                Attempt {
+                    retried: false,
+                    number: crate::label::BoundedInt::new(1).unwrap(),
+                }
+            ))
+        );
+        assert_eq!(composed.try_parse(&[("kind", "post")]), None);
+    }
 }
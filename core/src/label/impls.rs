@@ -1,6 +1,8 @@
 use alloc::sync::Arc;
 
-use super::{DynamicLabelSet, FixedCardinalitySet, LabelSet, LabelValue, LabelVisitor};
+use super::{
+    DynamicLabelSet, FixedCardinalityLabel, FixedCardinalitySet, LabelSet, LabelValue, LabelVisitor,
+};
 
 #[cfg(feature = "indexmap")]
 impl<T: LabelValue + core::hash::Hash + Eq + Clone, S: core::hash::BuildHasher> FixedCardinalitySet
@@ -28,6 +30,11 @@ impl<T: LabelValue + core::hash::Hash + Eq + Clone, S: core::hash::BuildHasher>
     fn decode(&self, value: usize) -> Self::Value<'_> {
         self.get_index(value).unwrap().clone()
     }
+
+    fn parse(&self, value: &str) -> Option<usize> {
+        self.iter()
+            .position(|x| x.visit(super::value::ValueEq(value)))
+    }
 }
 
 impl LabelValue for String {
@@ -48,6 +55,66 @@ impl<T: LabelValue + ?Sized> LabelValue for &T {
     }
 }
 
+impl LabelValue for bool {
+    fn visit<V: LabelVisitor>(&self, v: V) -> V::Output {
+        v.write_str(if *self { "true" } else { "false" })
+    }
+}
+
+impl FixedCardinalityLabel for bool {
+    fn cardinality() -> usize {
+        2
+    }
+
+    fn encode(&self) -> usize {
+        usize::from(*self)
+    }
+
+    fn decode(value: usize) -> Self {
+        value != 0
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        value.parse().ok()
+    }
+}
+
+/// `None` is written as an empty string, which prometheus treats the same as a missing label.
+impl<T: LabelValue> LabelValue for Option<T> {
+    fn visit<V: LabelVisitor>(&self, v: V) -> V::Output {
+        match self {
+            Some(x) => x.visit(v),
+            None => v.write_str(""),
+        }
+    }
+}
+
+/// `None` is encoded as an extra value after all the values of `T`.
+impl<T: FixedCardinalityLabel> FixedCardinalityLabel for Option<T> {
+    fn cardinality() -> usize {
+        T::cardinality() + 1
+    }
+
+    fn encode(&self) -> usize {
+        match self {
+            Some(x) => x.encode(),
+            None => T::cardinality(),
+        }
+    }
+
+    fn decode(value: usize) -> Self {
+        (value < T::cardinality()).then(|| T::decode(value))
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        if value.is_empty() {
+            Some(None)
+        } else {
+            T::parse(value).map(Some)
+        }
+    }
+}
+
 #[cfg(feature = "paracord")]
 impl<S: core::hash::BuildHasher> DynamicLabelSet for paracord::ParaCord<S> {}
 
@@ -71,6 +138,10 @@ impl<S: core::hash::BuildHasher> LabelSet for paracord::ParaCord<S> {
                 .unwrap(),
         )
     }
+
+    fn parse(&self, value: &str) -> Option<usize> {
+        Some(self.get(value)?.into_repr() as usize)
+    }
 }
 
 #[cfg(feature = "lasso")]
@@ -97,6 +168,10 @@ impl<K: lasso::Key, S: core::hash::BuildHasher> LabelSet for lasso::RodeoReader<
     fn decode(&self, value: usize) -> Self::Value<'_> {
         self.resolve(&K::try_from_usize(value).unwrap())
     }
+
+    fn parse(&self, value: &str) -> Option<usize> {
+        self.encode(value)
+    }
 }
 
 #[cfg(feature = "lasso")]
@@ -116,6 +191,10 @@ impl<K: lasso::Key + core::hash::Hash, S: core::hash::BuildHasher + Clone> Label
     fn decode(&self, value: usize) -> Self::Value<'_> {
         self.resolve(&K::try_from_usize(value).unwrap())
     }
+
+    fn parse(&self, value: &str) -> Option<usize> {
+        Some(self.get(value)?.into_usize())
+    }
 }
 
 #[cfg(feature = "phf")]
@@ -133,6 +212,10 @@ impl LabelSet for phf::OrderedSet<&'static str> {
     fn decode(&self, value: usize) -> Self::Value<'_> {
         self.index(value).unwrap()
     }
+
+    fn parse(&self, value: &str) -> Option<usize> {
+        self.get_index(value)
+    }
 }
 
 #[cfg(feature = "phf")]
@@ -164,11 +247,31 @@ impl<T: LabelSet + ?Sized> LabelSet for Arc<T> {
     fn decode(&self, value: usize) -> Self::Value<'_> {
         T::decode(self, value)
     }
+
+    fn folded_into(&self, value: usize) -> Option<usize> {
+        T::folded_into(self, value)
+    }
+
+    fn evicted(&self, value: usize) -> bool {
+        T::evicted(self, value)
+    }
+
+    fn parse(&self, value: &str) -> Option<usize> {
+        T::parse(self, value)
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::label::{FixedCardinalitySet, LabelSet};
+    #[cfg(any(feature = "phf", feature = "indexmap", feature = "lasso"))]
+    use crate::label::FixedCardinalitySet;
+    #[cfg(any(
+        feature = "phf",
+        feature = "indexmap",
+        feature = "lasso",
+        feature = "paracord"
+    ))]
+    use crate::label::LabelSet;
 
     #[cfg(feature = "phf")]
     #[test]
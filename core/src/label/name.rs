@@ -21,6 +21,121 @@ impl LabelName {
         // I could use bytemuck::TransparentWrapper, but the trait enabled users to skip this validation function.
         unsafe { &*(value as *const str as *const LabelName) }
     }
+
+    /// Validate the string is a valid label
+    ///
+    /// # Errors
+    /// Will error if the string does not conform to the prometheus label name requirements
+    pub fn try_from_str(value: &str) -> Result<&Self, InvalidLabelName> {
+        try_assert_label_name(value)?;
+
+        // SAFETY: `LabelName` is transparent over `str`. There's no way to do this safely.
+        // I could use bytemuck::TransparentWrapper, but the trait enabled users to skip this validation function.
+        Ok(unsafe { &*(value as *const str as *const LabelName) })
+    }
+}
+
+/// Error returned by [`LabelName::try_from_str`]
+#[derive(Debug)]
+pub enum InvalidLabelName {
+    /// The label name contained invalid characters
+    InvalidChars,
+    /// The label name was empty
+    Empty,
+    /// The label name started with a number
+    StartsWithNumber,
+}
+
+impl core::fmt::Display for InvalidLabelName {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            InvalidLabelName::InvalidChars => {
+                f.write_str("label name contained invalid characters")
+            }
+            InvalidLabelName::Empty => f.write_str("label name was empty"),
+            InvalidLabelName::StartsWithNumber => f.write_str("label name started with a number"),
+        }
+    }
+}
+
+impl std::error::Error for InvalidLabelName {}
+
+/// An owned, validated label name. See [`LabelName`].
+///
+/// ```
+/// use measured::label::LabelNameBuf;
+///
+/// let name = LabelNameBuf::try_from("region".to_owned()).unwrap();
+/// assert_eq!(name.as_str(), "region");
+///
+/// assert!(LabelNameBuf::try_from("2fa").is_err());
+/// ```
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct LabelNameBuf(Box<str>);
+
+impl LabelNameBuf {
+    /// Borrow the label name
+    pub fn as_label_name(&self) -> &LabelName {
+        // SAFETY: `LabelName` is transparent over `str`, and the string was validated on construction.
+        unsafe { &*(&*self.0 as *const str as *const LabelName) }
+    }
+}
+
+impl core::ops::Deref for LabelNameBuf {
+    type Target = LabelName;
+
+    fn deref(&self) -> &LabelName {
+        self.as_label_name()
+    }
+}
+
+impl AsRef<LabelName> for LabelNameBuf {
+    fn as_ref(&self) -> &LabelName {
+        self.as_label_name()
+    }
+}
+
+impl From<&LabelName> for LabelNameBuf {
+    fn from(value: &LabelName) -> Self {
+        Self(value.as_str().into())
+    }
+}
+
+impl TryFrom<String> for LabelNameBuf {
+    type Error = InvalidLabelName;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        try_assert_label_name(&value)?;
+        Ok(Self(value.into_boxed_str()))
+    }
+}
+
+impl TryFrom<&str> for LabelNameBuf {
+    type Error = InvalidLabelName;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        LabelName::try_from_str(value).map(Self::from)
+    }
+}
+
+impl core::str::FromStr for LabelNameBuf {
+    type Err = InvalidLabelName;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::try_from(s)
+    }
+}
+
+impl core::fmt::Debug for LabelNameBuf {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Debug::fmt(&*self.0, f)
+    }
+}
+
+impl core::fmt::Display for LabelNameBuf {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(&self.0)
+    }
 }
 
 const fn assert_label_name(name: &str) {
@@ -40,3 +155,20 @@ const fn assert_label_name(name: &str) {
         "string should not start with a digit"
     );
 }
+
+fn try_assert_label_name(value: &str) -> Result<(), InvalidLabelName> {
+    if value.is_empty() {
+        return Err(InvalidLabelName::Empty);
+    }
+
+    value.bytes().try_fold((), |(), b| match b {
+        b'0'..=b'9' | b'A'..=b'Z' | b'a'..=b'z' | b'_' => Ok(()),
+        _ => Err(InvalidLabelName::InvalidChars),
+    })?;
+
+    if value.as_bytes()[0].is_ascii_digit() {
+        return Err(InvalidLabelName::StartsWithNumber);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,277 @@
+use core::{ops::RangeInclusive, str::FromStr};
+
+use super::{FixedCardinalityLabel, LabelValue, LabelVisitor, ParseLabelValueError};
+
+/// An integer label value in the range `MIN..=MAX`.
+///
+/// The number of values in the range must fit in a `usize`, so the full `i64` range is not supported.
+///
+/// ```
+/// use measured::label::{BoundedInt, FixedCardinalityLabel, LabelTestVisitor, LabelValue};
+///
+/// type StatusCode = BoundedInt<100, 599>;
+///
+/// assert_eq!(StatusCode::cardinality(), 500);
+///
+/// let not_found = StatusCode::new(404).unwrap();
+/// assert_eq!(not_found.encode(), 304);
+/// assert_eq!(not_found.visit(LabelTestVisitor), "404");
+///
+/// assert_eq!(StatusCode::new(600), None);
+/// assert_eq!(StatusCode::saturating(600).get(), 599);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BoundedInt<const MIN: i64, const MAX: i64>(i64);
+
+impl<const MIN: i64, const MAX: i64> BoundedInt<MIN, MAX> {
+    const VALID: () = {
+        assert!(MIN <= MAX, "MIN must not be greater than MAX");
+        assert!(
+            MAX.abs_diff(MIN) < usize::MAX as u64,
+            "the number of values in MIN..=MAX must fit in a usize"
+        );
+    };
+
+    /// Create a new bounded integer. Returns `None` if the value is out of range.
+    pub const fn new(value: i64) -> Option<Self> {
+        let () = Self::VALID;
+        if MIN <= value && value <= MAX {
+            Some(Self(value))
+        } else {
+            None
+        }
+    }
+
+    /// Create a new bounded integer, clamping the value into range.
+    pub const fn saturating(value: i64) -> Self {
+        let () = Self::VALID;
+        if value < MIN {
+            Self(MIN)
+        } else if value > MAX {
+            Self(MAX)
+        } else {
+            Self(value)
+        }
+    }
+
+    /// Get the integer value
+    pub const fn get(self) -> i64 {
+        self.0
+    }
+}
+
+impl<const MIN: i64, const MAX: i64> FromStr for BoundedInt<MIN, MAX> {
+    type Err = ParseLabelValueError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse()
+            .ok()
+            .and_then(Self::new)
+            .ok_or_else(|| ParseLabelValueError::new(s))
+    }
+}
+
+impl<const MIN: i64, const MAX: i64> LabelValue for BoundedInt<MIN, MAX> {
+    fn visit<V: LabelVisitor>(&self, v: V) -> V::Output {
+        v.write_int(self.0)
+    }
+}
+
+impl<const MIN: i64, const MAX: i64> FixedCardinalityLabel for BoundedInt<MIN, MAX> {
+    fn cardinality() -> usize {
+        let () = Self::VALID;
+        MAX.abs_diff(MIN) as usize + 1
+    }
+
+    fn encode(&self) -> usize {
+        self.0.abs_diff(MIN) as usize
+    }
+
+    fn decode(value: usize) -> Self {
+        Self(MIN.wrapping_add_unsigned(value as u64))
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        value.parse().ok()
+    }
+}
+
+/// An integer label value, grouped into buckets of `WIDTH` values covering `MIN..=MAX`.
+///
+/// Values outside of the range are put into the first or last bucket.
+///
+/// Buckets are rendered as the range of values they cover, eg `10..=14`.
+/// If `WIDTH` is a power of 10 and the bucket is aligned, the bucket is rendered with
+/// the varying digits replaced by `x`, eg `2xx` for HTTP status codes.
+/// The bucket starting at 0 is still rendered as a range, eg `0..=9`, as `0x` would read as a hex prefix.
+///
+/// ```
+/// use measured::label::{Bucketed, FixedCardinalityLabel, LabelTestVisitor, LabelValue};
+///
+/// type StatusClass = Bucketed<100, 599, 100>;
+///
+/// assert_eq!(StatusClass::cardinality(), 5);
+/// assert_eq!(StatusClass::new(404).visit(LabelTestVisitor), "4xx");
+/// assert_eq!(StatusClass::new(404).range(), 400..=499);
+///
+/// type Retries = Bucketed<0, 9, 5>;
+/// assert_eq!(Retries::new(3).visit(LabelTestVisitor), "0..=4");
+/// assert_eq!(Retries::new(12).visit(LabelTestVisitor), "5..=9");
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Bucketed<const MIN: i64, const MAX: i64, const WIDTH: u64>(usize);
+
+impl<const MIN: i64, const MAX: i64, const WIDTH: u64> Bucketed<MIN, MAX, WIDTH> {
+    const VALID: () = {
+        assert!(MIN <= MAX, "MIN must not be greater than MAX");
+        assert!(WIDTH > 0, "WIDTH must not be zero");
+        assert!(
+            MAX.abs_diff(MIN) / WIDTH < usize::MAX as u64,
+            "the number of buckets must fit in a usize"
+        );
+    };
+
+    /// Put the value into its bucket
+    pub const fn new(value: i64) -> Self {
+        let () = Self::VALID;
+        let value = if value < MIN {
+            MIN
+        } else if value > MAX {
+            MAX
+        } else {
+            value
+        };
+        Self((value.abs_diff(MIN) / WIDTH) as usize)
+    }
+
+    /// The range of values that this bucket covers
+    pub const fn range(self) -> RangeInclusive<i64> {
+        let start = MIN.wrapping_add_unsigned(self.0 as u64 * WIDTH);
+        let end = start.wrapping_add_unsigned(WIDTH - 1);
+        start..=if end > MAX { MAX } else { end }
+    }
+}
+
+impl<const MIN: i64, const MAX: i64, const WIDTH: u64> LabelValue for Bucketed<MIN, MAX, WIDTH> {
+    fn visit<V: LabelVisitor>(&self, v: V) -> V::Output {
+        let (start, end) = self.range().into_inner();
+        if start == end {
+            return v.write_int(start);
+        }
+
+        let mut buf = Buf::default();
+        let aligned =
+            start > 0 && (start as u64).is_multiple_of(WIDTH) && end.abs_diff(start) == WIDTH - 1;
+        match WIDTH.checked_ilog10() {
+            Some(digits) if aligned && 10u64.pow(digits) == WIDTH => {
+                buf.push(itoa::Buffer::new().format(start as u64 / WIDTH));
+                for _ in 0..digits {
+                    buf.push("x");
+                }
+            }
+            _ => {
+                buf.push(itoa::Buffer::new().format(start));
+                buf.push("..=");
+                buf.push(itoa::Buffer::new().format(end));
+            }
+        }
+        v.write_str(buf.as_str())
+    }
+}
+
+impl<const MIN: i64, const MAX: i64, const WIDTH: u64> FixedCardinalityLabel
+    for Bucketed<MIN, MAX, WIDTH>
+{
+    fn cardinality() -> usize {
+        let () = Self::VALID;
+        (MAX.abs_diff(MIN) / WIDTH) as usize + 1
+    }
+
+    fn encode(&self) -> usize {
+        self.0
+    }
+
+    fn decode(value: usize) -> Self {
+        Self(value)
+    }
+}
+
+/// Stack buffer for rendering a bucket. Large enough for 2 `i64`s and a separator.
+struct Buf {
+    buf: [u8; 48],
+    len: usize,
+}
+
+impl Default for Buf {
+    fn default() -> Self {
+        Self {
+            buf: [0; 48],
+            len: 0,
+        }
+    }
+}
+
+impl Buf {
+    fn push(&mut self, s: &str) {
+        self.buf[self.len..self.len + s.len()].copy_from_slice(s.as_bytes());
+        self.len += s.len();
+    }
+
+    fn as_str(&self) -> &str {
+        // only whole strings are pushed
+        core::str::from_utf8(&self.buf[..self.len]).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::label::{FixedCardinalityLabel, LabelTestVisitor, LabelValue};
+
+    use super::{BoundedInt, Bucketed};
+
+    #[test]
+    fn bounded_int() {
+        type Offset = BoundedInt<-3, 3>;
+        assert_eq!(Offset::cardinality(), 7);
+        for x in -3..=3 {
+            let offset = Offset::new(x).unwrap();
+            assert_eq!(Offset::decode(offset.encode()), offset);
+        }
+        assert_eq!(Offset::new(-4), None);
+        assert_eq!(Offset::saturating(-4).encode(), 0);
+
+        type Full = BoundedInt<{ i64::MIN }, { i64::MAX - 1 }>;
+        let max = Full::new(i64::MAX - 1).unwrap();
+        assert_eq!(max.encode(), Full::cardinality() - 1);
+        assert_eq!(Full::decode(max.encode()), max);
+    }
+
+    #[test]
+    fn bucketed() {
+        type Temperature = Bucketed<-20, 45, 10>;
+        assert_eq!(Temperature::cardinality(), 7);
+
+        let render = |x| Temperature::new(x).visit(LabelTestVisitor);
+        assert_eq!(render(-100), "-20..=-11");
+        assert_eq!(render(-1), "-10..=-1");
+        assert_eq!(render(0), "0..=9");
+        assert_eq!(render(10), "1x");
+        assert_eq!(render(39), "3x");
+        assert_eq!(render(40), "40..=45");
+        assert_eq!(render(100), "40..=45");
+
+        for i in 0..Temperature::cardinality() {
+            assert_eq!(Temperature::decode(i).encode(), i);
+        }
+
+        #[cfg(target_pointer_width = "64")]
+        {
+            type Wide = Bucketed<{ i64::MIN }, { i64::MAX }, 2>;
+            assert_eq!(Wide::cardinality(), 1 << 63);
+            assert_eq!(Wide::new(i64::MAX).encode(), Wide::cardinality() - 1);
+        }
+
+        type Exact = Bucketed<0, 3, 1>;
+        assert_eq!(Exact::new(2).visit(LabelTestVisitor), "2");
+    }
+}
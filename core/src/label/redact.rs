@@ -0,0 +1,289 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use super::{DynamicLabelSet, FixedCardinalitySet, LabelSet, LabelValue, LabelVisitor};
+
+/// A secret key used to redact sensitive label values, such as user IDs or email addresses.
+///
+/// Values are rendered as the first 8 bytes of their HMAC-SHA256, in hex.
+/// The key should be configured once per process, eg from an environment variable, and kept secret.
+/// Without the key, the original values cannot be recovered or confirmed from the exported metrics.
+///
+/// ```
+/// use measured::{CounterVec, LabelGroup};
+/// use measured::label::{BoundedLabelSet, Redacted, RedactedLabelSet, RedactionKey};
+/// use std::sync::LazyLock;
+///
+/// static KEY: LazyLock<RedactionKey> = LazyLock::new(|| RedactionKey::new(b"secret key"));
+///
+/// #[derive(LabelGroup)]
+/// #[label(set = RequestSet)]
+/// struct Request<'a> {
+///     #[label(dynamic_with = RedactedLabelSet<BoundedLabelSet>)]
+///     user: Redacted<'a>,
+/// }
+///
+/// let requests = CounterVec::with_label_set(RequestSet::new(RedactedLabelSet::new(
+///     BoundedLabelSet::new(1000),
+/// )));
+///
+/// requests.inc(Request { user: KEY.redact("alice@example.com") });
+/// ```
+#[derive(Clone)]
+pub struct RedactionKey {
+    /// The hash states after absorbing the padded key
+    mac: Hmac<Sha256>,
+}
+
+impl RedactionKey {
+    /// Create a new key. Keys should be at least 32 bytes of random data.
+    pub fn new(key: impl AsRef<[u8]>) -> Self {
+        let mac = Hmac::new_from_slice(key.as_ref()).expect("HMAC accepts keys of any length");
+        Self { mac }
+    }
+
+    /// Redact the value with this key
+    pub fn redact<'a>(&'a self, value: &'a str) -> Redacted<'a> {
+        Redacted(Repr::Raw(self, value))
+    }
+
+    /// The truncated HMAC-SHA256 of the value
+    fn digest(&self, value: &str) -> u64 {
+        let digest = self.mac.clone().chain_update(value).finalize().into_bytes();
+        let (prefix, _) = digest.split_first_chunk().expect("digest is 32 bytes");
+        u64::from_be_bytes(*prefix)
+    }
+}
+
+impl core::fmt::Debug for RedactionKey {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("RedactionKey").finish_non_exhaustive()
+    }
+}
+
+/// A [`LabelValue`] that renders a keyed hash of the value, instead of the value itself.
+///
+/// Created with [`RedactionKey::redact`], and encoded with a [`RedactedLabelSet`] or [`RedactedBuckets`].
+#[derive(Clone, Copy)]
+pub struct Redacted<'a>(Repr<'a>);
+
+#[derive(Clone, Copy)]
+enum Repr<'a> {
+    Raw(&'a RedactionKey, &'a str),
+    /// Decoded from a [`RedactedLabelSet`], already rendered
+    Rendered(&'a str),
+    /// Decoded from [`RedactedBuckets`]
+    Bucket(usize),
+}
+
+impl Redacted<'_> {
+    fn digest(&self) -> Option<u64> {
+        match self.0 {
+            Repr::Raw(key, value) => Some(key.digest(value)),
+            Repr::Rendered(value) if value.len() == 16 => u64::from_str_radix(value, 16).ok(),
+            Repr::Rendered(_) | Repr::Bucket(_) => None,
+        }
+    }
+}
+
+fn render(digest: u64, buf: &mut [u8; 16]) -> &str {
+    const HEX: &[u8; 16] = b"0123456789abcdef";
+    for (i, b) in buf.iter_mut().enumerate() {
+        *b = HEX[(digest >> (60 - i * 4)) as usize & 0xf];
+    }
+    core::str::from_utf8(buf).expect("hex digits are ascii")
+}
+
+impl LabelValue for Redacted<'_> {
+    fn visit<V: LabelVisitor>(&self, v: V) -> V::Output {
+        match self.0 {
+            Repr::Raw(key, value) => v.write_str(render(key.digest(value), &mut [0; 16])),
+            Repr::Rendered(value) => v.write_str(value),
+            Repr::Bucket(bucket) => v.write_int(bucket as i64),
+        }
+    }
+}
+
+/// A [`LabelSet`] for [`Redacted`] values, which interns the rendered hashes into the inner set.
+///
+/// The inner set can be any set of strings, such as a [`BoundedLabelSet`](super::BoundedLabelSet)
+/// to limit the number of distinct values. The raw values are never stored.
+pub struct RedactedLabelSet<S> {
+    inner: S,
+}
+
+impl<S> RedactedLabelSet<S> {
+    /// Create a new set, interning the hashes into `inner`
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+
+    /// The inner set of hashes
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// The inner set of hashes, eg to evict unused values
+    pub fn inner_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+}
+
+impl<S: DynamicLabelSet> DynamicLabelSet for RedactedLabelSet<S> where
+    S: for<'a> LabelSet<Value<'a> = &'a str>
+{
+}
+
+impl<S> LabelSet for RedactedLabelSet<S>
+where
+    S: for<'a> LabelSet<Value<'a> = &'a str>,
+{
+    type Value<'a> = Redacted<'a>;
+
+    fn dynamic_cardinality(&self) -> Option<usize> {
+        self.inner.dynamic_cardinality()
+    }
+
+    fn encode(&self, value: Self::Value<'_>) -> Option<usize> {
+        match value.0 {
+            Repr::Raw(key, value) => self.inner.encode(render(key.digest(value), &mut [0; 16])),
+            // this might also be a fallback value of the inner set, eg "other"
+            Repr::Rendered(value) => self.inner.encode(value),
+            Repr::Bucket(_) => None,
+        }
+    }
+
+    fn decode(&self, value: usize) -> Self::Value<'_> {
+        Redacted(Repr::Rendered(self.inner.decode(value)))
+    }
+
+    fn folded_into(&self, value: usize) -> Option<usize> {
+        self.inner.folded_into(value)
+    }
+
+    fn evicted(&self, value: usize) -> bool {
+        self.inner.evicted(value)
+    }
+
+    fn parse(&self, value: &str) -> Option<usize> {
+        self.inner.parse(value)
+    }
+}
+
+/// A [`FixedCardinalitySet`] for [`Redacted`] values, which renders the keyed hash modulo the number of buckets.
+///
+/// This keeps the cardinality fixed, at the cost of grouping unrelated values together.
+/// Values that are not a keyed hash, such as the fallback value of a [`RedactedLabelSet`], are counted in bucket 0.
+///
+/// ```
+/// use measured::{CounterVec, LabelGroup};
+/// use measured::label::{Redacted, RedactedBuckets, RedactionKey};
+///
+/// #[derive(LabelGroup)]
+/// #[label(set = RequestSet)]
+/// struct Request<'a> {
+///     #[label(fixed_with = RedactedBuckets)]
+///     user: Redacted<'a>,
+/// }
+///
+/// let key = RedactionKey::new(b"secret key");
+/// let requests = CounterVec::with_label_set(RequestSet::new(RedactedBuckets::new(64)));
+///
+/// requests.inc(Request { user: key.redact("alice@example.com") });
+/// ```
+pub struct RedactedBuckets {
+    buckets: usize,
+}
+
+impl RedactedBuckets {
+    /// Create a new set with this many buckets
+    ///
+    /// # Panics
+    ///
+    /// Panics if `buckets` is 0.
+    pub fn new(buckets: usize) -> Self {
+        assert!(buckets > 0, "there should be at least 1 bucket");
+        Self { buckets }
+    }
+}
+
+impl FixedCardinalitySet for RedactedBuckets {}
+
+impl LabelSet for RedactedBuckets {
+    type Value<'a> = Redacted<'a>;
+
+    fn dynamic_cardinality(&self) -> Option<usize> {
+        Some(self.buckets)
+    }
+
+    fn encode(&self, value: Self::Value<'_>) -> Option<usize> {
+        let bucket = match value.0 {
+            Repr::Bucket(bucket) => bucket % self.buckets,
+            _ => value
+                .digest()
+                .map_or(0, |digest| (digest % self.buckets as u64) as usize),
+        };
+        Some(bucket)
+    }
+
+    fn decode(&self, value: usize) -> Self::Value<'_> {
+        Redacted(Repr::Bucket(value))
+    }
+
+    fn parse(&self, value: &str) -> Option<usize> {
+        value.parse().ok().filter(|&bucket| bucket < self.buckets)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::label::{BoundedLabelSet, LabelSet};
+
+    use super::{RedactedBuckets, RedactedLabelSet, RedactionKey};
+
+    #[test]
+    fn hmac() {
+        // RFC 4231, test case 2
+        let key = RedactionKey::new(b"Jefe");
+        assert_eq!(
+            key.digest("what do ya want for nothing?"),
+            0x5bdcc146bf60754e
+        );
+
+        // RFC 4231, test case 6
+        let key = RedactionKey::new([0xaa; 131]);
+        assert_eq!(
+            key.digest("Test Using Larger Than Block-Size Key - Hash Key First"),
+            0x60e431591ee0b67f
+        );
+    }
+
+    #[test]
+    fn redacted() {
+        let key = RedactionKey::new(b"Jefe");
+
+        let set = RedactedLabelSet::new(BoundedLabelSet::new(1).with_other("other"));
+        let index = set
+            .encode(key.redact("what do ya want for nothing?"))
+            .unwrap();
+        assert_eq!(set.inner().decode(index), "5bdcc146bf60754e");
+        assert_eq!(set.encode(set.decode(index)), Some(index));
+
+        let other = set.encode(key.redact("someone else")).unwrap();
+        assert_eq!(set.inner().decode(other), "other");
+
+        let buckets = RedactedBuckets::new(16);
+        let bucket = buckets.encode(key.redact("what do ya want for nothing?"));
+        assert_eq!(bucket, Some(0xe));
+        assert_eq!(buckets.encode(buckets.decode(0xe)), Some(0xe));
+        assert_eq!(
+            buckets.encode(set.decode(index)),
+            bucket,
+            "digests are bucketed consistently"
+        );
+
+        let other = set.decode(other);
+        assert_eq!(buckets.encode(other), Some(0));
+        assert_eq!(buckets.encode(RedactedBuckets::new(32).decode(20)), Some(4));
+    }
+}
@@ -0,0 +1,436 @@
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::{
+    collections::{BTreeSet, HashSet},
+    hash::{BuildHasher, RandomState},
+    sync::OnceLock,
+};
+
+use crossbeam_utils::CachePadded;
+use hashbrown::HashTable;
+use parking_lot::Mutex;
+
+use super::{DynamicLabelSet, LabelSet};
+
+/// A [`DynamicLabelSet`] that only keeps the `k` most frequent values as separate label values.
+///
+/// Frequencies are estimated with the Space-Saving algorithm, which tracks a bounded number of candidate values.
+/// A value is promoted to one of the `k` slots once its estimated count reaches the counts of the
+/// current top `k`. All other values are encoded as the "other" value.
+///
+/// Periodically, the set should be [rebalanced](Self::rebalance), which demotes the values that are no longer
+/// in the top `k`. The series of the demoted values are folded into the "other" series, so the totals stay accurate.
+/// This can only be done through [`MetricVec::rebalance_label_set`](crate::MetricVec::rebalance_label_set).
+/// Counts are halved after each rebalance, so the set adapts to changes in traffic.
+///
+/// Encoding a promoted value is lock-free. Other values update the frequency estimates in one of several
+/// shards, selected by the hash of the value, which takes the lock of that shard.
+///
+/// ```
+/// use measured::{CounterVec, LabelGroup};
+/// use measured::label::TopKLabelSet;
+/// use std::sync::atomic::Ordering;
+///
+/// #[derive(LabelGroup)]
+/// #[label(set = RequestSet)]
+/// struct Request<'a> {
+///     #[label(dynamic_with = TopKLabelSet)]
+///     client: &'a str,
+/// }
+///
+/// let mut requests = CounterVec::with_label_set(RequestSet::new(TopKLabelSet::new(1)));
+///
+/// for client in ["10.0.0.1", "10.0.0.2", "10.0.0.2", "10.0.0.2"] {
+///     requests.inc(Request { client });
+/// }
+///
+/// // 10.0.0.1 took the only slot first, but 10.0.0.2 is the heavy hitter
+/// requests.rebalance_label_set(|set| {
+///     set.client.rebalance();
+/// });
+///
+/// // the series of 10.0.0.1 was folded into "other", which already counted 10.0.0.2
+/// let other = requests.with_labels(Request { client: "other" });
+/// assert_eq!(requests.get_metric(other).count.load(Ordering::Relaxed), 4);
+///
+/// requests.inc(Request { client: "10.0.0.2" });
+/// let client = requests.with_labels(Request { client: "10.0.0.2" });
+/// assert_eq!(requests.get_metric(client).count.load(Ordering::Relaxed), 1);
+/// ```
+pub struct TopKLabelSet {
+    /// Values are only removed with `&mut self`, so they can be borrowed while promoting
+    values: Box<[OnceLock<Box<str>>]>,
+    /// The estimated counts of the promoted values
+    counts: Box<[AtomicU64]>,
+    /// Open addressing index of the promoted values, storing `slot + 1`. Only written while holding `free`.
+    index: Box<[AtomicUsize]>,
+    free: Mutex<Vec<usize>>,
+    /// The candidates that are not promoted, sharded by hash
+    sketches: Box<[CachePadded<Mutex<SpaceSaving>>]>,
+    /// The estimated count a value needs to be promoted
+    threshold: AtomicU64,
+    hasher: RandomState,
+    other: Box<str>,
+}
+
+/// The number of candidates tracked by each shard of the sketch
+const SHARD_CAPACITY: usize = 1024;
+const MAX_SHARDS: usize = 16;
+
+/// The candidates for the heavy hitters, see
+/// "Efficient Computation of Frequent and Top-k Elements in Data Streams" by Metwally, Agrawal and El Abbadi.
+struct SpaceSaving {
+    entries: Vec<Entry>,
+    table: HashTable<usize>,
+    /// Orders the entries by count, to find the entry to replace
+    counts: BTreeSet<(u64, usize)>,
+    capacity: usize,
+}
+
+struct Entry {
+    value: Box<str>,
+    count: u64,
+}
+
+impl TopKLabelSet {
+    /// Create a new set that keeps the `k` most frequent values, tracking 10000 candidate values
+    pub fn new(k: usize) -> Self {
+        Self::with_capacity(k, 10000.max(k))
+    }
+
+    /// Create a new set that keeps the `k` most frequent values, tracking `capacity` candidate values.
+    ///
+    /// More candidates make the frequency estimates more accurate, at the cost of memory.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0 or less than `k`.
+    pub fn with_capacity(k: usize, capacity: usize) -> Self {
+        assert!(
+            capacity >= k.max(1),
+            "capacity should be at least k, and not 0"
+        );
+        let shards = capacity.div_ceil(SHARD_CAPACITY).min(MAX_SHARDS);
+        let shard_capacity = capacity.div_ceil(shards);
+        Self {
+            values: (0..k).map(|_| OnceLock::new()).collect(),
+            counts: (0..k).map(|_| AtomicU64::new(0)).collect(),
+            // keep the index at most half full, so probing is short and always finds an empty slot
+            index: (0..(2 * k).next_power_of_two())
+                .map(|_| AtomicUsize::new(0))
+                .collect(),
+            free: Mutex::new((0..k).rev().collect()),
+            sketches: (0..shards)
+                .map(|_| {
+                    CachePadded::new(Mutex::new(SpaceSaving {
+                        entries: Vec::with_capacity(shard_capacity),
+                        table: HashTable::with_capacity(shard_capacity),
+                        counts: BTreeSet::new(),
+                        capacity: shard_capacity,
+                    }))
+                })
+                .collect(),
+            threshold: AtomicU64::new(0),
+            hasher: RandomState::new(),
+            other: "other".into(),
+        }
+    }
+
+    /// Encode all values outside of the top `k` as this value. Defaults to `"other"`
+    #[must_use]
+    pub fn with_other(mut self, other: impl Into<String>) -> Self {
+        self.other = other.into().into_boxed_str();
+        self
+    }
+
+    /// The number of values kept as separate label values
+    pub fn k(&self) -> usize {
+        self.values.len()
+    }
+
+    /// The number of values currently promoted
+    pub fn len(&self) -> usize {
+        self.values.iter().filter(|v| v.get().is_some()).count()
+    }
+
+    /// Whether no values are currently promoted
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Demote the values that are no longer in the top `k`, and decay the frequency estimates.
+    /// Returns the number of values demoted.
+    pub fn rebalance(&mut self) -> usize {
+        let k = self.values.len();
+
+        // the promoted values are not counted by the sketches after their promotion
+        let mut candidates: Vec<(u64, &str)> = self
+            .values
+            .iter()
+            .zip(&mut self.counts)
+            .filter_map(|(v, count)| Some((*count.get_mut(), &**v.get()?)))
+            .collect();
+        let promoted: HashSet<&str> = candidates.iter().map(|&(_, v)| v).collect();
+        for sketch in &mut self.sketches {
+            let sketch = sketch.get_mut();
+            candidates.extend(
+                (sketch.entries.iter())
+                    .filter(|e| !promoted.contains(&*e.value))
+                    .map(|e| (e.count, &*e.value)),
+            );
+        }
+        candidates.sort_unstable_by(|a, b| b.cmp(a));
+        let top: HashSet<&str> = candidates.iter().take(k).map(|&(_, v)| v).collect();
+
+        // after decaying, a value needs to reach the k-th largest count to be promoted
+        let threshold = match candidates.get(k.saturating_sub(1)) {
+            Some(&(count, _)) if k > 0 => count / 2,
+            _ => 0,
+        };
+
+        let demoted: Vec<usize> = (self.values.iter().enumerate())
+            .filter(|(_, v)| v.get().is_some_and(|v| !top.contains(&**v)))
+            .map(|(i, _)| i)
+            .collect();
+        for &i in &demoted {
+            self.values[i].take();
+            self.free.get_mut().push(i);
+        }
+
+        for index in &mut self.index {
+            *index.get_mut() = 0;
+        }
+        for (i, (value, count)) in self.values.iter().zip(&mut self.counts).enumerate() {
+            *count.get_mut() /= 2;
+            if let Some(value) = value.get() {
+                let hash = self.hasher.hash_one(&**value);
+                insert_index(&self.index, hash, i);
+            }
+        }
+        for sketch in &mut self.sketches {
+            sketch.get_mut().decay();
+        }
+        *self.threshold.get_mut() = threshold;
+
+        demoted.len()
+    }
+
+    fn find(&self, hash: u64, value: &str) -> Option<usize> {
+        let mask = self.index.len() - 1;
+        let mut pos = hash as usize & mask;
+        loop {
+            match self.index[pos].load(Ordering::Acquire) {
+                0 => return None,
+                i if self.values[i - 1].get().is_some_and(|v| **v == *value) => return Some(i - 1),
+                _ => pos = (pos + 1) & mask,
+            }
+        }
+    }
+
+    fn sketch(&self, hash: u64) -> &Mutex<SpaceSaving> {
+        // the low bits are used by the index and the sketch tables
+        &self.sketches[(hash >> 48) as usize % self.sketches.len()]
+    }
+}
+
+/// Publish the promoted slot `i` in the index. Must be called while holding `free`, or with `&mut self`.
+fn insert_index(index: &[AtomicUsize], hash: u64, i: usize) {
+    let mask = index.len() - 1;
+    let mut pos = hash as usize & mask;
+    while index[pos].load(Ordering::Relaxed) != 0 {
+        pos = (pos + 1) & mask;
+    }
+    // the value was set before, so readers that see the slot also see the value
+    index[pos].store(i + 1, Ordering::Release);
+}
+
+impl SpaceSaving {
+    /// Count the value, returning its estimated count
+    fn observe(&mut self, hasher: &RandomState, hash: u64, value: &str) -> u64 {
+        let entries = &self.entries;
+        let i = match self.table.find(hash, |&i| *entries[i].value == *value) {
+            Some(&i) => i,
+            None if self.entries.len() < self.capacity => {
+                let i = self.entries.len();
+                self.entries.push(Entry {
+                    value: value.into(),
+                    count: 0,
+                });
+                self.counts.insert((0, i));
+                self.insert(hasher, hash, i);
+                i
+            }
+            None => {
+                // replace the least frequent value. The new value inherits its count, which bounds the error.
+                let (_, i) = *self.counts.first().expect("capacity is not 0");
+                let old_hash = hasher.hash_one(&*self.entries[i].value);
+                self.table
+                    .find_entry(old_hash, |&j| j == i)
+                    .expect("entries should be indexed")
+                    .remove();
+                self.entries[i].value = value.into();
+                self.insert(hasher, hash, i);
+                i
+            }
+        };
+
+        let entry = &mut self.entries[i];
+        self.counts.remove(&(entry.count, i));
+        entry.count += 1;
+        self.counts.insert((entry.count, i));
+        entry.count
+    }
+
+    fn insert(&mut self, hasher: &RandomState, hash: u64, i: usize) {
+        let entries = &self.entries;
+        self.table
+            .insert_unique(hash, i, |&i| hasher.hash_one(&*entries[i].value));
+    }
+
+    fn decay(&mut self) {
+        for entry in &mut self.entries {
+            entry.count /= 2;
+        }
+        let entries = &self.entries;
+        self.counts = (0..entries.len()).map(|i| (entries[i].count, i)).collect();
+    }
+}
+
+impl DynamicLabelSet for TopKLabelSet {}
+
+impl LabelSet for TopKLabelSet {
+    type Value<'a> = &'a str;
+
+    fn dynamic_cardinality(&self) -> Option<usize> {
+        Some(self.k() + 1)
+    }
+
+    fn encode(&self, value: Self::Value<'_>) -> Option<usize> {
+        // the "other" value takes the slot after all the promoted values
+        let other = self.k();
+        if value == &*self.other {
+            return Some(other);
+        }
+
+        let hash = self.hasher.hash_one(value);
+        if let Some(i) = self.find(hash, value) {
+            self.counts[i].fetch_add(1, Ordering::Relaxed);
+            return Some(i);
+        }
+
+        let count = self.sketch(hash).lock().observe(&self.hasher, hash, value);
+        if count < self.threshold.load(Ordering::Relaxed) {
+            return Some(other);
+        }
+
+        let mut free = self.free.lock();
+        // another thread might have promoted the value while we were counting it
+        if let Some(i) = self.find(hash, value) {
+            self.counts[i].fetch_add(1, Ordering::Relaxed);
+            return Some(i);
+        }
+        let Some(i) = free.pop() else {
+            return Some(other);
+        };
+
+        assert!(
+            self.values[i].set(value.into()).is_ok(),
+            "slot should be free"
+        );
+        self.counts[i].store(count, Ordering::Relaxed);
+        insert_index(&self.index, hash, i);
+        Some(i)
+    }
+
+    fn decode(&self, value: usize) -> Self::Value<'_> {
+        match self.values.get(value) {
+            Some(v) => v.get().expect("value should be promoted"),
+            None => &self.other,
+        }
+    }
+
+    fn parse(&self, value: &str) -> Option<usize> {
+        if value == &*self.other {
+            return Some(self.k());
+        }
+        self.find(self.hasher.hash_one(value), value)
+    }
+
+    fn folded_into(&self, value: usize) -> Option<usize> {
+        // slots are only emptied when their values are demoted
+        let demoted = self.values.get(value).is_some_and(|v| v.get().is_none());
+        demoted.then_some(self.k())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::label::LabelSet;
+
+    use super::TopKLabelSet;
+
+    #[test]
+    fn top_k() {
+        let mut set = TopKLabelSet::with_capacity(2, 4);
+        assert_eq!(set.dynamic_cardinality(), Some(3));
+
+        let a = set.encode("a").unwrap();
+        let b = set.encode("b").unwrap();
+        let other = set.encode("c").unwrap();
+        assert_eq!(set.decode(a), "a");
+        assert_eq!(set.decode(b), "b");
+        assert_eq!(set.decode(other), "other");
+        assert_eq!(set.encode("other"), Some(other));
+
+        for _ in 0..4 {
+            assert_eq!(set.encode("c"), Some(other));
+            assert_eq!(set.encode("a"), Some(a));
+        }
+        assert_eq!(set.folded_into(b), None);
+
+        // `b` is no longer in the top 2
+        assert_eq!(set.rebalance(), 1);
+        assert_eq!(set.len(), 1);
+        assert_eq!(set.folded_into(b), Some(other));
+        assert_eq!(set.folded_into(a), None);
+        assert_eq!(set.folded_into(other), None);
+
+        // `c` was counted 5 times, and is now promoted into the free slot
+        let c = set.encode("c").unwrap();
+        assert_eq!(c, b);
+        assert_eq!(set.decode(c), "c");
+        assert_eq!(set.folded_into(c), None);
+
+        // `d` is not frequent enough to be promoted
+        assert_eq!(set.encode("d"), Some(other));
+        assert_eq!(set.encode("b"), Some(other));
+        assert_eq!(set.rebalance(), 0);
+    }
+
+    #[test]
+    fn sharded() {
+        let mut set = TopKLabelSet::with_capacity(2, 4 * super::SHARD_CAPACITY);
+        assert_eq!(set.sketches.len(), 4);
+
+        let values: Vec<String> = (0..64).map(|i| i.to_string()).collect();
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for (i, value) in values.iter().enumerate() {
+                        // the heavy hitters are 0 and 1
+                        for _ in 0..(if i < 2 { 20 } else { 1 }) {
+                            set.encode(value).unwrap();
+                        }
+                    }
+                });
+            }
+        });
+
+        // 0 and 1 were promoted first, and stay in the top 2
+        assert_eq!(set.rebalance(), 0);
+        let mut promoted = [set.decode(0), set.decode(1)];
+        promoted.sort_unstable();
+        assert_eq!(promoted, ["0", "1"]);
+        assert_eq!(set.encode("0"), set.parse("0"));
+        assert_eq!(set.encode("2"), Some(2));
+    }
+}
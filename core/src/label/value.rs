@@ -2,7 +2,7 @@ use core::marker::PhantomData;
 
 use crate::LabelGroup;
 
-use super::LabelGroupSet;
+use super::{FixedCardinalityGroupSet, LabelGroupSet};
 
 /// `StaticLabelSet` is a [`LabelSet`] for a [`FixedCardinalityLabel`]
 pub struct StaticLabelSet<T>(PhantomData<T>);
@@ -40,8 +40,14 @@ impl<T: FixedCardinalityLabel> LabelSet for StaticLabelSet<T> {
     fn decode(&self, value: usize) -> Self::Value<'_> {
         T::decode(value)
     }
+
+    fn parse(&self, value: &str) -> Option<usize> {
+        Some(T::parse(value)?.encode())
+    }
 }
 
+impl<T: FixedCardinalityLabel + LabelGroup> FixedCardinalityGroupSet for StaticLabelSet<T> {}
+
 impl<T: FixedCardinalityLabel + LabelGroup> LabelGroupSet for StaticLabelSet<T> {
     type Group<'a> = T;
 
@@ -66,6 +72,16 @@ impl<T: FixedCardinalityLabel + LabelGroup> LabelGroupSet for StaticLabelSet<T>
     fn decode(&self, value: &Self::Unique) -> Self::Group<'_> {
         LabelSet::decode(self, *value)
     }
+
+    fn try_parse(&self, labels: &[(&str, &str)]) -> Option<Self::Group<'_>> {
+        let [(name, value)] = labels else {
+            return None;
+        };
+        let value = T::parse(value)?;
+        let mut found = false;
+        self.visit_label_names(&mut |n| found = n.as_str() == *name);
+        found.then_some(value)
+    }
 }
 
 /// A [`LabelVisitor`] that is useful for testing purposes
@@ -97,6 +113,122 @@ impl LabelVisitor for LabelTestVisitor {
     }
 }
 
+/// The error returned when parsing a string which is not a known label value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseLabelValueError {
+    value: String,
+}
+
+impl ParseLabelValueError {
+    /// Create a new error for the unknown value
+    pub fn new(value: &str) -> Self {
+        Self {
+            value: value.to_owned(),
+        }
+    }
+
+    /// The value that could not be parsed
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+}
+
+impl core::fmt::Display for ParseLabelValueError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "unknown label value {:?}", self.value)
+    }
+}
+
+impl std::error::Error for ParseLabelValueError {}
+
+/// A [`LabelVisitor`] which prefixes the value with a string.
+///
+/// Used by the [`FixedCardinalityLabel`](macro@crate::FixedCardinalityLabel) derive for flattened variants.
+#[doc(hidden)]
+pub struct __PrefixVisitor<V> {
+    prefix: &'static str,
+    inner: V,
+}
+
+impl<V> __PrefixVisitor<V> {
+    pub fn new(prefix: &'static str, inner: V) -> Self {
+        Self { prefix, inner }
+    }
+}
+
+impl<V: LabelVisitor> LabelVisitor for __PrefixVisitor<V> {
+    type Output = V::Output;
+
+    fn write_int(self, x: i64) -> Self::Output {
+        self.write_str(itoa::Buffer::new().format(x))
+    }
+
+    fn write_float(self, x: f64) -> Self::Output {
+        self.write_str(format_float(x, &mut ryu::Buffer::new()))
+    }
+
+    fn write_str(self, x: &str) -> Self::Output {
+        self.inner.write_prefixed_str(self.prefix, x)
+    }
+}
+
+/// A [`LabelVisitor`] which renders the value into a string, the same way the text encoder does
+pub struct RenderValue;
+
+impl LabelVisitor for RenderValue {
+    type Output = String;
+
+    fn write_int(self, x: i64) -> String {
+        self.write_str(itoa::Buffer::new().format(x))
+    }
+
+    fn write_float(self, x: f64) -> String {
+        self.write_str(format_float(x, &mut ryu::Buffer::new()))
+    }
+
+    fn write_str(self, x: &str) -> String {
+        x.to_owned()
+    }
+
+    fn write_prefixed_str(self, prefix: &str, x: &str) -> String {
+        [prefix, x].concat()
+    }
+}
+
+/// Compares a label value with its rendered form
+pub(crate) struct ValueEq<'a>(pub(crate) &'a str);
+
+impl LabelVisitor for ValueEq<'_> {
+    type Output = bool;
+
+    fn write_int(self, x: i64) -> bool {
+        self.0 == itoa::Buffer::new().format(x)
+    }
+
+    fn write_float(self, x: f64) -> bool {
+        self.0 == format_float(x, &mut ryu::Buffer::new())
+    }
+
+    fn write_str(self, x: &str) -> bool {
+        self.0 == x
+    }
+
+    fn write_prefixed_str(self, prefix: &str, x: &str) -> bool {
+        self.0.strip_prefix(prefix) == Some(x)
+    }
+}
+
+/// Formats a float with the `+Inf`, `-Inf` and `NaN` spellings of the text format
+pub(crate) fn format_float(x: f64, buf: &mut ryu::Buffer) -> &str {
+    if x.is_infinite() {
+        if x.is_sign_positive() { "+Inf" } else { "-Inf" }
+    } else if x.is_nan() {
+        "NaN"
+    } else {
+        buf.format_finite(x)
+    }
+}
+
 /// A trait for visiting the value of a label
 pub trait LabelVisitor {
     /// Output of this visitor
@@ -108,6 +240,20 @@ pub trait LabelVisitor {
     fn write_float(self, x: f64) -> Self::Output;
     /// Write a string value to this visitor
     fn write_str(self, x: &str) -> Self::Output;
+
+    /// Write a string value made of `prefix` followed by `x`, such as a flattened enum variant.
+    ///
+    /// The default implementation concatenates the strings into a new allocation.
+    /// Visitors that write into a buffer should override this to write both parts directly.
+    fn write_prefixed_str(self, prefix: &str, x: &str) -> Self::Output
+    where
+        Self: Sized,
+    {
+        let mut value = String::with_capacity(prefix.len() + x.len());
+        value.push_str(prefix);
+        value.push_str(x);
+        self.write_str(&value)
+    }
 }
 
 /// A type that contains a label value
@@ -131,6 +277,17 @@ pub trait FixedCardinalityLabel: LabelValue + Copy {
     /// If the integer is outside the range of this set, the behaviour is not defined.
     /// It would most likely panic.
     fn decode(value: usize) -> Self;
+
+    /// Parse the label value from its rendered form. Returns `None` if no value renders as `value`.
+    ///
+    /// The default implementation compares against the rendered form of each value in turn.
+    /// The [`FixedCardinalityLabel`](macro@crate::FixedCardinalityLabel) derive uses the
+    /// generated [`FromStr`](core::str::FromStr) implementation instead, with `#[label(from_str)]`.
+    fn parse(value: &str) -> Option<Self> {
+        (0..Self::cardinality())
+            .map(Self::decode)
+            .find(|x| x.visit(ValueEq(value)))
+    }
 }
 
 /// `FixedCardinalitySet` is an immutable [`LabelSet`] that has a known fixed size.
@@ -177,6 +334,9 @@ pub trait DynamicLabelSet: LabelSet {
 /// * [`lasso::RodeoReader`] is an immutable label set that stores a `&str` into a larger `String` allocation. It has a `HashMap` to quickly find the index of the string
 /// * [`lasso::ThreadedRodeo`] is a mutable label set that works similarly to the `RodeoReader`.
 /// * [`indexmap::IndexSet`] is an immutable `HashSet` that stores an associated index position of the inserted elements.
+/// * [`BoundedLabelSet`](super::BoundedLabelSet) is a mutable label set that stops interning new values once it is full.
+/// * [`TopKLabelSet`](super::TopKLabelSet) is a mutable label set that only keeps the most frequent values.
+/// * `RedactedLabelSet` and `RedactedBuckets` encode a keyed hash of sensitive values, with the `sha2` feature.
 pub trait LabelSet {
     /// The label value this set can encode
     type Value<'a>: LabelValue;
@@ -192,6 +352,32 @@ pub trait LabelSet {
     /// If the integer is outside the range of this set, the behaviour is not defined.
     /// It would most likely panic.
     fn decode(&self, value: usize) -> Self::Value<'_>;
+
+    /// If the set has folded this value into another value, returns the encoding of that value.
+    ///
+    /// See [`MetricVec::rebalance_label_set`](crate::MetricVec::rebalance_label_set).
+    fn folded_into(&self, value: usize) -> Option<usize> {
+        let _ = value;
+        None
+    }
+
+    /// Whether the set has evicted this value, so the metrics recorded for it are no longer valid.
+    ///
+    /// See [`MetricVec::reset_label_set`](crate::MetricVec::reset_label_set).
+    fn evicted(&self, value: usize) -> bool {
+        let _ = value;
+        false
+    }
+
+    /// Encode the label value from its rendered form, without adding it to the set.
+    /// Returns `None` if the value is not in the set.
+    ///
+    /// Used by [`LabelGroupSet::try_parse`](super::LabelGroupSet::try_parse).
+    /// The default implementation never finds the value.
+    fn parse(&self, value: &str) -> Option<usize> {
+        let _ = value;
+        None
+    }
 }
 
 #[cfg(test)]
@@ -202,10 +388,19 @@ mod tests {
 
     use crate::CounterVec;
 
-    use super::StaticLabelSet;
+    use crate::{
+        label::{BoundedInt, Bucketed},
+        metric::{MetricFamilyEncoding, name::MetricName},
+        text::BufferedTextEncoder,
+    };
+
+    use super::{
+        FixedCardinalityLabel, LabelTestVisitor, LabelValue, LabelVisitor, RenderValue,
+        StaticLabelSet,
+    };
 
     #[derive(Clone, Copy, PartialEq, Debug, measured_derive::FixedCardinalityLabel)]
-    #[label(crate = crate)]
+    #[label(crate = crate, from_str)]
     #[label(singleton = "kind")]
     enum ErrorKind {
         User,
@@ -218,4 +413,149 @@ mod tests {
     struct Metrics {
         errors: CounterVec<StaticLabelSet<ErrorKind>>,
     }
+
+    #[derive(Clone, Copy, PartialEq, Debug, measured_derive::FixedCardinalityLabel)]
+    #[label(crate = crate, rename_all = "kebab-case", from_str, singleton = "error")]
+    enum RequestError {
+        BadRequest,
+        #[label(flatten)]
+        Upstream(ErrorKind),
+        #[label(ignore_fields)]
+        Unknown {
+            code: u16,
+        },
+        #[label(flatten, rename = "status")]
+        Status(BoundedInt<500, 503>),
+    }
+
+    #[test]
+    fn flattened_variants() {
+        assert_eq!(RequestError::cardinality(), 1 + 3 + 1 + 4);
+
+        let values: Vec<String> = (0..RequestError::cardinality())
+            .map(|i| {
+                let error = RequestError::decode(i);
+                assert_eq!(error.encode(), i);
+                error.visit(LabelTestVisitor)
+            })
+            .collect();
+        assert_eq!(
+            values,
+            [
+                "bad-request",
+                "upstream-user",
+                "upstream-internal",
+                "upstream-network",
+                "unknown",
+                "status-500",
+                "status-501",
+                "status-502",
+                "status-503",
+            ]
+        );
+
+        assert_eq!(RequestError::Unknown { code: 418 }.encode(), 4);
+        assert_eq!(RequestError::decode(4), RequestError::Unknown { code: 0 });
+    }
+
+    #[test]
+    fn prefixed_values() {
+        /// Records how the value was written
+        struct Parts;
+        impl LabelVisitor for Parts {
+            type Output = (String, String);
+            fn write_int(self, x: i64) -> Self::Output {
+                self.write_str(&x.to_string())
+            }
+            fn write_float(self, x: f64) -> Self::Output {
+                self.write_str(&x.to_string())
+            }
+            fn write_str(self, x: &str) -> Self::Output {
+                (String::new(), x.to_owned())
+            }
+            fn write_prefixed_str(self, prefix: &str, x: &str) -> Self::Output {
+                (prefix.to_owned(), x.to_owned())
+            }
+        }
+
+        let parts = |error: RequestError| {
+            let (prefix, x) = error.visit(Parts);
+            (prefix, x, error.visit(RenderValue))
+        };
+        assert_eq!(
+            parts(RequestError::Upstream(ErrorKind::Network)),
+            (
+                "upstream-".into(),
+                "network".into(),
+                "upstream-network".into()
+            )
+        );
+        assert_eq!(
+            parts(RequestError::Status(BoundedInt::new(501).unwrap())),
+            ("status-".into(), "501".into(), "status-501".into())
+        );
+        assert_eq!(
+            parts(RequestError::BadRequest),
+            (String::new(), "bad-request".into(), "bad-request".into())
+        );
+
+        let errors = CounterVec::with_label_set(StaticLabelSet::<RequestError>::new());
+        errors.inc(RequestError::Upstream(ErrorKind::User));
+        let mut enc = BufferedTextEncoder::new();
+        errors
+            .collect_family_into(MetricName::from_str("errors"), &mut enc)
+            .unwrap();
+        assert!(
+            std::str::from_utf8(&enc.finish())
+                .unwrap()
+                .contains("errors{error=\"upstream-user\"} 1\n")
+        );
+    }
+
+    #[test]
+    fn parse_values() {
+        for i in 0..RequestError::cardinality() {
+            let error = RequestError::decode(i);
+            let value = error.visit(LabelTestVisitor);
+            assert_eq!(value.parse(), Ok(error));
+        }
+
+        let err = RequestError::try_from("status-504").unwrap_err();
+        assert_eq!(err.value(), "status-504");
+        assert!("upstream-".parse::<RequestError>().is_err());
+        assert!("BadRequest".parse::<RequestError>().is_err());
+    }
+
+    #[derive(Clone, Copy, PartialEq, Debug, measured_derive::FixedCardinalityLabel)]
+    #[label(crate = crate)]
+    enum Region {
+        Eu,
+        Us,
+    }
+
+    #[derive(Clone, Copy, PartialEq, Debug, measured_derive::FixedCardinalityLabel)]
+    #[label(crate = crate, rename_all = "kebab-case", from_str)]
+    enum Route {
+        Local,
+        #[label(flatten)]
+        Remote(Region),
+        #[label(flatten)]
+        Retries(Bucketed<0, 9, 5>),
+    }
+
+    #[test]
+    fn parse_flattened_without_from_str() {
+        for i in 0..Route::cardinality() {
+            let route = Route::decode(i);
+            let value = route.visit(LabelTestVisitor);
+            assert_eq!(value.parse(), Ok(route));
+        }
+
+        assert_eq!("remote-us".parse(), Ok(Route::Remote(Region::Us)));
+        assert_eq!(
+            "retries-5..=9".parse(),
+            Ok(Route::Retries(Bucketed::new(7)))
+        );
+        assert!("remote-asia".parse::<Route>().is_err());
+    }
 }
@@ -18,10 +18,20 @@ use metric::{
     histogram::HistogramState,
 };
 
+pub mod compress;
+pub mod delta;
 #[cfg(any(doc, test))]
 pub mod docs;
+pub mod filter;
+pub mod grafana;
 pub mod label;
+pub mod lint;
 pub mod metric;
+pub mod negotiate;
+pub mod parse;
+pub mod relabel;
+pub mod schema;
+pub mod table;
 pub mod text;
 
 /// Reexport of lasso when feature is enabled
@@ -42,10 +52,18 @@ pub use lasso;
 ///     * `"Title Case"`
 ///     * `"Train-Case"`
 /// * `singleton = "..."` - This `FixedCardinalityLabel` on it's own represents a [`LabelGroup`]
+/// * `kind = Ident` - Generate a separate label enum with this name, instead of implementing the traits on this enum.
+///   This is needed if the enum is not `Copy`, such as an error type.
+/// * `from_str` - Also implement `FromStr` and `TryFrom<&str>`, parsing the label values back into the enum.
 ///
 /// # Variant attributes
 ///
 /// * `rename = "..."` - Rename this variant.
+/// * `flatten` - The variant has a single field which implements [`FixedCardinalityLabel`].
+///   The values are the variant name followed by the field value, eg `io_timed_out`.
+/// * `ignore_fields` - The fields of this variant are ignored, it's a single label value.
+///   Unless `kind` is set, the fields must implement `Default` so the variant can be decoded.
+/// * `other` - With `from_str`, any unknown value parses as this variant instead of returning an error.
 ///
 /// # Outputs
 ///
@@ -53,6 +71,10 @@ pub use lasso;
 /// * `impl LabelValue for T { ... }`
 /// * `impl LabelGroup for T { ... }`
 ///     - If `singleton` is specified
+/// * `enum Kind { ... }`, `impl From<T> for Kind { ... }` and `impl From<&T> for Kind { ... }`
+///     - If `kind` is specified. The traits above are implemented for `Kind` instead of `T`.
+/// * `impl FromStr for T { ... }` and `impl TryFrom<&str> for T { ... }`
+///     - If `from_str` is specified. Flattened variant types must also implement `FromStr`.
 ///
 /// # Example
 ///
@@ -142,6 +164,65 @@ pub use lasso;
 /// assert_eq!(StatusCode::ImATeapot.visit(LabelTestVisitor), "IM-A-TEAPOT");
 /// assert_eq!(StatusCode::InternalServerError.visit(LabelTestVisitor), "INTERNAL-SERVER-ERROR");
 /// ```
+///
+/// ## Error enums
+///
+/// ```
+/// use measured::{CounterVec, FixedCardinalityLabel, label::StaticLabelSet};
+///
+/// #[derive(FixedCardinalityLabel, Debug, Copy, Clone, PartialEq)]
+/// enum IoError {
+///     NotFound,
+///     TimedOut,
+/// }
+///
+/// #[derive(FixedCardinalityLabel)]
+/// #[label(kind = DbErrorKind, singleton = "error")]
+/// enum DbError {
+///     Timeout,
+///     #[label(flatten)]
+///     Io(IoError),
+///     #[label(ignore_fields)]
+///     Other(String),
+/// }
+///
+/// use measured::label::FixedCardinalityLabel as _;
+/// use measured::label::LabelValue as _;
+/// use measured::label::LabelTestVisitor;
+///
+/// assert_eq!(DbErrorKind::cardinality(), 4);
+///
+/// let kind = DbErrorKind::from(DbError::Io(IoError::TimedOut));
+/// assert_eq!(kind, DbErrorKind::Io(IoError::TimedOut));
+/// assert_eq!(kind.encode(), 2);
+/// assert_eq!(kind.visit(LabelTestVisitor), "io_timed_out");
+///
+/// let kind = DbErrorKind::from(DbError::Other("connection reset".to_owned()));
+/// assert_eq!(kind.visit(LabelTestVisitor), "other");
+///
+/// let errors = CounterVec::<StaticLabelSet<DbErrorKind>>::new();
+/// let err = DbError::Timeout;
+/// errors.inc(err.into());
+/// ```
+///
+/// ## Parsing
+///
+/// ```
+/// #[derive(measured::FixedCardinalityLabel)]
+/// #[derive(Debug, Copy, Clone, PartialEq)]
+/// #[label(from_str, rename_all = "kebab-case")]
+/// enum Method {
+///     Get,
+///     #[label(rename = "POST")]
+///     Post,
+///     #[label(other)]
+///     Unknown,
+/// }
+///
+/// assert_eq!("get".parse(), Ok(Method::Get));
+/// assert_eq!("POST".parse(), Ok(Method::Post));
+/// assert_eq!(Method::try_from("PATCH"), Ok(Method::Unknown));
+/// ```
 pub use measured_derive::FixedCardinalityLabel;
 
 pub use label::FixedCardinalityLabel;
@@ -163,12 +244,21 @@ pub use label::FixedCardinalityLabel;
 /// * `dynamic_with = Type` - The field corresponds to a [`DynamicLabelSet`](label::DynamicLabelSet)
 /// * `default` - The generated [`LabelGroupSet`](label::LabelGroupSet) can default this field.
 /// * `rename = "..."` - Rename this label.
+/// * `omit_none` - For `Option` fields, leave the label out when the value is `None`.
+///   Otherwise, `None` is written as an empty label value.
+/// * `flatten = Type` - The field is a nested [`LabelGroup`], and `Type` is its [`LabelGroupSet`](label::LabelGroupSet).
+///   The nested labels are written in place of the field. The nested set must be a
+///   [`FixedCardinalityGroupSet`](label::FixedCardinalityGroupSet).
+///
+/// `Option<T>` fields take an extra slot in the label set to represent `None`.
 ///
 /// # Outputs
 ///
 /// * `impl LabelGroup for T { ... }`
 /// * `struct TSet { ... }`
 /// * `impl LabelGroupSet for TSet { ... }`
+/// * `impl FixedCardinalityGroupSet for TSet { ... }`
+///     - only implemented if there are no `dynamic_with` fields.
 /// * `impl TSet { pub fn new(...) -> Self {} }`
 ///     - `new` contains args for all the non-default fields.
 /// * `impl Default for TSet { ... }`
@@ -223,6 +313,41 @@ pub use label::FixedCardinalityLabel;
 /// // the dynamic value `"conradludgate"` was inserted into the set
 /// assert_eq!(set.user_name.len(), 1);
 /// ```
+///
+/// ## Nested groups
+///
+/// ```
+/// use measured::{FixedCardinalityLabel, LabelGroup};
+/// use measured::label::LabelGroupSet as _;
+///
+/// #[derive(FixedCardinalityLabel, Copy, Clone)]
+/// enum Method {
+///     Get,
+///     Post,
+/// }
+///
+/// #[derive(LabelGroup)]
+/// #[label(set = RequestSet)]
+/// struct Request {
+///     method: Method,
+///     cached: bool,
+/// }
+///
+/// #[derive(LabelGroup)]
+/// #[label(set = ResponseSet)]
+/// struct Response {
+///     #[label(flatten = RequestSet, default)]
+///     request: Request,
+///     success: bool,
+/// }
+///
+/// let set = ResponseSet::new();
+/// assert_eq!(set.cardinality(), Some(8));
+///
+/// let mut names = vec![];
+/// set.visit_label_names(&mut |name| names.push(name.as_str().to_owned()));
+/// assert_eq!(names, ["method", "cached", "success"]);
+/// ```
 pub use measured_derive::LabelGroup;
 
 pub use label::group::LabelGroup;
@@ -242,6 +367,8 @@ pub use label::group::LabelGroup;
 ///
 /// * `namespace = "..."` - The field represents a nested group with the given namespace.
 /// * `flatten` - The field represents a nested group with no namespacing.
+/// * `labels = expr` - The [`LabelGroup`] to add to every sample of the nested group. See [`WithLabels`](metric::group::WithLabels).
+///   The expression is evaluated on each collection, and can refer to `self`. The labels are visited directly, so this does not allocate.
 /// * `init` - The expression needed to initialise the nested metric group.
 ///
 /// ## Metrics
@@ -0,0 +1,505 @@
+//! Validation of metric groups against the prometheus conventions
+//!
+//! A [`LintEncoder`] does not produce any output. Instead it inspects the metric families
+//! that a [`MetricGroup`] writes, and reports any [`Diagnostic`]s it finds.
+//! This is intended to be run from a unit test, to catch mistakes before they are deployed.
+//!
+//! ```
+//! use measured::{Counter, Gauge, MetricGroup, lint::{DiagnosticKind, lint}};
+//!
+//! #[derive(MetricGroup)]
+//! struct MyMetrics {
+//!     /// total requests served
+//!     requests: Counter,
+//!     /// number of connections currently open
+//!     open_connections: Gauge,
+//! }
+//!
+//! let metrics = MyMetrics {
+//!     requests: Counter::new(),
+//!     open_connections: Gauge::new(),
+//! };
+//!
+//! let diagnostics = lint(&metrics);
+//! assert_eq!(diagnostics.len(), 1);
+//! assert_eq!(diagnostics[0].family(), "requests");
+//! assert_eq!(diagnostics[0].kind(), &DiagnosticKind::CounterWithoutTotal);
+//! ```
+
+use std::{collections::HashSet, convert::Infallible};
+
+use crate::{
+    MetricGroup,
+    label::{LabelGroup, LabelGroupSet, LabelGroupVisitor, LabelName, LabelValue},
+    metric::{
+        MetricEncoding,
+        counter::CounterState,
+        gauge::{FloatGaugeState, GaugeState},
+        group::{DynMetricFamily, DynamicEncoding, DynamicFamilies, EncoderList, Encoding},
+        histogram::{HistogramState, Thresholds},
+        name::MetricNameEncoder,
+        timestamp::Timestamped,
+    },
+    text::MetricType,
+};
+
+/// Collect the metric group into a new [`LintEncoder`], returning all the diagnostics found.
+pub fn lint<G: MetricGroup<LintEncoder> + ?Sized>(group: &G) -> Vec<Diagnostic> {
+    let mut enc = LintEncoder::new();
+    let Ok(()) = group.collect_group_into(&mut enc);
+    enc.finish()
+}
+
+/// A problem found by the [`LintEncoder`]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Diagnostic {
+    family: String,
+    kind: DiagnosticKind,
+}
+
+impl Diagnostic {
+    /// The name of the metric family the problem was found in
+    pub fn family(&self) -> &str {
+        &self.family
+    }
+
+    /// The problem that was found
+    pub fn kind(&self) -> &DiagnosticKind {
+        &self.kind
+    }
+}
+
+impl core::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}: {}", self.family, self.kind)
+    }
+}
+
+/// The problems that the [`LintEncoder`] reports
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum DiagnosticKind {
+    /// The metric family was written more than once
+    DuplicateFamily,
+    /// The metric family name is not lowercase `snake_case`
+    NotSnakeCase,
+    /// A counter family name does not end in `_total`
+    CounterWithoutTotal,
+    /// A non-counter family name ends in a suffix reserved for counter or histogram series
+    ReservedSuffix(&'static str),
+    /// A label name is reserved for use by prometheus, such as `le`, `quantile` or a `__` prefix
+    ReservedLabel(String),
+    /// A label name appeared more than once in the same label group
+    DuplicateLabel(String),
+    /// The help text contains a newline or a backslash that is not escaped
+    UnescapedHelp,
+}
+
+impl core::fmt::Display for DiagnosticKind {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            DiagnosticKind::DuplicateFamily => f.write_str("metric family appeared more than once"),
+            DiagnosticKind::NotSnakeCase => f.write_str("metric name is not snake_case"),
+            DiagnosticKind::CounterWithoutTotal => f.write_str("counter name should end in _total"),
+            DiagnosticKind::ReservedSuffix(suffix) => {
+                write!(f, "non-counter metric name should not end in {suffix}")
+            }
+            DiagnosticKind::ReservedLabel(name) => write!(f, "label name {name} is reserved"),
+            DiagnosticKind::DuplicateLabel(name) => {
+                write!(f, "label name {name} appeared more than once")
+            }
+            DiagnosticKind::UnescapedHelp => {
+                f.write_str("help text contains an unescaped newline or backslash")
+            }
+        }
+    }
+}
+
+/// An [`Encoding`] which checks metric families against the prometheus naming conventions.
+///
+/// Label names are checked using [`LabelGroupSet::visit_label_names`] where possible,
+/// so most problems are found even if no values have been recorded yet. Label sets without
+/// a fixed cardinality are only checked once a value has been recorded.
+#[derive(Default)]
+pub struct LintEncoder {
+    diagnostics: Vec<Diagnostic>,
+    families: HashSet<String>,
+    current: String,
+    /// Whether the type of the current family has been written
+    typed: bool,
+    name_buf: Vec<u8>,
+    label_buf: Vec<String>,
+}
+
+impl LintEncoder {
+    /// Create a new lint encoder
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The diagnostics found so far
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// Take the diagnostics found, and reset the encoder for another collection.
+    pub fn finish(&mut self) -> Vec<Diagnostic> {
+        self.families.clear();
+        self.current.clear();
+        self.typed = false;
+        std::mem::take(&mut self.diagnostics)
+    }
+
+    /// Check the type of the metric family, as written by [`MetricEncoding::write_type`]
+    pub fn write_type(&mut self, name: &impl MetricNameEncoder, typ: MetricType) {
+        let family = self.encode_name(name);
+        // a family without help text starts at its type. A second type for the same name is a new family.
+        if family != self.current || self.typed {
+            self.start_family(family.clone());
+        }
+        self.typed = true;
+
+        if typ == MetricType::Counter {
+            if !family.ends_with("_total") {
+                self.report(&family, DiagnosticKind::CounterWithoutTotal);
+            }
+        } else {
+            for suffix in ["_total", "_count", "_sum", "_bucket"] {
+                if family.ends_with(suffix) {
+                    self.report(&family, DiagnosticKind::ReservedSuffix(suffix));
+                }
+            }
+        }
+    }
+
+    /// Check the label names used by the metric family
+    pub fn write_label_names(
+        &mut self,
+        name: &impl MetricNameEncoder,
+        labels: &impl LabelGroupSet,
+    ) {
+        let mut label_buf = std::mem::take(&mut self.label_buf);
+        labels.visit_label_names(&mut |name| label_buf.push(name.as_str().to_owned()));
+        self.check_labels(name, &mut label_buf);
+        self.label_buf = label_buf;
+    }
+
+    /// Check the label names of a single sample in the metric family
+    pub fn write_labels(&mut self, name: &impl MetricNameEncoder, labels: impl LabelGroup) {
+        struct Names<'a>(&'a mut Vec<String>);
+        impl LabelGroupVisitor for Names<'_> {
+            type Output = ();
+            fn write_value(&mut self, name: &LabelName, _x: &impl LabelValue) {
+                self.0.push(name.as_str().to_owned());
+            }
+        }
+
+        let mut label_buf = std::mem::take(&mut self.label_buf);
+        labels.visit_values(&mut Names(&mut label_buf));
+        self.check_labels(name, &mut label_buf);
+        self.label_buf = label_buf;
+    }
+
+    fn check_labels(&mut self, name: &impl MetricNameEncoder, labels: &mut Vec<String>) {
+        if labels.is_empty() {
+            return;
+        }
+
+        let family = self.encode_name(name);
+        for (i, label) in labels.iter().enumerate() {
+            if label == "le" || label == "quantile" || label.starts_with("__") {
+                self.report(&family, DiagnosticKind::ReservedLabel(label.clone()));
+            }
+            if labels[..i].contains(label) {
+                self.report(&family, DiagnosticKind::DuplicateLabel(label.clone()));
+            }
+        }
+        labels.clear();
+    }
+
+    fn start_family(&mut self, family: String) {
+        if !is_snake_case(&family) {
+            self.report(&family, DiagnosticKind::NotSnakeCase);
+        }
+        if !self.families.insert(family.clone()) {
+            self.report(&family, DiagnosticKind::DuplicateFamily);
+        }
+        self.current = family;
+        self.typed = false;
+    }
+
+    fn encode_name(&mut self, name: &impl MetricNameEncoder) -> String {
+        self.name_buf.clear();
+        // writing into a vec cannot fail
+        let _ = name.encode_utf8(&mut self.name_buf);
+        String::from_utf8_lossy(&self.name_buf).into_owned()
+    }
+
+    fn report(&mut self, family: &str, kind: DiagnosticKind) {
+        let diagnostic = Diagnostic {
+            family: family.to_owned(),
+            kind,
+        };
+        if !self.diagnostics.contains(&diagnostic) {
+            self.diagnostics.push(diagnostic);
+        }
+    }
+}
+
+impl Encoding for LintEncoder {
+    type Err = Infallible;
+
+    const MIME_TYPE: &'static str = "text/plain";
+
+    fn write_help(&mut self, name: impl MetricNameEncoder, help: &str) -> Result<(), Infallible> {
+        let family = self.encode_name(&name);
+        if !is_escaped(help) {
+            self.report(&family, DiagnosticKind::UnescapedHelp);
+        }
+        self.start_family(family);
+        Ok(())
+    }
+}
+
+impl<L: EncoderList> DynamicEncoding<L> for LintEncoder
+where
+    L::Family: DynMetricFamily<Self>,
+{
+    fn collect_dynamic(&mut self, families: DynamicFamilies<'_, L>) -> Result<(), Self::Err> {
+        families.collect_into(self)
+    }
+}
+
+fn is_snake_case(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name
+            .bytes()
+            .all(|b| matches!(b, b'a'..=b'z' | b'0'..=b'9' | b'_' | b':'))
+}
+
+/// The text format only allows `\\` and `\n` escapes in help text
+fn is_escaped(help: &str) -> bool {
+    let mut bytes = help.bytes();
+    while let Some(b) = bytes.next() {
+        match b {
+            b'\n' => return false,
+            b'\\' if !matches!(bytes.next(), Some(b'\\' | b'n')) => return false,
+            _ => {}
+        }
+    }
+    true
+}
+
+macro_rules! lint_metric {
+    ($state:ty, $metadata:ty, $typ:expr) => {
+        impl MetricEncoding<LintEncoder> for $state {
+            fn write_type(
+                name: impl MetricNameEncoder,
+                enc: &mut LintEncoder,
+            ) -> Result<(), Infallible> {
+                enc.write_type(&name, $typ);
+                Ok(())
+            }
+            fn write_metadata(
+                name: impl MetricNameEncoder,
+                _metadata: &$metadata,
+                labels: &impl LabelGroupSet,
+                enc: &mut LintEncoder,
+            ) -> Result<(), Infallible> {
+                enc.write_label_names(&name, labels);
+                Ok(())
+            }
+            fn collect_into(
+                &self,
+                _metadata: &$metadata,
+                labels: impl LabelGroup,
+                name: impl MetricNameEncoder,
+                enc: &mut LintEncoder,
+            ) -> Result<(), Infallible> {
+                enc.write_labels(&name, labels);
+                Ok(())
+            }
+        }
+    };
+}
+
+lint_metric!(CounterState, (), MetricType::Counter);
+lint_metric!(GaugeState, (), MetricType::Gauge);
+lint_metric!(FloatGaugeState, (), MetricType::Gauge);
+
+impl<const N: usize> MetricEncoding<LintEncoder> for HistogramState<N> {
+    fn write_type(name: impl MetricNameEncoder, enc: &mut LintEncoder) -> Result<(), Infallible> {
+        enc.write_type(&name, MetricType::Histogram);
+        Ok(())
+    }
+    fn write_metadata(
+        name: impl MetricNameEncoder,
+        _metadata: &Thresholds<N>,
+        labels: &impl LabelGroupSet,
+        enc: &mut LintEncoder,
+    ) -> Result<(), Infallible> {
+        enc.write_label_names(&name, labels);
+        Ok(())
+    }
+    fn collect_into(
+        &self,
+        _metadata: &Thresholds<N>,
+        labels: impl LabelGroup,
+        name: impl MetricNameEncoder,
+        enc: &mut LintEncoder,
+    ) -> Result<(), Infallible> {
+        enc.write_labels(&name, labels);
+        Ok(())
+    }
+}
+
+impl<M: MetricEncoding<LintEncoder>> MetricEncoding<LintEncoder> for Timestamped<M> {
+    fn write_type(name: impl MetricNameEncoder, enc: &mut LintEncoder) -> Result<(), Infallible> {
+        M::write_type(name, enc)
+    }
+    fn write_metadata(
+        name: impl MetricNameEncoder,
+        metadata: &M::Metadata,
+        labels: &impl LabelGroupSet,
+        enc: &mut LintEncoder,
+    ) -> Result<(), Infallible> {
+        M::write_metadata(name, metadata, labels, enc)
+    }
+    fn collect_into(
+        &self,
+        metadata: &M::Metadata,
+        labels: impl LabelGroup,
+        name: impl MetricNameEncoder,
+        enc: &mut LintEncoder,
+    ) -> Result<(), Infallible> {
+        self.metric().collect_into(metadata, labels, name, enc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use measured_derive::{FixedCardinalityLabel, LabelGroup, MetricGroup};
+
+    use super::{DiagnosticKind, lint};
+    use crate::{
+        Counter, CounterVec, Gauge, HistogramVec,
+        label::ComposedGroup,
+        metric::{group::Encoding, histogram::Thresholds, name::MetricName},
+    };
+
+    #[derive(FixedCardinalityLabel, Clone, Copy)]
+    #[label(crate = crate)]
+    enum Method {
+        Get,
+    }
+
+    #[derive(LabelGroup)]
+    #[label(crate = crate, set = BadLabelSet)]
+    struct BadLabels {
+        le: Method,
+        method: Method,
+    }
+
+    #[derive(MetricGroup)]
+    #[metric(crate = crate)]
+    struct Inner {
+        /// requests served
+        requests: CounterVec<ComposedGroup<BadLabelSet, BadLabelSet>>,
+    }
+
+    #[derive(MetricGroup)]
+    #[metric(crate = crate)]
+    struct Bad {
+        #[metric(namespace = "http")]
+        a: Inner,
+        #[metric(namespace = "http")]
+        b: Inner,
+        /// latency of requests \ in seconds
+        latency_seconds_count: HistogramVec<BadLabelSet, 4>,
+        /// connections
+        #[metric(rename = "openConnections")]
+        open_connections: Gauge,
+    }
+
+    #[test]
+    fn diagnostics() {
+        let inner = || Inner {
+            requests: CounterVec::with_label_set(ComposedGroup(
+                BadLabelSet::new(),
+                BadLabelSet::new(),
+            )),
+        };
+        let bad = Bad {
+            a: inner(),
+            b: inner(),
+            latency_seconds_count: HistogramVec::with_label_set_and_metadata(
+                BadLabelSet::new(),
+                Thresholds::exponential_buckets(0.1, 2.0),
+            ),
+            open_connections: Gauge::new(),
+        };
+
+        let diagnostics: Vec<String> = lint(&bad).iter().map(|d| d.to_string()).collect();
+        assert_eq!(
+            diagnostics,
+            [
+                "http_requests: counter name should end in _total",
+                "http_requests: label name le is reserved",
+                "http_requests: label name le appeared more than once",
+                "http_requests: label name method appeared more than once",
+                "http_requests: metric family appeared more than once",
+                "latency_seconds_count: help text contains an unescaped newline or backslash",
+                "latency_seconds_count: non-counter metric name should not end in _count",
+                "latency_seconds_count: label name le is reserved",
+                "openConnections: metric name is not snake_case",
+            ]
+        );
+
+        let mut enc = super::LintEncoder::new();
+        enc.write_help(MetricName::from_str("ok_total"), "escaped \\\\ and \\n")
+            .unwrap();
+        enc.write_help(MetricName::from_str("multiline"), "line 1\nline 2")
+            .unwrap();
+        assert_eq!(
+            enc.finish()
+                .iter()
+                .map(|d| (d.family(), d.kind().clone()))
+                .collect::<Vec<_>>(),
+            [("multiline", DiagnosticKind::UnescapedHelp)]
+        );
+    }
+
+    #[derive(MetricGroup)]
+    #[metric(crate = crate)]
+    struct UndocumentedInner {
+        requests_total: Counter,
+    }
+
+    #[derive(MetricGroup)]
+    #[metric(crate = crate)]
+    struct Undocumented {
+        #[metric(namespace = "http")]
+        a: UndocumentedInner,
+        #[metric(namespace = "http")]
+        b: UndocumentedInner,
+        errors_total: Counter,
+    }
+
+    #[test]
+    fn duplicate_without_help() {
+        let inner = || UndocumentedInner {
+            requests_total: Counter::new(),
+        };
+        let metrics = Undocumented {
+            a: inner(),
+            b: inner(),
+            errors_total: Counter::new(),
+        };
+
+        let diagnostics: Vec<String> = lint(&metrics).iter().map(|d| d.to_string()).collect();
+        assert_eq!(
+            diagnostics,
+            ["http_requests_total: metric family appeared more than once"]
+        );
+    }
+}
@@ -4,7 +4,7 @@ use core::hash::Hash;
 use std::{
     hash::BuildHasher,
     ops::{Deref, DerefMut},
-    sync::OnceLock,
+    sync::{Arc, OnceLock},
 };
 
 use crate::label::{LabelGroup, LabelGroupSet, NoLabels};
@@ -18,6 +18,7 @@ pub mod group;
 pub mod histogram;
 pub mod name;
 mod sparse;
+pub mod timestamp;
 
 /// Defines a metric
 pub trait MetricType: Default {
@@ -25,6 +26,14 @@ pub trait MetricType: Default {
     type Metadata: Sized;
 }
 
+/// A metric whose values can be summed together, such as a counter or a histogram.
+///
+/// See [`MetricVec::rebalance_label_set`] and [`AggregatingRelabelEncoder`](crate::relabel::AggregatingRelabelEncoder).
+pub trait MergeMetric: MetricType {
+    /// Add the values of `other` into this metric
+    fn merge(&mut self, other: &Self);
+}
+
 /// A shared ref to an individual metric value.
 ///
 /// As the name implies, it might hold a lock (only applicable to sparse metric vecs, but this is not guaranteed behaviour)
@@ -115,10 +124,14 @@ pub struct Metric<M: MetricType> {
 /// on each shard. This is not considered stable.
 ///
 /// This is currently the default if the label set cardinality is > 1024, or unbounded.
+///
+/// Sparse metric vecs are collected in an unspecified order, which can change between collections.
+/// Use [`MetricVec::with_sorted_output`] if a deterministic order is needed.
 pub struct MetricVec<M: MetricType, L: LabelGroupSet> {
     metrics: VecInner<L::Unique, M>,
     metadata: M::Metadata,
     label_set: L,
+    sorted: bool,
 }
 
 enum VecInner<U: Hash + Eq, M: MetricType> {
@@ -278,6 +291,7 @@ impl<M: MetricType, L: LabelGroupSet> MetricVec<M, L> {
             metrics,
             metadata,
             label_set,
+            sorted: false,
         }
     }
 
@@ -291,6 +305,7 @@ impl<M: MetricType, L: LabelGroupSet> MetricVec<M, L> {
             metrics: VecInner::Dense(new_dense(c)),
             metadata,
             label_set,
+            sorted: false,
         }
     }
 
@@ -300,9 +315,28 @@ impl<M: MetricType, L: LabelGroupSet> MetricVec<M, L> {
             metrics: VecInner::Sparse(sparse::ShardedMap::new()),
             metadata,
             label_set,
+            sorted: false,
         }
     }
 
+    /// Collect the metrics ordered by their label values.
+    ///
+    /// Label values are compared in the order of the label names. Integers and floats are compared numerically,
+    /// and strings are compared lexicographically.
+    /// This is useful for golden-file tests, or for diffing the output of multiple scrapes.
+    ///
+    /// # Note
+    /// This only affects 'sparse' metric vecs. 'dense' metric vecs are always collected in
+    /// the order of their [`LabelGroupSet::encode_dense`] indices.
+    ///
+    /// While collecting a sorted sparse metric vec, all shards are read-locked at once,
+    /// so new label groups cannot be inserted until the collection completes.
+    #[must_use]
+    pub fn with_sorted_output(mut self) -> Self {
+        self.sorted = true;
+        self
+    }
+
     /// For dense metric-vecs, sometimes you might want to initialise all metric values to their initial state.
     /// This is intended to run once at startup.
     ///
@@ -400,12 +434,59 @@ impl<M: MetricType, L: LabelGroupSet> MetricVec<M, L> {
     pub fn get_label_set(&self) -> &L {
         &self.label_set
     }
+
+    /// Modify the label set, and then remove the metrics of any label groups that it evicted.
+    ///
+    /// The metrics of evicted values would not be valid for the modified label set, or would be attributed to
+    /// the next value interned in their place. The metrics of all other label groups are kept.
+    /// This is useful for periodically clearing a [`BoundedLabelSet`](crate::label::BoundedLabelSet).
+    ///
+    /// Label sets with a fixed cardinality never evict their values, so this only affects sparse vecs.
+    pub fn reset_label_set(&mut self, f: impl FnOnce(&mut L)) {
+        f(&mut self.label_set);
+        if let VecInner::Sparse(metrics) = &mut self.metrics {
+            let label_set = &self.label_set;
+            metrics.retain(|id| !label_set.evicted(id));
+        }
+    }
+
+    /// Modify the label set, and then merge the metrics of any label groups that it folded
+    /// into other label groups.
+    ///
+    /// Unlike [`MetricVec::reset_label_set`], the totals across all the metrics are preserved.
+    /// This is useful for periodically rebalancing a [`TopKLabelSet`](crate::label::TopKLabelSet).
+    ///
+    /// Label sets with a fixed cardinality never fold their values, so this only affects sparse vecs.
+    pub fn rebalance_label_set(&mut self, f: impl FnOnce(&mut L))
+    where
+        M: MergeMetric,
+    {
+        f(&mut self.label_set);
+        if let VecInner::Sparse(metrics) = &mut self.metrics {
+            let label_set = &self.label_set;
+            metrics.fold(|id| label_set.folded_into(id), M::merge);
+        }
+    }
 }
 
 /// Defines the encoding of a metric
 pub trait MetricEncoding<T: Encoding>: MetricType {
     /// Write the type information for this metric into the encoder
     fn write_type(name: impl MetricNameEncoder, enc: &mut T) -> Result<(), T::Err>;
+    /// Describe the metadata and label set of this metric family to the encoder.
+    ///
+    /// [`Metric`] and [`MetricVec`] call this after [`MetricEncoding::write_type`], before any values are collected,
+    /// so encoders that only inspect the structure of the metrics do not need any values to be observed.
+    /// Most encoders do not need this, and the default implementation does nothing.
+    fn write_metadata(
+        name: impl MetricNameEncoder,
+        metadata: &Self::Metadata,
+        labels: &impl LabelGroupSet,
+        enc: &mut T,
+    ) -> Result<(), T::Err> {
+        let _ = (name, metadata, labels, enc);
+        Ok(())
+    }
     /// Sample this metric into the encoder
     fn collect_into(
         &self,
@@ -431,10 +512,17 @@ impl<M: MetricFamilyEncoding<T>, T: Encoding> MetricFamilyEncoding<T> for Option
     }
 }
 
+impl<M: MetricFamilyEncoding<T>, T: Encoding> MetricFamilyEncoding<T> for Arc<M> {
+    fn collect_family_into(&self, name: impl MetricNameEncoder, enc: &mut T) -> Result<(), T::Err> {
+        M::collect_family_into(self, name, enc)
+    }
+}
+
 impl<M: MetricEncoding<T>, T: Encoding> MetricFamilyEncoding<T> for Metric<M> {
     /// Collect this metric value into the given encoder with the given metric name
     fn collect_family_into(&self, name: impl MetricNameEncoder, enc: &mut T) -> Result<(), T::Err> {
         M::write_type(&name, enc)?;
+        M::write_metadata(&name, &self.metadata, &NoLabels, enc)?;
         self.metric
             .collect_into(&self.metadata, NoLabels, name, enc)
     }
@@ -445,6 +533,7 @@ impl<M: MetricEncoding<T>, L: LabelGroupSet, T: Encoding> MetricFamilyEncoding<T
 {
     fn collect_family_into(&self, name: impl MetricNameEncoder, enc: &mut T) -> Result<(), T::Err> {
         M::write_type(&name, enc)?;
+        M::write_metadata(&name, &self.metadata, &self.label_set, enc)?;
         match &self.metrics {
             VecInner::Dense(m) => {
                 for (index, value) in m.iter().enumerate() {
@@ -458,6 +547,27 @@ impl<M: MetricEncoding<T>, L: LabelGroupSet, T: Encoding> MetricFamilyEncoding<T
                     }
                 }
             }
+            VecInner::Sparse(m) if self.sorted => {
+                let shards: Vec<_> = m.shards.iter().map(|shard| shard.read()).collect();
+
+                // render all the sort keys into a single buffer, to avoid allocating per metric.
+                let mut keys = Vec::new();
+                let mut entries = Vec::with_capacity(shards.iter().map(|s| s.len()).sum());
+                for shard in &shards {
+                    for (k, v) in shard.iter() {
+                        let start = keys.len();
+                        self.label_set
+                            .decode(k)
+                            .visit_values(&mut sparse::SortKey(&mut keys));
+                        entries.push((start, keys.len(), k, v));
+                    }
+                }
+                entries.sort_unstable_by(|a, b| keys[a.0..a.1].cmp(&keys[b.0..b.1]));
+
+                for (_, _, k, v) in entries {
+                    v.collect_into(&self.metadata, self.label_set.decode(k), &name, enc)?;
+                }
+            }
             VecInner::Sparse(m) => {
                 for shard in &m.shards {
                     for (k, v) in shard.read().iter() {
@@ -499,6 +609,8 @@ impl<L: Hash + Eq> Eq for LabelIdInner<L> {}
 
 #[cfg(test)]
 mod tests {
+    use std::sync::atomic::Ordering;
+
     use crate::{CounterVec, FixedCardinalityLabel, LabelGroup};
 
     #[derive(Clone, Copy, PartialEq, Debug, LabelGroup)]
@@ -607,4 +719,161 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn reset_evicted() {
+        use crate::label::BoundedLabelSet;
+
+        #[derive(Clone, Copy, PartialEq, Debug, LabelGroup)]
+        #[label(crate = crate, set = UserErrorsSet)]
+        struct UserError<'a> {
+            kind: ErrorKind,
+            #[label(dynamic_with = BoundedLabelSet)]
+            user: &'a str,
+        }
+
+        let mut errors = CounterVec::with_label_set(UserErrorsSet {
+            kind: Default::default(),
+            user: BoundedLabelSet::new(2),
+        });
+        let alice = UserError {
+            kind: ErrorKind::User,
+            user: "alice",
+        };
+        let bob = UserError {
+            kind: ErrorKind::Network,
+            user: "bob",
+        };
+        errors.inc(alice);
+        errors.inc(bob);
+        errors.reset_label_set(|set| assert_eq!(set.user.evict_unused(), 0));
+        assert_eq!(errors.get_cardinality().0, 2);
+
+        errors.inc(bob);
+        errors.reset_label_set(|set| assert_eq!(set.user.evict_unused(), 1));
+        assert_eq!(errors.get_cardinality().0, 1);
+        let bob = errors.with_labels(bob);
+        assert_eq!(errors.get_metric(bob).count.load(Ordering::Relaxed), 2);
+
+        // carol takes the slot of alice, but not her metrics
+        let carol = errors.with_labels(UserError {
+            kind: ErrorKind::User,
+            user: "carol",
+        });
+        assert_eq!(errors.get_metric(carol).count.load(Ordering::Relaxed), 0);
+    }
+
+    #[cfg(feature = "lasso")]
+    #[test]
+    fn sparse_sorted_output() {
+        use fake::{Fake, faker::name::raw::Name, locales::EN};
+
+        use crate::{
+            GaugeVec,
+            metric::{MetricFamilyEncoding, name::MetricName},
+            parse::parse_text,
+            text::BufferedTextEncoder,
+        };
+
+        let set = GaugeVec::sparse_with_label_set(ErrorsSet2::default()).with_sorted_output();
+
+        let names = (0..64).map(|_| Name(EN).fake()).collect::<Vec<String>>();
+        for kind in [ErrorKind::User, ErrorKind::Internal, ErrorKind::Network] {
+            for name in &names {
+                set.inc(Error2 { kind, user: name });
+            }
+        }
+
+        let mut enc = BufferedTextEncoder::new();
+        set.collect_family_into(MetricName::from_str("errors"), &mut enc)
+            .unwrap();
+        let output = enc.finish();
+
+        let families = parse_text(std::str::from_utf8(&output).unwrap()).unwrap();
+        let labels: Vec<_> = families[0]
+            .samples
+            .iter()
+            .map(|s| (s.label("kind").unwrap(), s.label("user").unwrap()))
+            .collect();
+        assert_eq!(labels.len(), set.get_cardinality().0);
+        assert!(labels.is_sorted());
+
+        // the output is stable between collections
+        set.collect_family_into(MetricName::from_str("errors"), &mut enc)
+            .unwrap();
+        assert_eq!(enc.finish(), output);
+    }
+
+    #[test]
+    fn sparse_sorted_numbers() {
+        use crate::{
+            label::{BoundedInt, LabelGroupVisitor, LabelName, LabelValue, LabelVisitor},
+            metric::{MetricFamilyEncoding, name::MetricName},
+            text::BufferedTextEncoder,
+        };
+
+        #[derive(Clone, Copy, PartialEq, Debug, LabelGroup)]
+        #[label(crate = crate, set = CodeSet)]
+        struct Code {
+            code: BoundedInt<-20, 20>,
+        }
+
+        let set = CounterVec::sparse_with_label_set(CodeSet::default()).with_sorted_output();
+        for code in [10, 9, -10, 1, -1, 0, 20] {
+            set.inc(Code {
+                code: BoundedInt::new(code).unwrap(),
+            });
+        }
+
+        let mut enc = BufferedTextEncoder::new();
+        set.collect_family_into(MetricName::from_str("codes"), &mut enc)
+            .unwrap();
+        assert_eq!(
+            enc.finish(),
+            r#"# TYPE codes counter
+codes{code="-10"} 1
+codes{code="-1"} 1
+codes{code="0"} 1
+codes{code="1"} 1
+codes{code="9"} 1
+codes{code="10"} 1
+codes{code="20"} 1
+"#
+        );
+
+        struct Float(f64);
+        impl LabelValue for Float {
+            fn visit<V: LabelVisitor>(&self, v: V) -> V::Output {
+                v.write_float(self.0)
+            }
+        }
+        impl crate::label::LabelGroup for Float {
+            fn visit_values(&self, v: &mut impl LabelGroupVisitor) {
+                v.write_value(LabelName::from_str("x"), self);
+            }
+        }
+
+        let floats = [
+            f64::NEG_INFINITY,
+            -2.5,
+            -0.0,
+            0.5,
+            9.0,
+            10.0,
+            f64::INFINITY,
+            f64::NAN,
+        ];
+        let keys: Vec<Vec<u8>> = floats
+            .iter()
+            .map(|&x| {
+                let mut key = vec![];
+                crate::label::LabelGroup::visit_values(
+                    &Float(x),
+                    &mut super::sparse::SortKey(&mut key),
+                );
+                key
+            })
+            .collect();
+        assert!(keys.is_sorted());
+    }
 }
@@ -1,12 +1,13 @@
 //! All things counters. See [`Counter`]
 
-use core::sync::atomic::AtomicU64;
+use core::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
 
 use crate::{Counter, CounterVec, LabelGroup, label::LabelGroupSet};
 
 use super::{
-    MetricEncoding, MetricLockGuard, MetricMut, MetricType, group::Encoding,
-    name::MetricNameEncoder,
+    MergeMetric, MetricEncoding, MetricLockGuard, MetricMut, MetricType, group::Encoding,
+    name::MetricNameEncoder, timestamp::Timestamped,
 };
 
 #[derive(Default)]
@@ -101,6 +102,12 @@ impl MetricType for CounterState {
     type Metadata = ();
 }
 
+impl MergeMetric for CounterState {
+    fn merge(&mut self, other: &Self) {
+        *self.count.get_mut() += other.count.load(Ordering::Relaxed);
+    }
+}
+
 pub fn write_counter<Enc: Encoding>(
     enc: &mut Enc,
     name: impl MetricNameEncoder,
@@ -115,3 +122,23 @@ where
     }
     .collect_into(&(), labels, name, enc)
 }
+
+/// Like [`write_counter`], but with an explicit sample timestamp. See [`Timestamped`]
+pub fn write_counter_at<Enc: Encoding>(
+    enc: &mut Enc,
+    name: impl MetricNameEncoder,
+    labels: impl LabelGroup,
+    value: u64,
+    time: SystemTime,
+) -> Result<(), Enc::Err>
+where
+    Timestamped<CounterState>: MetricEncoding<Enc>,
+{
+    Timestamped::new(
+        CounterState {
+            count: AtomicU64::new(value),
+        },
+        time,
+    )
+    .collect_into(&(), labels, name, enc)
+}
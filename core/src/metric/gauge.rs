@@ -1,12 +1,13 @@
 //! All things gauges. See [`Gauge`]
 
 use core::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::time::SystemTime;
 
 use crate::{FloatGauge, FloatGaugeVec, Gauge, GaugeVec, LabelGroup, label::LabelGroupSet};
 
 use super::{
     MetricEncoding, MetricLockGuard, MetricMut, MetricType, group::Encoding,
-    name::MetricNameEncoder,
+    name::MetricNameEncoder, timestamp::Timestamped,
 };
 
 #[derive(Default)]
@@ -161,6 +162,26 @@ where
     .collect_into(&(), labels, name, enc)
 }
 
+/// Like [`write_gauge`], but with an explicit sample timestamp. See [`Timestamped`]
+pub fn write_gauge_at<Enc: Encoding>(
+    enc: &mut Enc,
+    name: impl MetricNameEncoder,
+    labels: impl LabelGroup,
+    value: i64,
+    time: SystemTime,
+) -> Result<(), Enc::Err>
+where
+    Timestamped<GaugeState>: MetricEncoding<Enc>,
+{
+    Timestamped::new(
+        GaugeState {
+            count: AtomicI64::new(value),
+        },
+        time,
+    )
+    .collect_into(&(), labels, name, enc)
+}
+
 #[derive(Default)]
 /// The internal state that is used by [`FloatGauge`] and [`FloatGaugeVec`]
 pub struct FloatGaugeState {
@@ -373,3 +394,23 @@ where
     }
     .collect_into(&(), labels, name, enc)
 }
+
+/// Like [`write_float_gauge`], but with an explicit sample timestamp. See [`Timestamped`]
+pub fn write_float_gauge_at<Enc: Encoding>(
+    enc: &mut Enc,
+    name: impl MetricNameEncoder,
+    labels: impl LabelGroup,
+    value: f64,
+    time: SystemTime,
+) -> Result<(), Enc::Err>
+where
+    Timestamped<FloatGaugeState>: MetricEncoding<Enc>,
+{
+    Timestamped::new(
+        FloatGaugeState {
+            count: AtomicF64::new(value),
+        },
+        time,
+    )
+    .collect_into(&(), labels, name, enc)
+}
@@ -1,12 +1,17 @@
 //! Groups of metrics
 
-use std::sync::Arc;
+use std::{collections::BTreeMap, sync::Arc};
+
+use parking_lot::RwLock;
 
 pub use crate::label::ComposedGroup;
+use crate::label::{
+    LabelGroup, LabelGroupSet, LabelGroupVisitor, LabelName, LabelValue, LabelVisitor,
+};
 
 use super::{
-    MetricEncoding,
-    name::{MetricNameEncoder, WithNamespace},
+    MetricEncoding, MetricFamilyEncoding,
+    name::{MetricName, MetricNameBuf, MetricNameEncoder, Namespace, WithNamespace},
 };
 
 /// Values that prometheus supports in the text format
@@ -75,12 +80,493 @@ where
 {
     fn collect_group_into(&self, enc: &mut E) -> Result<(), E::Err> {
         self.inner.collect_group_into(&mut WithNamespace {
-            namespace: self.namespace,
+            namespace: self.namespace.clone(),
             inner: enc,
         })
     }
 }
 
+/// A metric group with some constant labels added to every sample it writes.
+///
+/// The labels are written before the labels of each metric. They are also included in the label set passed to
+/// [`MetricEncoding::write_metadata`], so analysis encoders see them too.
+///
+/// Note that the text format does not allow a metric family to be written more than once,
+/// so two instances of a group should not be collected into the same encoder with different labels,
+/// unless they are also in different namespaces.
+///
+/// ```
+/// use measured::{Counter, FixedCardinalityLabel, LabelGroup, MetricGroup};
+/// use measured::metric::group::WithLabels;
+/// use measured::text::BufferedTextEncoder;
+///
+/// #[derive(MetricGroup, Default)]
+/// struct DbMetrics {
+///     /// number of queries executed
+///     queries_total: Counter,
+/// }
+///
+/// #[derive(FixedCardinalityLabel, Clone, Copy)]
+/// enum Database {
+///     Primary,
+///     Replica,
+/// }
+///
+/// #[derive(LabelGroup)]
+/// #[label(set = DbLabelsSet)]
+/// struct DbLabels {
+///     db: Database,
+/// }
+///
+/// let metrics = WithLabels::new(DbLabels { db: Database::Primary }, DbMetrics::default());
+/// metrics.inner().queries_total.inc();
+///
+/// let mut enc = BufferedTextEncoder::new();
+/// metrics.collect_group_into(&mut enc).unwrap();
+/// assert_eq!(
+///     enc.finish(),
+///     concat!(
+///         "# HELP queries_total number of queries executed\n",
+///         "# TYPE queries_total counter\n",
+///         "queries_total{db=\"primary\"} 1\n",
+///     )
+/// );
+/// ```
+pub struct WithLabels<L, T> {
+    pub(crate) labels: L,
+    pub(crate) inner: T,
+}
+
+impl<L, T> WithLabels<L, T> {
+    /// Add the labels to every sample of the inner value.
+    pub fn new(labels: L, inner: T) -> Self {
+        Self { labels, inner }
+    }
+
+    /// Get the labels
+    pub fn labels(&self) -> &L {
+        &self.labels
+    }
+
+    /// Get the inner value
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<L, G, E> MetricGroup<E> for WithLabels<L, G>
+where
+    L: LabelGroup,
+    G: for<'a> MetricGroup<WithLabels<ConstLabels<'a>, &'a mut E>>,
+    E: Encoding,
+{
+    fn collect_group_into(&self, enc: &mut E) -> Result<(), E::Err> {
+        self.inner.collect_group_into(&mut WithLabels {
+            labels: ConstLabels(&self.labels),
+            inner: enc,
+        })
+    }
+}
+
+/// The labels of a [`WithLabels`], with the type of the [`LabelGroup`] erased.
+///
+/// This lets nested groups be collected without depending on the type of the labels.
+#[derive(Clone, Copy)]
+pub struct ConstLabels<'a>(&'a dyn ErasedLabelGroup);
+
+impl LabelGroup for ConstLabels<'_> {
+    fn visit_values(&self, v: &mut impl LabelGroupVisitor) {
+        struct Visit<'a, V>(&'a mut V);
+        impl<V: LabelGroupVisitor> ErasedVisitor for Visit<'_, V> {
+            fn write_value(&mut self, name: &LabelName, x: ErasedValue<'_>) {
+                self.0.write_value(name, &x);
+            }
+        }
+
+        self.0.visit_erased(&mut Visit(v));
+    }
+}
+
+trait ErasedLabelGroup {
+    fn visit_erased(&self, v: &mut dyn ErasedVisitor);
+}
+
+trait ErasedVisitor {
+    fn write_value(&mut self, name: &LabelName, x: ErasedValue<'_>);
+}
+
+#[derive(Clone, Copy)]
+enum ErasedValue<'a> {
+    Int(i64),
+    Float(f64),
+    Str(&'a str),
+    Prefixed(&'a str, &'a str),
+}
+
+impl LabelValue for ErasedValue<'_> {
+    fn visit<V: LabelVisitor>(&self, v: V) -> V::Output {
+        match *self {
+            ErasedValue::Int(x) => v.write_int(x),
+            ErasedValue::Float(x) => v.write_float(x),
+            ErasedValue::Str(x) => v.write_str(x),
+            ErasedValue::Prefixed(prefix, x) => v.write_prefixed_str(prefix, x),
+        }
+    }
+}
+
+impl<L: LabelGroup> ErasedLabelGroup for L {
+    fn visit_erased(&self, v: &mut dyn ErasedVisitor) {
+        struct Erase<'a>(&'a mut dyn ErasedVisitor);
+        impl LabelGroupVisitor for Erase<'_> {
+            type Output = ();
+            fn write_value(&mut self, name: &LabelName, x: &impl LabelValue) {
+                x.visit(EraseValue(self.0, name));
+            }
+        }
+
+        struct EraseValue<'a>(&'a mut dyn ErasedVisitor, &'a LabelName);
+        impl LabelVisitor for EraseValue<'_> {
+            type Output = ();
+            fn write_int(self, x: i64) {
+                self.0.write_value(self.1, ErasedValue::Int(x));
+            }
+            fn write_float(self, x: f64) {
+                self.0.write_value(self.1, ErasedValue::Float(x));
+            }
+            fn write_str(self, x: &str) {
+                self.0.write_value(self.1, ErasedValue::Str(x));
+            }
+            fn write_prefixed_str(self, prefix: &str, x: &str) {
+                self.0.write_value(self.1, ErasedValue::Prefixed(prefix, x));
+            }
+        }
+
+        self.visit_values(&mut Erase(v));
+    }
+}
+
+/// The [`LabelGroupSet`] of a metric, with the constant labels of a [`WithLabels`] prepended
+struct ConstLabelSet<'a, S> {
+    labels: ConstLabels<'a>,
+    set: &'a S,
+}
+
+impl<'a, S: LabelGroupSet> LabelGroupSet for ConstLabelSet<'a, S> {
+    type Group<'b> = ComposedGroup<ConstLabels<'a>, S::Group<'b>>;
+
+    fn cardinality(&self) -> Option<usize> {
+        self.set.cardinality()
+    }
+
+    fn encode_dense(&self, value: Self::Unique) -> Option<usize> {
+        self.set.encode_dense(value)
+    }
+
+    fn decode_dense(&self, value: usize) -> Self::Group<'_> {
+        ComposedGroup(self.labels, self.set.decode_dense(value))
+    }
+
+    type Unique = S::Unique;
+
+    fn encode(&self, value: Self::Group<'_>) -> Option<Self::Unique> {
+        self.set.encode(value.1)
+    }
+
+    fn decode(&self, value: &Self::Unique) -> Self::Group<'_> {
+        ComposedGroup(self.labels, self.set.decode(value))
+    }
+
+    fn folded_into(&self, value: &Self::Unique) -> Option<Self::Unique> {
+        self.set.folded_into(value)
+    }
+
+    fn evicted(&self, value: &Self::Unique) -> bool {
+        self.set.evicted(value)
+    }
+
+    fn visit_label_names(&self, v: &mut impl FnMut(&LabelName)) {
+        struct Names<'a, F>(&'a mut F);
+        impl<F: FnMut(&LabelName)> LabelGroupVisitor for Names<'_, F> {
+            type Output = ();
+            fn write_value(&mut self, name: &LabelName, _x: &impl LabelValue) {
+                (self.0)(name);
+            }
+        }
+
+        self.labels.visit_values(&mut Names(v));
+        self.set.visit_label_names(v);
+    }
+}
+
+/// A metric group with metric families that are only known at runtime, such as from a config file.
+///
+/// The families are type-erased, so they can only be collected into the encoders listed in `L`,
+/// eg `(BufferedTextEncoder,)`, or the text and protobuf encoders of a [`Negotiator`](crate::negotiate::Negotiator).
+/// Encoders that wrap a listed encoder, such as [`WithNamespace`] or [`FilterEncoder`](crate::filter::FilterEncoder),
+/// are also supported. See [`DynamicEncoding`]. Constant labels added by [`WithLabels`] are not supported.
+///
+/// Families can be inserted and removed while the group is shared, eg to reload the config.
+/// Families are collected in order of their names.
+///
+/// ```
+/// use std::sync::Arc;
+///
+/// use measured::{Counter, MetricGroup};
+/// use measured::lint::LintEncoder;
+/// use measured::metric::group::DynamicMetricGroup;
+/// use measured::metric::name::MetricNameBuf;
+/// use measured::text::BufferedTextEncoder;
+///
+/// let group = DynamicMetricGroup::<(BufferedTextEncoder, LintEncoder)>::new();
+///
+/// let calls = Arc::new(Counter::new());
+/// group.insert(MetricNameBuf::try_from("plugin_calls").unwrap(), calls.clone());
+/// calls.inc();
+///
+/// let mut enc = BufferedTextEncoder::new();
+/// group.collect_group_into(&mut enc).unwrap();
+/// assert_eq!(
+///     enc.finish(),
+///     concat!("# TYPE plugin_calls counter\n", "plugin_calls 1\n"),
+/// );
+///
+/// let mut lint = LintEncoder::new();
+/// group.collect_group_into(&mut lint).unwrap();
+/// ```
+pub struct DynamicMetricGroup<L: EncoderList> {
+    families: RwLock<BTreeMap<MetricNameBuf, Box<L::Family>>>,
+}
+
+/// A list of [`Encoding`]s that the families of a [`DynamicMetricGroup`] can be collected into.
+///
+/// Implemented for tuples of up to 4 encoders.
+pub trait EncoderList {
+    /// The type-erased metric family
+    #[doc(hidden)]
+    type Family: ?Sized + Send + Sync;
+}
+
+/// A metric family that can be collected into all the encoders of `L`
+pub trait DynamicFamily<L: EncoderList> {
+    #[doc(hidden)]
+    fn __into_dyn(self) -> Box<L::Family>;
+}
+
+/// An object safe [`MetricFamilyEncoding`]
+#[doc(hidden)]
+pub trait DynMetricFamily<E: Encoding> {
+    fn collect_family_into(&self, name: &DynamicName<'_>, enc: &mut E) -> Result<(), E::Err>;
+}
+
+impl<M: MetricFamilyEncoding<E>, E: Encoding> DynMetricFamily<E> for M {
+    fn collect_family_into(&self, name: &DynamicName<'_>, enc: &mut E) -> Result<(), E::Err> {
+        M::collect_family_into(self, name, enc)
+    }
+}
+
+macro_rules! encoder_list {
+    ($family:ident: $($E:ident),*) => {
+        #[doc(hidden)]
+        pub trait $family<$($E: Encoding),*>: $(DynMetricFamily<$E> +)* Send + Sync {}
+
+        impl<T, $($E: Encoding),*> $family<$($E),*> for T where T: $(DynMetricFamily<$E> +)* Send + Sync {}
+
+        impl<$($E: Encoding),*> EncoderList for ($($E,)*) {
+            type Family = dyn $family<$($E),*>;
+        }
+
+        impl<M, $($E: Encoding),*> DynamicFamily<($($E,)*)> for M
+        where
+            M: $(MetricFamilyEncoding<$E> +)* Send + Sync + 'static,
+        {
+            fn __into_dyn(self) -> Box<dyn $family<$($E),*>> {
+                Box::new(self)
+            }
+        }
+    };
+}
+
+encoder_list!(DynFamily1: A);
+encoder_list!(DynFamily2: A, B);
+encoder_list!(DynFamily3: A, B, C);
+encoder_list!(DynFamily4: A, B, C, D);
+
+/// The name of a family in a [`DynamicMetricGroup`], in the namespaces of the encoders it was collected through
+#[doc(hidden)]
+pub struct DynamicName<'a> {
+    namespace: Option<&'a Namespace>,
+    name: &'a MetricName,
+}
+
+impl MetricNameEncoder for DynamicName<'_> {
+    fn encode_utf8(&self, b: &mut impl std::io::Write) -> std::io::Result<()> {
+        match self.namespace {
+            Some(namespace) => WithNamespace {
+                namespace: namespace.clone(),
+                inner: self.name,
+            }
+            .encode_utf8(b),
+            None => self.name.encode_utf8(b),
+        }
+    }
+    fn encode_len(&self) -> usize {
+        self.namespace.map_or(0, |ns| ns.as_str().len() + 1) + self.name.encode_len()
+    }
+}
+
+/// An [`Encoding`] that the families of a [`DynamicMetricGroup<L>`] can be collected into.
+///
+/// Encoders in this crate implement this when they are listed in `L`, by calling [`DynamicFamilies::collect_into`].
+/// Encoders that wrap another encoder, such as [`WithNamespace`] and [`FilterEncoder`](crate::filter::FilterEncoder),
+/// pass the families on to the inner encoder instead.
+pub trait DynamicEncoding<L: EncoderList>: Encoding {
+    /// Collect the families of a [`DynamicMetricGroup`] into this encoder
+    fn collect_dynamic(&mut self, families: DynamicFamilies<'_, L>) -> Result<(), Self::Err>;
+}
+
+/// The families of a [`DynamicMetricGroup`] being collected. See [`DynamicEncoding`].
+pub struct DynamicFamilies<'a, L: EncoderList> {
+    families: &'a BTreeMap<MetricNameBuf, Box<L::Family>>,
+    namespace: Option<Namespace>,
+    filters: Vec<&'a crate::filter::NameFilter>,
+}
+
+impl<'a, L: EncoderList> DynamicFamilies<'a, L> {
+    /// Collect the families into an encoder listed in `L`
+    pub fn collect_into<E: Encoding>(self, enc: &mut E) -> Result<(), E::Err>
+    where
+        L::Family: DynMetricFamily<E>,
+    {
+        let mut full_name = Vec::new();
+        for (name, family) in self.families {
+            let name = DynamicName {
+                namespace: self.namespace.as_ref(),
+                name,
+            };
+            if !self.filters.is_empty() {
+                full_name.clear();
+                name.encode_utf8(&mut full_name)
+                    .expect("writing to a vec is infallible");
+                let full_name = std::str::from_utf8(&full_name).expect("metric names are utf8");
+                if !self.filters.iter().all(|filter| filter.matches(full_name)) {
+                    continue;
+                }
+            }
+            family.collect_family_into(&name, enc)?;
+        }
+        Ok(())
+    }
+
+    /// Only collect the families whose full names match the filter
+    pub(crate) fn filtered<'b>(
+        self,
+        filter: &'b crate::filter::NameFilter,
+    ) -> DynamicFamilies<'b, L>
+    where
+        'a: 'b,
+    {
+        let mut filters = self.filters;
+        filters.push(filter);
+        DynamicFamilies {
+            families: self.families,
+            namespace: self.namespace,
+            filters,
+        }
+    }
+
+    /// Collect the families under this namespace, outside of any existing namespace
+    fn in_namespace(self, namespace: &Namespace) -> Self {
+        let namespace = match self.namespace {
+            None => namespace.clone(),
+            Some(inner) => {
+                Namespace::Shared(format!("{}_{}", namespace.as_str(), inner.as_str()).into())
+            }
+        };
+        Self {
+            namespace: Some(namespace),
+            ..self
+        }
+    }
+}
+
+impl<L: EncoderList, E: DynamicEncoding<L>> DynamicEncoding<L> for WithNamespace<&mut E> {
+    fn collect_dynamic(&mut self, families: DynamicFamilies<'_, L>) -> Result<(), Self::Err> {
+        self.inner
+            .collect_dynamic(families.in_namespace(&self.namespace))
+    }
+}
+
+impl<L: EncoderList, E: DynamicEncoding<L>> DynamicEncoding<L> for &mut E {
+    fn collect_dynamic(&mut self, families: DynamicFamilies<'_, L>) -> Result<(), Self::Err> {
+        E::collect_dynamic(self, families)
+    }
+}
+
+impl<L: EncoderList> DynamicMetricGroup<L> {
+    /// Create an empty group
+    pub fn new() -> Self {
+        Self {
+            families: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    /// Add a metric family to the group. Returns `true` if it replaced a family with the same name.
+    pub fn insert(&self, name: MetricNameBuf, family: impl DynamicFamily<L>) -> bool {
+        self.families
+            .write()
+            .insert(name, family.__into_dyn())
+            .is_some()
+    }
+
+    /// Remove a metric family from the group. Returns `true` if the family was present.
+    pub fn remove(&self, name: &str) -> bool {
+        self.families.write().remove(name).is_some()
+    }
+
+    /// Remove the metric families that don't match the predicate, eg the families removed from a config file
+    pub fn retain(&self, mut f: impl FnMut(&MetricName) -> bool) {
+        self.families.write().retain(|name, _| f(name));
+    }
+
+    /// Whether the group contains a metric family with this name
+    pub fn contains(&self, name: &str) -> bool {
+        self.families.read().contains_key(name)
+    }
+
+    /// The names of the metric families in this group
+    pub fn names(&self) -> Vec<MetricNameBuf> {
+        self.families.read().keys().cloned().collect()
+    }
+
+    /// The number of metric families in this group
+    pub fn len(&self) -> usize {
+        self.families.read().len()
+    }
+
+    /// Whether the group has no metric families
+    pub fn is_empty(&self) -> bool {
+        self.families.read().is_empty()
+    }
+}
+
+impl<L: EncoderList> Default for DynamicMetricGroup<L> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<L: EncoderList, E: DynamicEncoding<L>> MetricGroup<E> for DynamicMetricGroup<L> {
+    fn collect_group_into(&self, enc: &mut E) -> Result<(), E::Err> {
+        // new families wait for the collection to finish
+        let families = self.families.read();
+        enc.collect_dynamic(DynamicFamilies {
+            families: &families,
+            namespace: None,
+            filters: Vec::new(),
+        })
+    }
+}
+
 impl<M: MetricGroup<T>, T: Encoding> MetricGroup<T> for Option<M> {
     fn collect_group_into(&self, enc: &mut T) -> Result<(), T::Err> {
         if let Some(this) = self {
@@ -104,7 +590,7 @@ impl<E: Encoding> Encoding for WithNamespace<E> {
     fn write_help(&mut self, name: impl MetricNameEncoder, help: &str) -> Result<(), Self::Err> {
         self.inner.write_help(
             WithNamespace {
-                namespace: self.namespace,
+                namespace: self.namespace.clone(),
                 inner: name,
             },
             help,
@@ -116,9 +602,25 @@ impl<M: MetricEncoding<E>, E: Encoding> MetricEncoding<WithNamespace<E>> for M {
     fn write_type(name: impl MetricNameEncoder, enc: &mut WithNamespace<E>) -> Result<(), E::Err> {
         M::write_type(
             WithNamespace {
-                namespace: enc.namespace,
+                namespace: enc.namespace.clone(),
+                inner: name,
+            },
+            &mut enc.inner,
+        )
+    }
+    fn write_metadata(
+        name: impl MetricNameEncoder,
+        metadata: &M::Metadata,
+        labels: &impl LabelGroupSet,
+        enc: &mut WithNamespace<E>,
+    ) -> Result<(), E::Err> {
+        M::write_metadata(
+            WithNamespace {
+                namespace: enc.namespace.clone(),
                 inner: name,
             },
+            metadata,
+            labels,
             &mut enc.inner,
         )
     }
@@ -133,7 +635,7 @@ impl<M: MetricEncoding<E>, E: Encoding> MetricEncoding<WithNamespace<E>> for M {
             metadata,
             labels,
             WithNamespace {
-                namespace: enc.namespace,
+                namespace: enc.namespace.clone(),
                 inner: name,
             },
             &mut enc.inner,
@@ -141,10 +643,63 @@ impl<M: MetricEncoding<E>, E: Encoding> MetricEncoding<WithNamespace<E>> for M {
     }
 }
 
+impl<E: Encoding> Encoding for WithLabels<ConstLabels<'_>, E> {
+    type Err = E::Err;
+
+    const MIME_TYPE: &'static str = E::MIME_TYPE;
+
+    fn write_help(&mut self, name: impl MetricNameEncoder, help: &str) -> Result<(), Self::Err> {
+        self.inner.write_help(name, help)
+    }
+}
+
+impl<M: MetricEncoding<E>, E: Encoding> MetricEncoding<WithLabels<ConstLabels<'_>, E>> for M {
+    fn write_type(
+        name: impl MetricNameEncoder,
+        enc: &mut WithLabels<ConstLabels<'_>, E>,
+    ) -> Result<(), E::Err> {
+        M::write_type(name, &mut enc.inner)
+    }
+    fn write_metadata(
+        name: impl MetricNameEncoder,
+        metadata: &M::Metadata,
+        labels: &impl LabelGroupSet,
+        enc: &mut WithLabels<ConstLabels<'_>, E>,
+    ) -> Result<(), E::Err> {
+        let labels = ConstLabelSet {
+            labels: enc.labels,
+            set: labels,
+        };
+        M::write_metadata(name, metadata, &labels, &mut enc.inner)
+    }
+    fn collect_into(
+        &self,
+        metadata: &M::Metadata,
+        labels: impl crate::label::LabelGroup,
+        name: impl MetricNameEncoder,
+        enc: &mut WithLabels<ConstLabels<'_>, E>,
+    ) -> Result<(), E::Err> {
+        self.collect_into(
+            metadata,
+            ComposedGroup(enc.labels, labels),
+            name,
+            &mut enc.inner,
+        )
+    }
+}
+
 impl<'a, M: MetricEncoding<E>, E: Encoding> MetricEncoding<&'a mut E> for M {
     fn write_type(name: impl MetricNameEncoder, enc: &mut &'a mut E) -> Result<(), E::Err> {
         M::write_type(name, *enc)
     }
+    fn write_metadata(
+        name: impl MetricNameEncoder,
+        metadata: &M::Metadata,
+        labels: &impl LabelGroupSet,
+        enc: &mut &'a mut E,
+    ) -> Result<(), E::Err> {
+        M::write_metadata(name, metadata, labels, *enc)
+    }
     fn collect_into(
         &self,
         metadata: &M::Metadata,
@@ -163,7 +718,7 @@ mod tests {
     use prometheus_client::encoding::EncodeLabelValue;
 
     use crate::{
-        Counter, CounterVec, Gauge, Histogram, metric::histogram::Thresholds,
+        Counter, CounterVec, Gauge, Histogram, metric::histogram::Thresholds, schema::schema,
         text::BufferedTextEncoder,
     };
 
@@ -286,6 +841,154 @@ http_request_errors{kind="network",route="/api/v1/products"} 0
 http_request_errors{kind="network",route="/api/v1/products/:id"} 0
 http_request_errors{kind="network",route="/api/v1/products/:id/owner"} 0
 http_request_errors{kind="network",route="/api/v1/products/:id/purchase"} 0
+"#
+        );
+    }
+
+    #[derive(Clone, Copy, FixedCardinalityLabel)]
+    #[label(crate = crate)]
+    enum Database {
+        Users,
+        Orders,
+    }
+
+    #[derive(LabelGroup)]
+    #[label(crate = crate, set = DbLabelsSet)]
+    struct DbLabels {
+        db: Database,
+    }
+
+    #[derive(LabelGroup)]
+    #[label(crate = crate, set = QueryLabelsSet)]
+    struct QueryLabels {
+        kind: ErrorKind,
+    }
+
+    #[derive(MetricGroup)]
+    #[metric(crate = crate)]
+    struct DbMetrics {
+        /// query errors
+        errors: CounterVec<QueryLabelsSet>,
+    }
+
+    #[derive(MetricGroup)]
+    #[metric(crate = crate)]
+    struct ServiceMetrics {
+        #[metric(namespace = "primary", labels = DbLabels { db: Database::Users })]
+        primary: DbMetrics,
+
+        #[metric(labels = DbLabels { db: Database::Orders })]
+        replica: DbMetrics,
+    }
+
+    #[test]
+    fn const_labels() {
+        let metrics = ServiceMetrics {
+            primary: DbMetrics {
+                errors: CounterVec::with_label_set(QueryLabelsSet::new()),
+            },
+            replica: DbMetrics {
+                errors: CounterVec::with_label_set(QueryLabelsSet::new()),
+            },
+        };
+        metrics.primary.errors.inc(QueryLabels {
+            kind: ErrorKind::User,
+        });
+        metrics.replica.errors.inc(QueryLabels {
+            kind: ErrorKind::Network,
+        });
+
+        let mut text_encoder = BufferedTextEncoder::new();
+        metrics.collect_group_into(&mut text_encoder).unwrap();
+        assert_eq!(
+            text_encoder.finish(),
+            r#"# HELP primary_errors query errors
+# TYPE primary_errors counter
+primary_errors{db="users",kind="user"} 1
+
+# HELP errors query errors
+# TYPE errors counter
+errors{db="orders",kind="network"} 1
+"#
+        );
+
+        let schema = schema(&metrics);
+        let family = schema.family("primary_errors").unwrap();
+        assert_eq!(family.labels(), ["db", "kind"]);
+        assert_eq!(family.label_values("db").unwrap(), ["users"]);
+    }
+
+    #[test]
+    fn dynamic_group() {
+        use std::sync::Arc;
+
+        use super::DynamicMetricGroup;
+        use crate::{
+            filter::{FilterEncoder, NameFilter},
+            lint::LintEncoder,
+            metric::name::{MetricNameBuf, WithNamespace},
+            negotiate::{BufferedEncoding, Negotiator},
+        };
+
+        let group = Arc::new(DynamicMetricGroup::<(BufferedTextEncoder, LintEncoder)>::new());
+        let calls = Arc::new(Counter::new());
+        let errors = Arc::new(CounterVec::with_label_set(QueryLabelsSet::new()));
+        assert!(!group.insert(MetricNameBuf::try_from("errors").unwrap(), errors.clone()));
+        assert!(!group.insert(MetricNameBuf::try_from("calls").unwrap(), calls.clone()));
+        assert!(group.insert(MetricNameBuf::try_from("calls").unwrap(), calls.clone()));
+        assert_eq!(group.len(), 2);
+
+        calls.inc();
+        errors.inc(QueryLabels {
+            kind: ErrorKind::Internal,
+        });
+
+        let ns = MetricNameBuf::try_from(String::from("plugin_auth")).unwrap();
+        let metrics = WithNamespace::new("app", WithNamespace::new_owned(ns, group.clone()));
+
+        let mut negotiator = Negotiator::new().with_encoder(BufferedTextEncoder::new());
+        let text = negotiator.encode(None, &metrics).unwrap();
+        assert_eq!(
+            text.body,
+            r#"# TYPE app_plugin_auth_calls counter
+app_plugin_auth_calls 1
+
+# TYPE app_plugin_auth_errors counter
+app_plugin_auth_errors{kind="internal"} 1
+"#
+        );
+
+        let mut filtered = FilterEncoder::new(
+            BufferedTextEncoder::new(),
+            NameFilter::new().with_name("app_plugin_auth_errors"),
+        );
+        metrics.collect_group_into(&mut filtered).unwrap();
+        assert_eq!(
+            filtered.finish().unwrap(),
+            r#"# TYPE app_plugin_auth_errors counter
+app_plugin_auth_errors{kind="internal"} 1
+"#
+        );
+
+        let mut lint = LintEncoder::new();
+        metrics.collect_group_into(&mut lint).unwrap();
+        let diagnostics = lint.finish();
+        assert!(
+            diagnostics
+                .iter()
+                .any(|d| d.family() == "app_plugin_auth_calls")
+        );
+
+        // reload through the shared group
+        assert!(group.remove("errors"));
+        assert!(!group.remove("errors"));
+        assert_eq!(group.names(), [MetricNameBuf::try_from("calls").unwrap()]);
+
+        let text = negotiator.encode(None, &metrics).unwrap();
+        assert_eq!(
+            text.body,
+            r#"# TYPE app_plugin_auth_calls counter
+app_plugin_auth_calls 1
 "#
         );
     }
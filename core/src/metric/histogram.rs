@@ -7,7 +7,7 @@ use std::{
 
 use parking_lot::RwLock;
 
-use super::{MetricLockGuard, MetricMut, MetricType, gauge::AtomicF64};
+use super::{MergeMetric, MetricLockGuard, MetricMut, MetricType, gauge::AtomicF64};
 use crate::{Histogram, HistogramVec, label::LabelGroupSet};
 
 /// The inner state of a histogram.
@@ -48,7 +48,11 @@ impl<const N: usize> HistogramStateInner<N> {
         self.sum.set_mut(v + x);
     }
 
-    pub(crate) fn sample(&mut self) -> ([u64; N], u64, f64) {
+    /// Take a snapshot of the histogram.
+    ///
+    /// Returns the (non-cumulative) count of each bucket, the count of observations
+    /// greater than all thresholds, and the accumulated sum.
+    pub fn sample(&mut self) -> ([u64; N], u64, f64) {
         let mut output = [0; N];
         #[allow(clippy::needless_range_loop)]
         for i in 0..N {
@@ -89,7 +93,22 @@ impl<const N: usize> MetricType for HistogramState<N> {
     type Metadata = Thresholds<N>;
 }
 
+impl<const N: usize> MergeMetric for HistogramState<N> {
+    fn merge(&mut self, other: &Self) {
+        let (buckets, inf, sum) = other.inner.write().sample();
+
+        let inner = self.inner.get_mut();
+        for (b, x) in inner.buckets.iter_mut().zip(buckets) {
+            *b.get_mut() += x;
+        }
+        *inner.inf.get_mut() += inf;
+        let x = inner.sum.get_ex();
+        inner.sum.set_mut(x + sum);
+    }
+}
+
 /// `Thresholds` defines the size of buckets used in a [`Histogram`]
+#[derive(Clone)]
 pub struct Thresholds<const N: usize> {
     le: [f64; N],
 }
@@ -1,6 +1,6 @@
 //! Metric names and name encodings
 
-use std::io::Write;
+use std::{io::Write, sync::Arc};
 
 /// `MetricName` represents a type that can be encoded into the name of a metric when collected.
 pub trait MetricNameEncoder {
@@ -123,10 +123,7 @@ impl MetricName {
     /// Add a namespace prefix to this metric name.
     #[must_use]
     pub const fn in_namespace(&self, ns: &'static str) -> WithNamespace<&'_ Self> {
-        WithNamespace {
-            namespace: MetricName::from_str(ns),
-            inner: self,
-        }
+        WithNamespace::new(ns, self)
     }
 
     /// Adds a semantic suffix to this metric name.
@@ -148,6 +145,104 @@ impl MetricNameEncoder for MetricName {
     }
 }
 
+/// An owned, validated metric name. See [`MetricName`].
+///
+/// ```
+/// use measured::metric::name::{MetricNameBuf, MetricNameEncoder};
+///
+/// let name = MetricNameBuf::try_from("plugin_calls".to_owned()).unwrap();
+/// assert_eq!(name.encode_len(), 12);
+///
+/// assert!(MetricNameBuf::try_from("plugin calls").is_err());
+/// ```
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MetricNameBuf(Box<str>);
+
+impl MetricNameBuf {
+    /// Borrow the metric name
+    pub fn as_metric_name(&self) -> &MetricName {
+        // SAFETY: `MetricName` is transparent over `str`, and the string was validated on construction.
+        unsafe { &*(std::ptr::from_ref::<str>(&self.0) as *const MetricName) }
+    }
+
+    /// Get the metric name as a string
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl core::ops::Deref for MetricNameBuf {
+    type Target = MetricName;
+
+    fn deref(&self) -> &MetricName {
+        self.as_metric_name()
+    }
+}
+
+impl AsRef<MetricName> for MetricNameBuf {
+    fn as_ref(&self) -> &MetricName {
+        self.as_metric_name()
+    }
+}
+
+impl core::borrow::Borrow<str> for MetricNameBuf {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&MetricName> for MetricNameBuf {
+    fn from(value: &MetricName) -> Self {
+        Self(value.0.into())
+    }
+}
+
+impl TryFrom<String> for MetricNameBuf {
+    type Error = InvalidMetricName;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        try_assert_metric_name(&value)?;
+        Ok(Self(value.into_boxed_str()))
+    }
+}
+
+impl TryFrom<&str> for MetricNameBuf {
+    type Error = InvalidMetricName;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        MetricName::try_from_str(value).map(Self::from)
+    }
+}
+
+impl core::str::FromStr for MetricNameBuf {
+    type Err = InvalidMetricName;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::try_from(s)
+    }
+}
+
+impl core::fmt::Debug for MetricNameBuf {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Debug::fmt(&*self.0, f)
+    }
+}
+
+impl core::fmt::Display for MetricNameBuf {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl MetricNameEncoder for MetricNameBuf {
+    fn encode_utf8(&self, b: &mut impl Write) -> std::io::Result<()> {
+        b.write_all(self.0.as_bytes())
+    }
+    fn encode_len(&self) -> usize {
+        self.0.len()
+    }
+}
+
 /// `Suffix` defines semantic suffixes as suggested by Prometheus
 ///
 /// Included suffixes:
@@ -174,10 +269,27 @@ impl<T: MetricNameEncoder + ?Sized> MetricNameEncoder for &T {
 
 /// See [`MetricName::in_namespace`]
 pub struct WithNamespace<T: ?Sized> {
-    pub(crate) namespace: &'static MetricName,
+    pub(crate) namespace: Namespace,
     pub(crate) inner: T,
 }
 
+/// The namespace of a [`WithNamespace`]. Cheap to clone.
+#[derive(Clone)]
+pub(crate) enum Namespace {
+    Static(&'static MetricName),
+    /// Validated as a [`MetricName`] on construction
+    Shared(Arc<str>),
+}
+
+impl Namespace {
+    pub(crate) fn as_str(&self) -> &str {
+        match self {
+            Namespace::Static(ns) => &ns.0,
+            Namespace::Shared(ns) => ns,
+        }
+    }
+}
+
 impl<T> WithNamespace<T> {
     /// Create a new namespaced value.
     ///
@@ -185,7 +297,26 @@ impl<T> WithNamespace<T> {
     /// Will panic if the `ns` string contains invalid metric name characters
     pub const fn new(ns: &'static str, inner: T) -> Self {
         Self {
-            namespace: MetricName::from_str(ns),
+            namespace: Namespace::Static(MetricName::from_str(ns)),
+            inner,
+        }
+    }
+
+    /// Create a new namespaced value, with a namespace only known at runtime.
+    ///
+    /// ```
+    /// use measured::metric::name::{MetricName, MetricNameBuf, MetricNameEncoder, WithNamespace};
+    ///
+    /// let ns = MetricNameBuf::try_from("plugin_auth").unwrap();
+    /// let name = WithNamespace::new_owned(ns, MetricName::from_str("calls"));
+    ///
+    /// let mut buf = vec![];
+    /// name.encode_utf8(&mut buf).unwrap();
+    /// assert_eq!(buf, b"plugin_auth_calls");
+    /// ```
+    pub fn new_owned(ns: MetricNameBuf, inner: T) -> Self {
+        Self {
+            namespace: Namespace::Shared(ns.0.into()),
             inner,
         }
     }
@@ -201,12 +332,12 @@ impl<T> WithNamespace<T> {
 
 impl<T: MetricNameEncoder + ?Sized> MetricNameEncoder for WithNamespace<T> {
     fn encode_utf8(&self, b: &mut impl Write) -> std::io::Result<()> {
-        b.write_all(self.namespace.0.as_bytes())?;
+        b.write_all(self.namespace.as_str().as_bytes())?;
         b.write_all(b"_")?;
         self.inner.encode_utf8(b)
     }
     fn encode_len(&self) -> usize {
-        self.namespace.0.len() + 1 + self.inner.encode_len()
+        self.namespace.as_str().len() + 1 + self.inner.encode_len()
     }
 }
 
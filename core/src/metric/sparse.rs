@@ -7,6 +7,8 @@ use std::{
     sync::OnceLock,
 };
 
+use crate::label::{LabelGroupVisitor, LabelName, LabelValue, LabelVisitor};
+
 use super::{LabelIdInner, MetricType};
 
 pub(super) struct ShardedMap<K, V> {
@@ -108,6 +110,36 @@ impl<M: MetricType, U: Hash + Eq + Copy> ShardedMap<U, M> {
         v
     }
 
+    /// Removes the metrics whose labels are no longer valid.
+    pub(super) fn retain(&mut self, f: impl Fn(&U) -> bool) {
+        for shard in &mut self.shards {
+            shard.get_mut().retain(|(k, _)| f(k));
+        }
+    }
+
+    /// Removes the metrics whose labels were folded into other labels, and merges them into those metrics.
+    pub(super) fn fold(
+        &mut self,
+        folded_into: impl Fn(&U) -> Option<U>,
+        merge: impl Fn(&mut M, &M),
+    ) {
+        let mut folded = Vec::new();
+        for shard in &mut self.shards {
+            shard.get_mut().retain(|(k, v)| match folded_into(k) {
+                Some(into) => {
+                    folded.push((into, std::mem::take(v)));
+                    false
+                }
+                None => true,
+            });
+        }
+
+        for (id, metric) in folded {
+            let hash = self.hasher.hash_one(id);
+            merge(self.get_metric_mut(LabelIdInner { id, hash }), &metric);
+        }
+    }
+
     pub(super) fn get_cardinality(&self) -> usize {
         self.shards
             .iter()
@@ -115,3 +147,55 @@ impl<M: MetricType, U: Hash + Eq + Copy> ShardedMap<U, M> {
             .sum::<usize>()
     }
 }
+
+/// Renders the values of a label group into a sort key.
+///
+/// Each value is tagged with its type, so that integers and floats compare numerically
+/// and strings compare lexicographically. Values of different types are ordered integers,
+/// floats, then strings.
+pub(super) struct SortKey<'a>(pub(super) &'a mut Vec<u8>);
+
+impl LabelGroupVisitor for SortKey<'_> {
+    type Output = ();
+
+    fn write_value(&mut self, _name: &LabelName, x: &impl LabelValue) {
+        x.visit(SortKeyValue(self.0));
+    }
+}
+
+struct SortKeyValue<'a>(&'a mut Vec<u8>);
+
+impl LabelVisitor for SortKeyValue<'_> {
+    type Output = ();
+
+    fn write_int(self, x: i64) {
+        // flip the sign bit, so negative values sort first
+        let x = (x as u64) ^ (1 << 63);
+        self.0.push(0);
+        self.0.extend_from_slice(&x.to_be_bytes());
+    }
+
+    fn write_float(self, x: f64) {
+        // the same ordering as `f64::total_cmp`: -NaN, -Inf, ..., -0, 0, ..., Inf, NaN
+        let x = x.to_bits();
+        let x = if x >> 63 == 1 { !x } else { x | (1 << 63) };
+        self.0.push(1);
+        self.0.extend_from_slice(&x.to_be_bytes());
+    }
+
+    fn write_str(self, x: &str) {
+        self.write_prefixed_str("", x);
+    }
+
+    fn write_prefixed_str(self, prefix: &str, x: &str) {
+        // terminate with 0 0 so that shorter values sort first, escaping any 0 bytes
+        self.0.push(2);
+        for &b in prefix.as_bytes().iter().chain(x.as_bytes()) {
+            self.0.push(b);
+            if b == 0 {
+                self.0.push(0xff);
+            }
+        }
+        self.0.extend_from_slice(&[0, 0]);
+    }
+}
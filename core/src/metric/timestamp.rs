@@ -0,0 +1,51 @@
+//! Metrics with an explicit sample timestamp. See [`Timestamped`]
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::MetricType;
+
+/// Attaches an explicit timestamp to the samples of a metric.
+///
+/// Prometheus uses the scrape time for samples without a timestamp. This is only useful
+/// for values that were observed at a known time, such as metrics relayed from an external source.
+///
+/// See [`write_counter_at`](super::counter::write_counter_at) and
+/// [`write_gauge_at`](super::gauge::write_gauge_at) for writing a single timestamped value.
+#[derive(Default)]
+pub struct Timestamped<M> {
+    metric: M,
+    timestamp_ms: i64,
+}
+
+impl<M> Timestamped<M> {
+    /// Attach the time to the metric
+    pub fn new(metric: M, time: SystemTime) -> Self {
+        let timestamp_ms = match time.duration_since(UNIX_EPOCH) {
+            Ok(d) => d.as_millis() as i64,
+            Err(e) => -(e.duration().as_millis() as i64),
+        };
+        Self::from_millis(metric, timestamp_ms)
+    }
+
+    /// Attach the timestamp, in milliseconds since the unix epoch, to the metric
+    pub fn from_millis(metric: M, timestamp_ms: i64) -> Self {
+        Self {
+            metric,
+            timestamp_ms,
+        }
+    }
+
+    /// The inner metric
+    pub fn metric(&self) -> &M {
+        &self.metric
+    }
+
+    /// The timestamp, in milliseconds since the unix epoch
+    pub fn timestamp_ms(&self) -> i64 {
+        self.timestamp_ms
+    }
+}
+
+impl<M: MetricType> MetricType for Timestamped<M> {
+    type Metadata = M::Metadata;
+}
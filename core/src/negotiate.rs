@@ -0,0 +1,371 @@
+//! Content negotiation between multiple [`Encoding`]s
+//!
+//! A [`Negotiator`] holds a set of buffered encoders, and picks the best one for a request
+//! based on its `Accept` header, following <https://prometheus.io/docs/instrumenting/content_negotiation/>.
+//!
+//! ```
+//! use measured::{Counter, MetricGroup, negotiate::Negotiator, text::BufferedTextEncoder};
+//!
+//! #[derive(MetricGroup)]
+//! struct MyMetrics {
+//!     /// total requests served
+//!     requests: Counter,
+//! }
+//!
+//! let metrics = MyMetrics { requests: Counter::new() };
+//!
+//! let mut negotiator = Negotiator::new().with_encoder(BufferedTextEncoder::new());
+//!
+//! let encoded = negotiator.encode(Some("text/plain;version=0.0.4;q=0.5,*/*;q=0.1"), &metrics).unwrap();
+//! assert_eq!(encoded.content_type, "text/plain; version=0.0.4");
+//!
+//! assert!(negotiator.encode(Some("application/json"), &metrics).is_err());
+//! ```
+
+use bytes::Bytes;
+
+use crate::{MetricGroup, metric::group::Encoding};
+
+/// An [`Encoding`] that buffers the encoded metrics in memory.
+pub trait BufferedEncoding: Encoding {
+    /// Finish the encoding and extract the bytes to send in a HTTP response.
+    ///
+    /// # Errors
+    /// Will error if the encoder failed to flush.
+    fn finish(&mut self) -> Result<Bytes, Self::Err>;
+
+    /// Discard any partially encoded metrics, eg after a collection failed.
+    ///
+    /// By default, this finishes the encoding and drops the output.
+    fn reset(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+impl BufferedEncoding for crate::text::BufferedTextEncoder {
+    fn finish(&mut self) -> Result<Bytes, Self::Err> {
+        Ok(crate::text::BufferedTextEncoder::finish(self))
+    }
+}
+
+/// The output of [`Negotiator::encode`]
+#[derive(Clone, Debug)]
+pub struct Encoded {
+    /// The value to send in the `Content-Type` response header
+    pub content_type: &'static str,
+    /// The encoded metrics to send as the response body
+    pub body: Bytes,
+}
+
+/// Error returned by [`Negotiator::encode`]
+#[derive(Debug)]
+pub enum NegotiationError {
+    /// None of the registered encodings are acceptable.
+    ///
+    /// This should usually be returned as a `406 Not Acceptable` response.
+    NotAcceptable,
+    /// The chosen encoding failed to collect the metrics
+    Encoding(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl core::fmt::Display for NegotiationError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            NegotiationError::NotAcceptable => {
+                f.write_str("no registered encoding matches the accept header")
+            }
+            NegotiationError::Encoding(e) => write!(f, "could not encode metrics: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for NegotiationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            NegotiationError::NotAcceptable => None,
+            NegotiationError::Encoding(e) => Some(&**e),
+        }
+    }
+}
+
+trait ErasedEncoding<G: ?Sized> {
+    fn mime_type(&self) -> &'static str;
+    fn encode(&mut self, group: &G) -> Result<Bytes, NegotiationError>;
+}
+
+impl<G, E> ErasedEncoding<G> for E
+where
+    G: MetricGroup<E> + ?Sized,
+    E: BufferedEncoding,
+    E::Err: std::error::Error + Send + Sync + 'static,
+{
+    fn mime_type(&self) -> &'static str {
+        E::MIME_TYPE
+    }
+
+    fn encode(&mut self, group: &G) -> Result<Bytes, NegotiationError> {
+        let res = group.collect_group_into(self).and_then(|()| self.finish());
+        if res.is_err() {
+            // don't leave the partial output in the buffer for the next collection
+            self.reset();
+        }
+        res.map_err(|e| NegotiationError::Encoding(Box::new(e)))
+    }
+}
+
+/// Chooses between a set of registered encodings based on the `Accept` header of a request.
+///
+/// The first registered encoding is used if the request has no `Accept` header,
+/// or when multiple encodings are equally acceptable.
+///
+/// This should ideally be cached and re-used between collections to reduce re-allocating
+pub struct Negotiator<G: ?Sized> {
+    encoders: Vec<Box<dyn ErasedEncoding<G> + Send>>,
+}
+
+impl<G: ?Sized> Default for Negotiator<G> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<G: ?Sized> Negotiator<G> {
+    /// Create a new negotiator with no registered encodings
+    pub fn new() -> Self {
+        Self {
+            encoders: Vec::new(),
+        }
+    }
+
+    /// Register an additional encoding
+    #[must_use]
+    pub fn with_encoder<E>(mut self, encoder: E) -> Self
+    where
+        E: BufferedEncoding + Send + 'static,
+        E::Err: std::error::Error + Send + Sync + 'static,
+        G: MetricGroup<E>,
+    {
+        self.register(encoder);
+        self
+    }
+
+    /// Register an additional encoding
+    pub fn register<E>(&mut self, encoder: E)
+    where
+        E: BufferedEncoding + Send + 'static,
+        E::Err: std::error::Error + Send + Sync + 'static,
+        G: MetricGroup<E>,
+    {
+        self.encoders.push(Box::new(encoder));
+    }
+
+    /// Get the `Content-Type` of the encoding that would be chosen for this `Accept` header.
+    pub fn content_type(&self, accept: Option<&str>) -> Option<&'static str> {
+        self.select(accept).map(|i| self.encoders[i].mime_type())
+    }
+
+    /// Collect the metric group using the encoding best matching the `Accept` header.
+    ///
+    /// # Errors
+    /// Will error if none of the registered encodings are acceptable, or if the chosen encoding fails.
+    pub fn encode(&mut self, accept: Option<&str>, group: &G) -> Result<Encoded, NegotiationError> {
+        let i = self.select(accept).ok_or(NegotiationError::NotAcceptable)?;
+        let encoder = &mut self.encoders[i];
+        Ok(Encoded {
+            content_type: encoder.mime_type(),
+            body: encoder.encode(group)?,
+        })
+    }
+
+    fn select(&self, accept: Option<&str>) -> Option<usize> {
+        match accept.map(str::trim) {
+            None | Some("") => (!self.encoders.is_empty()).then_some(0),
+            Some(accept) => select(accept, self.encoders.iter().map(|e| e.mime_type())),
+        }
+    }
+}
+
+/// Find the index of the most acceptable offered media type.
+fn select<'a>(accept: &str, offers: impl Iterator<Item = &'a str>) -> Option<usize> {
+    let ranges: Vec<MediaType<'_>> = accept.split(',').filter_map(MediaType::parse).collect();
+
+    let mut best = None;
+    for (i, offer) in offers.enumerate() {
+        let Some(offer) = MediaType::parse(offer) else {
+            continue;
+        };
+
+        // the quality of an offer is determined by the most specific range that matches it
+        let q = ranges
+            .iter()
+            .filter(|range| range.matches(&offer))
+            .max_by_key(|range| range.specificity())
+            .map_or(0.0, |range| range.q);
+
+        if q > 0.0 && best.is_none_or(|(_, best_q)| q > best_q) {
+            best = Some((i, q));
+        }
+    }
+    best.map(|(i, _)| i)
+}
+
+struct MediaType<'a> {
+    typ: &'a str,
+    subtype: &'a str,
+    params: Vec<(&'a str, &'a str)>,
+    q: f32,
+}
+
+impl<'a> MediaType<'a> {
+    fn parse(s: &'a str) -> Option<Self> {
+        let mut parts = s.split(';');
+        let (typ, subtype) = parts.next()?.trim().split_once('/')?;
+        let (typ, subtype) = (typ.trim(), subtype.trim());
+        if typ.is_empty() || subtype.is_empty() {
+            return None;
+        }
+
+        let mut params = Vec::new();
+        let mut q = 1.0;
+        for param in parts {
+            let Some((key, value)) = param.split_once('=') else {
+                continue;
+            };
+            let (key, value) = (key.trim(), value.trim().trim_matches('"'));
+            if key.eq_ignore_ascii_case("q") {
+                q = value
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|q| (0.0..=1.0).contains(q))?;
+            } else {
+                params.push((key, value));
+            }
+        }
+
+        Some(Self {
+            typ,
+            subtype,
+            params,
+            q,
+        })
+    }
+
+    /// Whether this media range accepts the offered media type
+    fn matches(&self, offer: &MediaType<'_>) -> bool {
+        let typ = self.typ == "*" || self.typ.eq_ignore_ascii_case(offer.typ);
+        let subtype = self.subtype == "*" || self.subtype.eq_ignore_ascii_case(offer.subtype);
+        let params = self.params.iter().all(|(k, v)| {
+            offer
+                .params
+                .iter()
+                .any(|(k2, v2)| k.eq_ignore_ascii_case(k2) && v == v2)
+        });
+        typ && subtype && params
+    }
+
+    fn specificity(&self) -> (bool, bool, usize) {
+        (self.typ != "*", self.subtype != "*", self.params.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use bytes::Bytes;
+
+    use super::{BufferedEncoding, Negotiator, select};
+    use crate::{
+        MetricGroup,
+        metric::{group::Encoding, name::MetricNameEncoder},
+        text::BufferedTextEncoder,
+    };
+
+    const TEXT: &str = "text/plain; version=0.0.4";
+    const PROTO: &str = "application/vnd.google.protobuf; proto=io.prometheus.client.MetricFamily; encoding=delimited";
+    const OPENMETRICS: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+    fn choose(accept: &str) -> Option<usize> {
+        select(accept, [TEXT, PROTO, OPENMETRICS].into_iter())
+    }
+
+    #[test]
+    fn prometheus_accept_headers() {
+        // prometheus 2.x with protobuf enabled
+        assert_eq!(
+            choose(
+                "application/vnd.google.protobuf;proto=io.prometheus.client.MetricFamily;encoding=delimited;q=0.7,text/plain;version=0.0.4;q=0.3,*/*;q=0.1"
+            ),
+            Some(1)
+        );
+        // prometheus 2.x default
+        assert_eq!(
+            choose(
+                "application/openmetrics-text;version=1.0.0,application/openmetrics-text;version=0.0.1;q=0.75,text/plain;version=0.0.4;q=0.5,*/*;q=0.1"
+            ),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn specificity() {
+        // the specific range excludes text, even though */* would allow it
+        assert_eq!(choose("text/plain;q=0,*/*"), Some(1));
+        // version mismatch doesn't match
+        assert_eq!(choose("text/plain;version=1.0.0"), None);
+        assert_eq!(choose("text/*"), Some(0));
+        // ties are broken by registration order
+        assert_eq!(choose("*/*"), Some(0));
+        assert_eq!(choose("application/json"), None);
+        assert_eq!(choose("garbage"), None);
+    }
+
+    /// A text encoder whose metric group fails halfway through collecting
+    struct FlakyEncoder(BufferedTextEncoder);
+
+    impl Encoding for FlakyEncoder {
+        type Err = std::io::Error;
+
+        const MIME_TYPE: &'static str = TEXT;
+
+        fn write_help(&mut self, name: impl MetricNameEncoder, help: &str) -> std::io::Result<()> {
+            let Ok(()) = self.0.write_help(name, help);
+            Ok(())
+        }
+    }
+
+    impl BufferedEncoding for FlakyEncoder {
+        fn finish(&mut self) -> std::io::Result<Bytes> {
+            Ok(self.0.finish())
+        }
+    }
+
+    struct FlakyGroup(AtomicBool);
+
+    impl MetricGroup<FlakyEncoder> for FlakyGroup {
+        fn collect_group_into(&self, enc: &mut FlakyEncoder) -> std::io::Result<()> {
+            enc.write_help(
+                crate::metric::name::MetricName::from_str("requests"),
+                "total requests",
+            )?;
+            if self.0.load(Ordering::Relaxed) {
+                return Err(std::io::Error::other("scrape failed"));
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn reset_after_error() {
+        let mut negotiator =
+            Negotiator::new().with_encoder(FlakyEncoder(BufferedTextEncoder::new()));
+
+        let group = FlakyGroup(AtomicBool::new(true));
+        let err = negotiator.encode(None, &group).unwrap_err();
+        assert_eq!(err.to_string(), "could not encode metrics: scrape failed");
+
+        group.0.store(false, Ordering::Relaxed);
+        let encoded = negotiator.encode(None, &group).unwrap();
+        assert_eq!(encoded.body, "# HELP requests total requests\n");
+    }
+}
@@ -0,0 +1,842 @@
+//! Parser for the prometheus text exposition format
+//!
+//! This turns the output of [`TextEncoder`](crate::text::TextEncoder) (or any other prometheus client)
+//! back into typed [`MetricFamily`] values. It's primarily useful for testing your instrumentation,
+//! or for building tools that consume the metrics of other services.
+//!
+//! ```
+//! use measured::{
+//!     Counter,
+//!     metric::{MetricFamilyEncoding, group::Encoding, name::MetricName},
+//!     parse::parse_text,
+//!     text::{BufferedTextEncoder, MetricType},
+//! };
+//!
+//! let counter = Counter::new();
+//! counter.inc_by(5);
+//!
+//! let mut enc = BufferedTextEncoder::new();
+//! let name = MetricName::from_str("requests_total");
+//! enc.write_help(name, "total requests served").unwrap();
+//! counter.collect_family_into(name, &mut enc).unwrap();
+//!
+//! let text = enc.finish();
+//! let families = parse_text(std::str::from_utf8(&text).unwrap()).unwrap();
+//!
+//! assert_eq!(families[0].name, "requests_total");
+//! assert_eq!(families[0].help.as_deref(), Some("total requests served"));
+//! assert_eq!(families[0].metric_type, MetricType::Counter);
+//! assert_eq!(families[0].samples[0].value, 5.0);
+//! ```
+
+use std::collections::{HashMap, HashSet};
+
+use crate::text::MetricType;
+
+/// A metric family, as parsed from an exposition format
+#[derive(Clone, Debug, PartialEq)]
+pub struct MetricFamily {
+    /// The name of this metric family
+    pub name: String,
+    /// The help text of this metric family, if any was provided
+    pub help: Option<String>,
+    /// The type of this metric family. Families without a `TYPE` line are [`MetricType::Untyped`]
+    pub metric_type: MetricType,
+    /// The samples of this metric family, in the order they were written
+    pub samples: Vec<Sample>,
+}
+
+/// A single sample of a [`MetricFamily`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sample {
+    /// The full name of this sample, including any suffix such as `_bucket` or `_count`
+    pub name: String,
+    /// The label pairs of this sample, in the order they were written
+    pub labels: Vec<(String, String)>,
+    /// The value of this sample
+    pub value: f64,
+    /// The timestamp of this sample, in milliseconds since the unix epoch
+    pub timestamp_ms: Option<i64>,
+}
+
+impl Sample {
+    /// Get the value of the label with the given name
+    pub fn label(&self, name: &str) -> Option<&str> {
+        self.labels
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Error returned by [`parse_text`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    line: usize,
+    kind: ParseErrorKind,
+}
+
+impl ParseError {
+    /// The line number (starting from 1) that the error occurred on
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// The reason the input could not be parsed
+    pub fn kind(&self) -> &ParseErrorKind {
+        &self.kind
+    }
+}
+
+impl core::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.kind)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// The reason an exposition could not be parsed
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParseErrorKind {
+    /// A metric name was empty or contained invalid characters
+    InvalidMetricName,
+    /// A label name was empty or contained invalid characters
+    InvalidLabelName,
+    /// The label set of a sample was malformed
+    MalformedLabels,
+    /// A label value or help text contained an unsupported escape sequence
+    InvalidEscape,
+    /// A sample contained the same label name twice
+    DuplicateLabel,
+    /// A sample value could not be parsed as a float
+    InvalidValue,
+    /// A sample timestamp could not be parsed as an integer
+    InvalidTimestamp,
+    /// A `TYPE` line contained an unknown metric type
+    InvalidType,
+    /// A metric family had more than one `HELP` line
+    DuplicateHelp,
+    /// A metric family had more than one `TYPE` line
+    DuplicateType,
+    /// A `HELP` or `TYPE` line appeared after the samples of its metric family
+    MetadataAfterSamples,
+    /// A metric family appeared more than once, or was interleaved with another family
+    DuplicateFamily(String),
+    /// A sample name does not belong to the metric family it was written in
+    UnexpectedSample,
+    /// A histogram bucket was missing or had an invalid `le` label
+    InvalidBucketBound,
+    /// Histogram bucket bounds were not increasing, or bucket counts were decreasing
+    NonMonotonicBuckets,
+    /// A histogram series had no `le="+Inf"` bucket
+    MissingInfBucket,
+    /// A histogram `_count` did not match the `le="+Inf"` bucket
+    CountMismatch,
+}
+
+impl core::fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ParseErrorKind::InvalidMetricName => f.write_str("invalid metric name"),
+            ParseErrorKind::InvalidLabelName => f.write_str("invalid label name"),
+            ParseErrorKind::MalformedLabels => f.write_str("malformed label set"),
+            ParseErrorKind::InvalidEscape => f.write_str("invalid escape sequence"),
+            ParseErrorKind::DuplicateLabel => f.write_str("label name appeared more than once"),
+            ParseErrorKind::InvalidValue => f.write_str("invalid sample value"),
+            ParseErrorKind::InvalidTimestamp => f.write_str("invalid sample timestamp"),
+            ParseErrorKind::InvalidType => f.write_str("invalid metric type"),
+            ParseErrorKind::DuplicateHelp => f.write_str("metric family has more than one HELP"),
+            ParseErrorKind::DuplicateType => f.write_str("metric family has more than one TYPE"),
+            ParseErrorKind::MetadataAfterSamples => {
+                f.write_str("HELP or TYPE appeared after the samples of the metric family")
+            }
+            ParseErrorKind::DuplicateFamily(name) => {
+                write!(f, "metric family {name} appeared more than once")
+            }
+            ParseErrorKind::UnexpectedSample => {
+                f.write_str("sample does not belong to the metric family")
+            }
+            ParseErrorKind::InvalidBucketBound => f.write_str("invalid histogram bucket bound"),
+            ParseErrorKind::NonMonotonicBuckets => {
+                f.write_str("histogram buckets were not monotonic")
+            }
+            ParseErrorKind::MissingInfBucket => f.write_str("histogram is missing the +Inf bucket"),
+            ParseErrorKind::CountMismatch => {
+                f.write_str("histogram count does not match the +Inf bucket")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseErrorKind {}
+
+impl MetricFamily {
+    /// Check that this metric family is well formed.
+    ///
+    /// Families returned by [`parse_text`] have already been validated.
+    ///
+    /// # Errors
+    /// Will error if any names are invalid, if samples do not belong to this family,
+    /// or if histogram buckets are not cumulative.
+    pub fn validate(&self) -> Result<(), ParseErrorKind> {
+        self.validate_samples().map_err(|(_, kind)| kind)
+    }
+
+    /// Validates the family, returning the index of the offending sample on error
+    fn validate_samples(&self) -> Result<(), (Option<usize>, ParseErrorKind)> {
+        if !is_metric_name(&self.name) {
+            return Err((None, ParseErrorKind::InvalidMetricName));
+        }
+
+        for (i, sample) in self.samples.iter().enumerate() {
+            if !self.owns_sample(&sample.name) {
+                return Err((Some(i), ParseErrorKind::UnexpectedSample));
+            }
+            for (j, (name, _)) in sample.labels.iter().enumerate() {
+                if !is_label_name(name) {
+                    return Err((Some(i), ParseErrorKind::InvalidLabelName));
+                }
+                if sample.labels[..j].iter().any(|(n, _)| n == name) {
+                    return Err((Some(i), ParseErrorKind::DuplicateLabel));
+                }
+            }
+        }
+
+        if self.metric_type == MetricType::Histogram {
+            validate_histogram(&self.name, &self.samples).map_err(|(i, e)| (Some(i), e))?;
+        }
+
+        Ok(())
+    }
+
+    /// Whether a sample with this name belongs to this family
+    fn owns_sample(&self, sample: &str) -> bool {
+        let Some(suffix) = sample.strip_prefix(&*self.name) else {
+            return false;
+        };
+        match self.metric_type {
+            MetricType::Histogram => matches!(suffix, "_bucket" | "_sum" | "_count"),
+            MetricType::Summary => matches!(suffix, "" | "_sum" | "_count"),
+            MetricType::Counter | MetricType::Gauge | MetricType::Untyped => suffix.is_empty(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct HistogramSeries {
+    first: usize,
+    le: Option<f64>,
+    cumulative: f64,
+    inf: Option<f64>,
+    count: Option<(usize, f64)>,
+}
+
+fn validate_histogram(name: &str, samples: &[Sample]) -> Result<(), (usize, ParseErrorKind)> {
+    let mut series = HashMap::<Vec<(&str, &str)>, HistogramSeries>::new();
+
+    for (i, sample) in samples.iter().enumerate() {
+        let mut key: Vec<(&str, &str)> = sample
+            .labels
+            .iter()
+            .filter(|(n, _)| n != "le")
+            .map(|(n, v)| (n.as_str(), v.as_str()))
+            .collect();
+        key.sort_unstable();
+
+        let s = series.entry(key).or_insert_with(|| HistogramSeries {
+            first: i,
+            ..HistogramSeries::default()
+        });
+
+        match &sample.name[name.len()..] {
+            "_bucket" => {
+                let le = sample
+                    .label("le")
+                    .and_then(parse_float)
+                    .filter(|le| !le.is_nan())
+                    .ok_or((i, ParseErrorKind::InvalidBucketBound))?;
+
+                if s.le.is_some_and(|prev| prev >= le) || s.cumulative > sample.value {
+                    return Err((i, ParseErrorKind::NonMonotonicBuckets));
+                }
+                s.le = Some(le);
+                s.cumulative = sample.value;
+                if le == f64::INFINITY {
+                    s.inf = Some(sample.value);
+                }
+            }
+            "_count" => s.count = Some((i, sample.value)),
+            _ => {}
+        }
+    }
+
+    for s in series.values() {
+        let Some(inf) = s.inf else {
+            return Err((s.first, ParseErrorKind::MissingInfBucket));
+        };
+        if let Some((i, count)) = s.count
+            && count != inf
+        {
+            return Err((i, ParseErrorKind::CountMismatch));
+        }
+    }
+
+    Ok(())
+}
+
+fn is_metric_name(s: &str) -> bool {
+    !s.is_empty()
+        && !s.as_bytes()[0].is_ascii_digit()
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b':')
+}
+
+fn is_label_name(s: &str) -> bool {
+    !s.is_empty()
+        && !s.as_bytes()[0].is_ascii_digit()
+        && s.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_')
+}
+
+/// Parses a sample value, including the `+Inf`, `-Inf` and `NaN` special values
+fn parse_float(s: &str) -> Option<f64> {
+    // rust accepts more spellings of infinity than prometheus does, but that's harmless.
+    s.parse().ok()
+}
+
+/// Parse the prometheus text exposition format (version 0.0.4)
+///
+/// # Errors
+/// Will error if the input is not a valid exposition. This includes syntax errors,
+/// `HELP`/`TYPE` lines that come after the samples of their family, metric families that appear
+/// more than once, invalid escape sequences in label values or help text,
+/// and histograms whose buckets are not cumulative.
+pub fn parse_text(input: &str) -> Result<Vec<MetricFamily>, ParseError> {
+    let mut parser = TextParser {
+        families: Vec::new(),
+        seen: HashSet::new(),
+        current: None,
+    };
+
+    for (i, line) in input.lines().enumerate() {
+        parser.parse_line(i + 1, line)?;
+    }
+    parser.finish_family()?;
+
+    Ok(parser.families)
+}
+
+struct TextParser {
+    families: Vec<MetricFamily>,
+    seen: HashSet<String>,
+    current: Option<CurrentFamily>,
+}
+
+struct CurrentFamily {
+    family: MetricFamily,
+    has_type: bool,
+    start: usize,
+    lines: Vec<usize>,
+}
+
+impl TextParser {
+    fn parse_line(&mut self, line_no: usize, line: &str) -> Result<(), ParseError> {
+        let error = |kind| ParseError {
+            line: line_no,
+            kind,
+        };
+
+        let line = line.trim_start_matches([' ', '\t']);
+        if line.trim_end().is_empty() {
+            return Ok(());
+        }
+
+        if let Some(comment) = line.strip_prefix('#') {
+            let comment = comment.trim_start_matches([' ', '\t']);
+            if let Some(rest) = comment.strip_prefix("HELP ") {
+                let (name, help) = split_token(rest);
+                let help = unescape_help(help).map_err(error)?;
+                let current = self.metadata_family(line_no, name)?;
+                if current.family.help.is_some() {
+                    return Err(error(ParseErrorKind::DuplicateHelp));
+                }
+                current.family.help = Some(help);
+            } else if let Some(rest) = comment.strip_prefix("TYPE ") {
+                let (name, typ) = split_token(rest);
+                let typ: MetricType = typ
+                    .trim_end()
+                    .parse()
+                    .map_err(|_| error(ParseErrorKind::InvalidType))?;
+                let current = self.metadata_family(line_no, name)?;
+                if current.has_type {
+                    return Err(error(ParseErrorKind::DuplicateType));
+                }
+                current.has_type = true;
+                current.family.metric_type = typ;
+            }
+            // all other comments are ignored
+            return Ok(());
+        }
+
+        let sample = parse_sample(line).map_err(error)?;
+        let current = match &mut self.current {
+            Some(current) if current.family.owns_sample(&sample.name) => current,
+            _ => self.start_family(line_no, sample.name.clone())?,
+        };
+        current.family.samples.push(sample);
+        current.lines.push(line_no);
+
+        Ok(())
+    }
+
+    /// Get the family that a `HELP` or `TYPE` line refers to, starting a new one if necessary
+    fn metadata_family(
+        &mut self,
+        line_no: usize,
+        name: &str,
+    ) -> Result<&mut CurrentFamily, ParseError> {
+        let error = |kind| ParseError {
+            line: line_no,
+            kind,
+        };
+
+        if !is_metric_name(name) {
+            return Err(error(ParseErrorKind::InvalidMetricName));
+        }
+
+        if self.current.as_ref().is_none_or(|c| c.family.name != name) {
+            return self.start_family(line_no, name.to_owned());
+        }
+
+        let current = self
+            .current
+            .as_mut()
+            .expect("current family was checked above");
+        if !current.family.samples.is_empty() {
+            return Err(error(ParseErrorKind::MetadataAfterSamples));
+        }
+        Ok(current)
+    }
+
+    fn start_family(
+        &mut self,
+        line_no: usize,
+        name: String,
+    ) -> Result<&mut CurrentFamily, ParseError> {
+        // errors in the previous family are reported at their own line
+        self.finish_family()?;
+
+        if !self.seen.insert(name.clone()) {
+            return Err(ParseError {
+                line: line_no,
+                kind: ParseErrorKind::DuplicateFamily(name),
+            });
+        }
+
+        Ok(self.current.insert(CurrentFamily {
+            family: MetricFamily {
+                name,
+                help: None,
+                metric_type: MetricType::Untyped,
+                samples: Vec::new(),
+            },
+            has_type: false,
+            start: line_no,
+            lines: Vec::new(),
+        }))
+    }
+
+    fn finish_family(&mut self) -> Result<(), ParseError> {
+        if let Some(current) = self.current.take() {
+            current
+                .family
+                .validate_samples()
+                .map_err(|(i, kind)| ParseError {
+                    line: i.map_or(current.start, |i| current.lines[i]),
+                    kind,
+                })?;
+            self.families.push(current.family);
+        }
+        Ok(())
+    }
+}
+
+/// Splits off the first whitespace delimited token
+fn split_token(s: &str) -> (&str, &str) {
+    let s = s.trim_start_matches([' ', '\t']);
+    match s.find([' ', '\t']) {
+        Some(i) => (&s[..i], s[i + 1..].trim_start_matches([' ', '\t'])),
+        None => (s, ""),
+    }
+}
+
+fn unescape_help(s: &str) -> Result<String, ParseErrorKind> {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('\\') => out.push('\\'),
+                Some('n') => out.push('\n'),
+                _ => return Err(ParseErrorKind::InvalidEscape),
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    Ok(out)
+}
+
+fn parse_sample(line: &str) -> Result<Sample, ParseErrorKind> {
+    let name_len = line
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == ':'))
+        .unwrap_or(line.len());
+    let (name, mut rest) = line.split_at(name_len);
+    if !is_metric_name(name) {
+        return Err(ParseErrorKind::InvalidMetricName);
+    }
+
+    let mut labels = Vec::new();
+    if let Some(r) = rest.strip_prefix('{') {
+        rest = parse_labels(r, &mut labels)?;
+    }
+
+    if !rest.starts_with([' ', '\t']) {
+        return Err(ParseErrorKind::InvalidValue);
+    }
+    let (value, timestamp) = split_token(rest);
+    let value = parse_float(value).ok_or(ParseErrorKind::InvalidValue)?;
+    let timestamp = timestamp.trim_end_matches([' ', '\t']);
+    let timestamp_ms = if timestamp.is_empty() {
+        None
+    } else {
+        Some(
+            timestamp
+                .parse()
+                .map_err(|_| ParseErrorKind::InvalidTimestamp)?,
+        )
+    };
+
+    Ok(Sample {
+        name: name.to_owned(),
+        labels,
+        value,
+        timestamp_ms,
+    })
+}
+
+/// Parse the label pairs following a `{`, returning the remainder of the line after the `}`
+fn parse_labels<'a>(
+    mut s: &'a str,
+    labels: &mut Vec<(String, String)>,
+) -> Result<&'a str, ParseErrorKind> {
+    loop {
+        s = s.trim_start_matches([' ', '\t']);
+        if let Some(rest) = s.strip_prefix('}') {
+            return Ok(rest);
+        }
+
+        let name_len = s
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .ok_or(ParseErrorKind::MalformedLabels)?;
+        let (name, rest) = s.split_at(name_len);
+        if !is_label_name(name) {
+            return Err(ParseErrorKind::InvalidLabelName);
+        }
+
+        let rest = rest.trim_start_matches([' ', '\t']);
+        let rest = rest
+            .strip_prefix('=')
+            .ok_or(ParseErrorKind::MalformedLabels)?;
+        let rest = rest.trim_start_matches([' ', '\t']);
+        let rest = rest
+            .strip_prefix('"')
+            .ok_or(ParseErrorKind::MalformedLabels)?;
+
+        let mut value = String::new();
+        let mut chars = rest.char_indices();
+        let end = loop {
+            match chars.next() {
+                Some((i, '"')) => break i,
+                Some((_, '\\')) => match chars.next() {
+                    Some((_, '\\')) => value.push('\\'),
+                    Some((_, '"')) => value.push('"'),
+                    Some((_, 'n')) => value.push('\n'),
+                    _ => return Err(ParseErrorKind::InvalidEscape),
+                },
+                Some((_, c)) => value.push(c),
+                None => return Err(ParseErrorKind::MalformedLabels),
+            }
+        };
+
+        if labels.iter().any(|(n, _)| n == name) {
+            return Err(ParseErrorKind::DuplicateLabel);
+        }
+        labels.push((name.to_owned(), value));
+
+        s = rest[end + 1..].trim_start_matches([' ', '\t']);
+        if let Some(rest) = s.strip_prefix(',') {
+            s = rest;
+        } else if !s.starts_with('}') {
+            return Err(ParseErrorKind::MalformedLabels);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        CounterVec, HistogramVec,
+        label::{LabelGroup, LabelGroupVisitor, LabelName, StaticLabelSet},
+        metric::{
+            MetricEncoding, MetricFamilyEncoding,
+            gauge::{GaugeState, write_gauge},
+            group::Encoding,
+            histogram::Thresholds,
+            name::{MetricName, Total},
+        },
+        text::{BufferedTextEncoder, MetricType},
+    };
+
+    use super::{ParseErrorKind, Sample, parse_text};
+
+    #[derive(Clone, Copy, PartialEq, Debug, measured_derive::LabelGroup)]
+    #[label(crate = crate, set = RequestLabelSet)]
+    struct RequestLabels {
+        method: Method,
+    }
+
+    #[derive(Clone, Copy, PartialEq, Debug, measured_derive::FixedCardinalityLabel)]
+    #[label(crate = crate, rename_all = "snake_case")]
+    enum Method {
+        Post,
+        Get,
+    }
+
+    struct PathLabel<'a>(&'a str);
+
+    impl LabelGroup for PathLabel<'_> {
+        fn visit_values(&self, v: &mut impl LabelGroupVisitor) {
+            v.write_value(LabelName::from_str("path"), &self.0);
+        }
+    }
+
+    fn sample(name: &str, labels: &[(&str, &str)], value: f64) -> Sample {
+        Sample {
+            name: name.to_owned(),
+            labels: labels
+                .iter()
+                .map(|&(n, v)| (n.to_owned(), v.to_owned()))
+                .collect(),
+            value,
+            timestamp_ms: None,
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let requests = CounterVec::with_label_set(RequestLabelSet {
+            method: StaticLabelSet::new(),
+        });
+        requests.inc_by(
+            RequestLabels {
+                method: Method::Post,
+            },
+            1027,
+        );
+        requests.inc_by(
+            RequestLabels {
+                method: Method::Get,
+            },
+            3,
+        );
+
+        let durations = HistogramVec::with_label_set_and_metadata(
+            RequestLabelSet {
+                method: StaticLabelSet::new(),
+            },
+            Thresholds::<2>::linear_buckets(1.0, 1.0),
+        );
+        durations.observe(
+            RequestLabels {
+                method: Method::Get,
+            },
+            1.5,
+        );
+        durations.observe(
+            RequestLabels {
+                method: Method::Get,
+            },
+            3.5,
+        );
+
+        let mut enc = BufferedTextEncoder::new();
+
+        let name = MetricName::from_str("http_request").with_suffix(Total);
+        enc.write_help(&name, "The total number of HTTP requests.")
+            .unwrap();
+        requests.collect_family_into(&name, &mut enc).unwrap();
+
+        let name = MetricName::from_str("inflight");
+        <GaugeState as MetricEncoding<_>>::write_type(name, &mut enc).unwrap();
+        write_gauge(&mut enc, name, PathLabel("/a \"quoted\"\n\\path"), -4).unwrap();
+
+        let name = MetricName::from_str("duration_seconds");
+        enc.write_help(name, "Request durations").unwrap();
+        durations.collect_family_into(name, &mut enc).unwrap();
+
+        let text = enc.finish();
+        let families = parse_text(std::str::from_utf8(&text).unwrap()).unwrap();
+
+        assert_eq!(families.len(), 3);
+
+        assert_eq!(families[0].name, "http_request_total");
+        assert_eq!(
+            families[0].help.as_deref(),
+            Some("The total number of HTTP requests.")
+        );
+        assert_eq!(families[0].metric_type, MetricType::Counter);
+        assert_eq!(
+            families[0].samples,
+            [
+                sample("http_request_total", &[("method", "post")], 1027.0),
+                sample("http_request_total", &[("method", "get")], 3.0),
+            ]
+        );
+
+        assert_eq!(families[1].name, "inflight");
+        assert_eq!(families[1].help, None);
+        assert_eq!(families[1].metric_type, MetricType::Gauge);
+        assert_eq!(
+            families[1].samples,
+            [sample(
+                "inflight",
+                &[("path", "/a \"quoted\"\n\\path")],
+                -4.0
+            )]
+        );
+
+        assert_eq!(families[2].name, "duration_seconds");
+        assert_eq!(families[2].metric_type, MetricType::Histogram);
+        assert_eq!(
+            families[2].samples,
+            [
+                sample(
+                    "duration_seconds_bucket",
+                    &[("method", "get"), ("le", "1.0")],
+                    0.0
+                ),
+                sample(
+                    "duration_seconds_bucket",
+                    &[("method", "get"), ("le", "2.0")],
+                    1.0
+                ),
+                sample(
+                    "duration_seconds_bucket",
+                    &[("method", "get"), ("le", "+Inf")],
+                    2.0
+                ),
+                sample("duration_seconds_sum", &[("method", "get")], 5.0),
+                sample("duration_seconds_count", &[("method", "get")], 2.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn timestamps_and_comments() {
+        let families = parse_text(
+            "# a comment\n\
+             # TYPE up gauge\n\
+             up{ job = \"a\", } 1 1700000000000\n\
+             \n\
+             other 2.5e3\n",
+        )
+        .unwrap();
+
+        assert_eq!(families.len(), 2);
+        assert_eq!(families[0].samples[0].label("job"), Some("a"));
+        assert_eq!(families[0].samples[0].timestamp_ms, Some(1_700_000_000_000));
+        assert_eq!(families[1].metric_type, MetricType::Untyped);
+        assert_eq!(families[1].samples[0].value, 2500.0);
+    }
+
+    #[test]
+    fn errors() {
+        let cases = [
+            (
+                "foo 1\n# TYPE foo counter\n",
+                2,
+                ParseErrorKind::MetadataAfterSamples,
+            ),
+            (
+                "# HELP foo a\n# HELP foo b\n",
+                2,
+                ParseErrorKind::DuplicateHelp,
+            ),
+            (
+                "# TYPE foo counter\n# TYPE foo gauge\n",
+                2,
+                ParseErrorKind::DuplicateType,
+            ),
+            ("# TYPE foo timer\n", 1, ParseErrorKind::InvalidType),
+            ("# HELP foo a \\t b\n", 1, ParseErrorKind::InvalidEscape),
+            (
+                "foo 1\nbar 1\nfoo 2\n",
+                3,
+                ParseErrorKind::DuplicateFamily("foo".to_owned()),
+            ),
+            ("foo{a=\"\\x\"} 1\n", 1, ParseErrorKind::InvalidEscape),
+            ("foo{a=\"b} 1\n", 1, ParseErrorKind::MalformedLabels),
+            (
+                "foo{a=\"b\" c=\"d\"} 1\n",
+                1,
+                ParseErrorKind::MalformedLabels,
+            ),
+            (
+                "foo{a=\"b\",a=\"c\"} 1\n",
+                1,
+                ParseErrorKind::DuplicateLabel,
+            ),
+            ("foo{1a=\"b\"} 1\n", 1, ParseErrorKind::InvalidLabelName),
+            ("foo one\n", 1, ParseErrorKind::InvalidValue),
+            ("foo 1 soon\n", 1, ParseErrorKind::InvalidTimestamp),
+            ("1foo 1\n", 1, ParseErrorKind::InvalidMetricName),
+            (
+                "# TYPE h histogram\nh_bucket{le=\"1\"} 2\nh_bucket{le=\"2\"} 1\nh_bucket{le=\"+Inf\"} 2\n",
+                3,
+                ParseErrorKind::NonMonotonicBuckets,
+            ),
+            (
+                "# TYPE h histogram\nh_bucket{le=\"2\"} 1\nh_bucket{le=\"1\"} 1\nh_bucket{le=\"+Inf\"} 2\n",
+                3,
+                ParseErrorKind::NonMonotonicBuckets,
+            ),
+            (
+                "# TYPE h histogram\nh_bucket{le=\"1\"} 1\nh_count 1\n",
+                2,
+                ParseErrorKind::MissingInfBucket,
+            ),
+            (
+                "# TYPE h histogram\nh_bucket{le=\"+Inf\"} 1\nh_count 2\n",
+                3,
+                ParseErrorKind::CountMismatch,
+            ),
+            (
+                "# TYPE h histogram\nh_bucket{le=\"x\"} 1\n",
+                2,
+                ParseErrorKind::InvalidBucketBound,
+            ),
+            // the histogram is only validated once the next family starts
+            (
+                "# TYPE h histogram\nh_bucket{le=\"1\"} 2\nh_bucket{le=\"+Inf\"} 1\n# TYPE foo counter\nfoo 1\n",
+                3,
+                ParseErrorKind::NonMonotonicBuckets,
+            ),
+        ];
+
+        for (input, line, kind) in cases {
+            let err = parse_text(input).unwrap_err();
+            assert_eq!((err.line(), err.kind()), (line, &kind), "{input}");
+        }
+    }
+}
@@ -0,0 +1,715 @@
+//! Relabelling of metrics at encode time
+//!
+//! A [`RelabelEncoder`] wraps any other [`Encoding`] and rewrites the labels of every
+//! metric according to a set of [`Relabel`] rules, without changing the [`LabelGroup`] types used to record them.
+//! This is similar to the prometheus `metric_relabel_configs`.
+//!
+//! Dropping or rewriting a label can cause multiple series to end up with the same labels.
+//! The [`AggregatingRelabelEncoder`] instead sums these colliding series together.
+//!
+//! ```
+//! use measured::{
+//!     CounterVec, FixedCardinalityLabel, LabelGroup, MetricGroup,
+//!     relabel::{AggregatingRelabelEncoder, Relabel},
+//!     text::BufferedTextEncoder,
+//! };
+//!
+//! #[derive(LabelGroup)]
+//! #[label(set = RequestLabelSet)]
+//! struct RequestLabels {
+//!     method: Method,
+//!     code: StatusCode,
+//! }
+//!
+//! #[derive(FixedCardinalityLabel, Clone, Copy)]
+//! enum Method { Get, Post }
+//!
+//! #[derive(FixedCardinalityLabel, Clone, Copy)]
+//! enum StatusCode { Ok = 200, NotFound = 404 }
+//!
+//! #[derive(MetricGroup)]
+//! #[metric(new())]
+//! struct MyMetrics {
+//!     /// total requests served
+//!     requests: CounterVec<RequestLabelSet>,
+//! }
+//!
+//! let metrics = MyMetrics::new();
+//! metrics.requests.inc(RequestLabels { method: Method::Get, code: StatusCode::Ok });
+//! metrics.requests.inc(RequestLabels { method: Method::Get, code: StatusCode::NotFound });
+//! metrics.requests.inc(RequestLabels { method: Method::Post, code: StatusCode::Ok });
+//!
+//! let mut enc = AggregatingRelabelEncoder::new(
+//!     BufferedTextEncoder::new(),
+//!     Relabel::new()
+//!         .drop_label("code")
+//!         .replace_value("method", |m| m.to_uppercase()),
+//! );
+//! metrics.collect_group_into(&mut enc).unwrap();
+//! enc.flush().unwrap();
+//!
+//! assert_eq!(
+//!     enc.inner.finish(),
+//!     concat!(
+//!         "# HELP requests total requests served\n",
+//!         "# TYPE requests counter\n",
+//!         "requests{method=\"GET\"} 2\n",
+//!         "requests{method=\"POST\"} 1\n",
+//!     )
+//! );
+//! ```
+
+use std::{
+    any::Any,
+    collections::{HashMap, hash_map::Entry},
+    io::Write,
+};
+
+use bytes::Bytes;
+
+use crate::{
+    label::{
+        LabelGroup, LabelGroupSet, LabelGroupVisitor, LabelName, LabelNameBuf, LabelValue,
+        RenderValue,
+    },
+    metric::{
+        MergeMetric, MetricEncoding, MetricType,
+        group::{DynMetricFamily, DynamicEncoding, DynamicFamilies, EncoderList, Encoding},
+        name::MetricNameEncoder,
+    },
+    negotiate::BufferedEncoding,
+};
+
+/// A set of rules to rewrite labels with.
+///
+/// Rules are matched against the original label names, and are applied in the order they were added.
+#[derive(Default)]
+pub struct Relabel {
+    rules: Vec<Rule>,
+}
+
+struct Rule {
+    label: String,
+    action: Action,
+}
+
+enum Action {
+    Drop,
+    Rename(LabelNameBuf),
+    Replace(Box<dyn Fn(&str) -> String + Send + Sync>),
+}
+
+impl Relabel {
+    /// Create a new set of rules which leaves all labels unchanged
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remove this label from all metrics
+    #[must_use]
+    pub fn drop_label(self, label: impl Into<String>) -> Self {
+        self.with_rule(label, Action::Drop)
+    }
+
+    /// Rename this label
+    ///
+    /// # Panics
+    /// This function will panic if the new name does not conform to the prometheus label name requirements
+    #[must_use]
+    pub fn rename_label(self, label: impl Into<String>, to: impl Into<String>) -> Self {
+        let to = LabelName::from_str(&to.into()).into();
+        self.with_rule(label, Action::Rename(to))
+    }
+
+    /// Rewrite the values of this label
+    #[must_use]
+    pub fn replace_value(
+        self,
+        label: impl Into<String>,
+        f: impl Fn(&str) -> String + Send + Sync + 'static,
+    ) -> Self {
+        self.with_rule(label, Action::Replace(Box::new(f)))
+    }
+
+    fn with_rule(mut self, label: impl Into<String>, action: Action) -> Self {
+        self.rules.push(Rule {
+            label: label.into(),
+            action,
+        });
+        self
+    }
+
+    /// The new name of this label, or `None` if it is dropped
+    fn rename<'a>(&'a self, name: &'a LabelName) -> Option<&'a LabelName> {
+        let mut new_name = name;
+        for rule in self.rules.iter().filter(|rule| rule.label == name.as_str()) {
+            match &rule.action {
+                Action::Drop => return None,
+                Action::Rename(to) => new_name = to,
+                Action::Replace(_) => {}
+            }
+        }
+        Some(new_name)
+    }
+}
+
+/// A [`LabelGroupSet`] with the [`Relabel`] rules applied, for [`MetricEncoding::write_metadata`]
+///
+/// The cardinality is that of the original set, which is an upper bound if rules cause series to collide.
+struct RelabeledSet<'a, S> {
+    set: &'a S,
+    rules: &'a Relabel,
+}
+
+impl<'r, S: LabelGroupSet> LabelGroupSet for RelabeledSet<'r, S> {
+    type Group<'a> = Relabeled<'r, S::Group<'a>>;
+
+    fn cardinality(&self) -> Option<usize> {
+        self.set.cardinality()
+    }
+
+    fn encode_dense(&self, value: Self::Unique) -> Option<usize> {
+        self.set.encode_dense(value)
+    }
+
+    fn decode_dense(&self, value: usize) -> Self::Group<'_> {
+        Relabeled {
+            labels: self.set.decode_dense(value),
+            rules: self.rules,
+        }
+    }
+
+    type Unique = S::Unique;
+
+    fn encode(&self, value: Self::Group<'_>) -> Option<Self::Unique> {
+        self.set.encode(value.labels)
+    }
+
+    fn decode(&self, value: &Self::Unique) -> Self::Group<'_> {
+        Relabeled {
+            labels: self.set.decode(value),
+            rules: self.rules,
+        }
+    }
+
+    fn visit_label_names(&self, v: &mut impl FnMut(&LabelName)) {
+        self.set.visit_label_names(&mut |name| {
+            if let Some(name) = self.rules.rename(name) {
+                v(name);
+            }
+        });
+    }
+}
+
+/// A [`LabelGroup`] with the [`Relabel`] rules applied
+struct Relabeled<'a, L> {
+    labels: L,
+    rules: &'a Relabel,
+}
+
+impl<L: LabelGroup> LabelGroup for Relabeled<'_, L> {
+    fn visit_values(&self, v: &mut impl LabelGroupVisitor) {
+        self.labels.visit_values(&mut RelabelVisitor {
+            rules: self.rules,
+            inner: v,
+        });
+    }
+}
+
+struct RelabelVisitor<'a, V> {
+    rules: &'a Relabel,
+    inner: &'a mut V,
+}
+
+impl<V: LabelGroupVisitor> LabelGroupVisitor for RelabelVisitor<'_, V> {
+    type Output = ();
+
+    fn write_value(&mut self, name: &LabelName, x: &impl LabelValue) {
+        let mut new_name = name;
+        let mut replaced: Option<String> = None;
+
+        for rule in &self.rules.rules {
+            if rule.label != name.as_str() {
+                continue;
+            }
+            match &rule.action {
+                Action::Drop => return,
+                Action::Rename(to) => new_name = to,
+                Action::Replace(f) => {
+                    let value = match &replaced {
+                        Some(value) => f(value),
+                        None => f(&x.visit(RenderValue)),
+                    };
+                    replaced = Some(value);
+                }
+            }
+        }
+
+        match replaced {
+            Some(value) => self.inner.write_value(new_name, &value),
+            None => self.inner.write_value(new_name, x),
+        };
+    }
+}
+
+/// An [`Encoding`] adapter which rewrites the labels of every metric with the [`Relabel`] rules.
+///
+/// Series which end up with the same labels are all written to the inner encoder.
+/// See [`AggregatingRelabelEncoder`] to sum them instead.
+pub struct RelabelEncoder<E> {
+    /// The inner encoder for this relabel encoder.
+    pub inner: E,
+    rules: Relabel,
+}
+
+impl<E: Encoding> RelabelEncoder<E> {
+    /// Create a new relabel encoder.
+    pub fn new(inner: E, rules: Relabel) -> Self {
+        Self { inner, rules }
+    }
+}
+
+impl<E: Encoding> Encoding for RelabelEncoder<E> {
+    type Err = E::Err;
+
+    const MIME_TYPE: &'static str = E::MIME_TYPE;
+
+    fn write_help(&mut self, name: impl MetricNameEncoder, help: &str) -> Result<(), Self::Err> {
+        self.inner.write_help(name, help)
+    }
+}
+
+impl<L: EncoderList, E: Encoding> DynamicEncoding<L> for RelabelEncoder<E>
+where
+    L::Family: DynMetricFamily<Self>,
+{
+    fn collect_dynamic(&mut self, families: DynamicFamilies<'_, L>) -> Result<(), Self::Err> {
+        families.collect_into(self)
+    }
+}
+
+impl<E: BufferedEncoding> BufferedEncoding for RelabelEncoder<E> {
+    fn finish(&mut self) -> Result<Bytes, Self::Err> {
+        self.inner.finish()
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset();
+    }
+}
+
+impl<M: MetricEncoding<E>, E: Encoding> MetricEncoding<RelabelEncoder<E>> for M {
+    fn write_type(name: impl MetricNameEncoder, enc: &mut RelabelEncoder<E>) -> Result<(), E::Err> {
+        M::write_type(name, &mut enc.inner)
+    }
+
+    fn write_metadata(
+        name: impl MetricNameEncoder,
+        metadata: &M::Metadata,
+        labels: &impl LabelGroupSet,
+        enc: &mut RelabelEncoder<E>,
+    ) -> Result<(), E::Err> {
+        let labels = RelabeledSet {
+            set: labels,
+            rules: &enc.rules,
+        };
+        M::write_metadata(name, metadata, &labels, &mut enc.inner)
+    }
+
+    fn collect_into(
+        &self,
+        metadata: &M::Metadata,
+        labels: impl LabelGroup,
+        name: impl MetricNameEncoder,
+        enc: &mut RelabelEncoder<E>,
+    ) -> Result<(), E::Err> {
+        let labels = Relabeled {
+            labels,
+            rules: &enc.rules,
+        };
+        self.collect_into(metadata, labels, name, &mut enc.inner)
+    }
+}
+
+/// An [`Encoding`] adapter which rewrites the labels of every metric with the [`Relabel`] rules,
+/// summing together all series of a metric family which end up with the same labels.
+///
+/// The series of each metric family are buffered until the next family is started.
+/// [`AggregatingRelabelEncoder::flush`] must be called after collecting to write the final family.
+///
+/// Only metrics which implement [`MergeMetric`] can be collected, which are counters and histograms.
+/// Summing gauges is rarely meaningful, so they should be relabelled with a [`RelabelEncoder`] instead.
+///
+/// This should ideally be cached and re-used between collections to reduce re-allocating
+pub struct AggregatingRelabelEncoder<E: Encoding> {
+    /// The inner encoder for this relabel encoder.
+    pub inner: E,
+    rules: Relabel,
+    pending: Option<Box<dyn PendingFamily<E> + Send>>,
+    name_buf: Vec<u8>,
+}
+
+impl<E: Encoding> AggregatingRelabelEncoder<E> {
+    /// Create a new aggregating relabel encoder.
+    pub fn new(inner: E, rules: Relabel) -> Self {
+        Self {
+            inner,
+            rules,
+            pending: None,
+            name_buf: Vec::new(),
+        }
+    }
+
+    /// Write the buffered series of the current metric family to the inner encoder.
+    ///
+    /// # Errors
+    /// Will error if the inner encoder errors
+    pub fn flush(&mut self) -> Result<(), E::Err> {
+        match self.pending.take() {
+            Some(pending) => pending.flush(&mut self.inner),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<E: Encoding> Encoding for AggregatingRelabelEncoder<E> {
+    type Err = E::Err;
+
+    const MIME_TYPE: &'static str = E::MIME_TYPE;
+
+    fn write_help(&mut self, name: impl MetricNameEncoder, help: &str) -> Result<(), Self::Err> {
+        self.flush()?;
+        self.inner.write_help(name, help)
+    }
+}
+
+impl<L: EncoderList, E: Encoding> DynamicEncoding<L> for AggregatingRelabelEncoder<E>
+where
+    L::Family: DynMetricFamily<Self>,
+{
+    fn collect_dynamic(&mut self, families: DynamicFamilies<'_, L>) -> Result<(), Self::Err> {
+        families.collect_into(self)
+    }
+}
+
+impl<E: BufferedEncoding> BufferedEncoding for AggregatingRelabelEncoder<E> {
+    fn finish(&mut self) -> Result<Bytes, Self::Err> {
+        self.flush()?;
+        self.inner.finish()
+    }
+
+    fn reset(&mut self) {
+        self.pending = None;
+        self.inner.reset();
+    }
+}
+
+impl<M, E> MetricEncoding<AggregatingRelabelEncoder<E>> for M
+where
+    M: MetricEncoding<E> + MergeMetric + Send + 'static,
+    M::Metadata: Clone + Send,
+    E: Encoding,
+{
+    fn write_type(
+        name: impl MetricNameEncoder,
+        enc: &mut AggregatingRelabelEncoder<E>,
+    ) -> Result<(), E::Err> {
+        enc.flush()?;
+        M::write_type(name, &mut enc.inner)
+    }
+
+    fn write_metadata(
+        name: impl MetricNameEncoder,
+        metadata: &M::Metadata,
+        labels: &impl LabelGroupSet,
+        enc: &mut AggregatingRelabelEncoder<E>,
+    ) -> Result<(), E::Err> {
+        enc.flush()?;
+        let labels = RelabeledSet {
+            set: labels,
+            rules: &enc.rules,
+        };
+        M::write_metadata(name, metadata, &labels, &mut enc.inner)
+    }
+
+    fn collect_into(
+        &self,
+        metadata: &M::Metadata,
+        labels: impl LabelGroup,
+        name: impl MetricNameEncoder,
+        enc: &mut AggregatingRelabelEncoder<E>,
+    ) -> Result<(), E::Err> {
+        enc.name_buf.clear();
+        name.encode_utf8(&mut enc.name_buf)
+            .expect("writing into a vec should not error");
+        let name = std::str::from_utf8(&enc.name_buf).expect("metric names should be utf8");
+
+        // a different family is being collected without a call to write_type.
+        let is_current = |p: &mut Box<dyn PendingFamily<E> + Send>| {
+            p.as_any()
+                .downcast_mut::<Pending<M>>()
+                .is_some_and(|p| p.name == name)
+        };
+        if enc.pending.as_mut().is_some_and(|p| !is_current(p)) {
+            let pending = enc.pending.take().expect("pending should be set");
+            pending.flush(&mut enc.inner)?;
+        }
+
+        let pending = enc
+            .pending
+            .get_or_insert_with(|| {
+                Box::new(Pending::<M> {
+                    name: name.to_owned(),
+                    metadata: metadata.clone(),
+                    index: HashMap::new(),
+                    series: Vec::new(),
+                })
+            })
+            .as_any()
+            .downcast_mut::<Pending<M>>()
+            .expect("pending family should have the same metric type");
+
+        let mut capture = CaptureLabels {
+            key: String::new(),
+            labels: Vec::new(),
+        };
+        Relabeled {
+            labels,
+            rules: &enc.rules,
+        }
+        .visit_values(&mut capture);
+
+        match pending.index.entry(capture.key) {
+            Entry::Occupied(e) => pending.series[*e.get()].1.merge(self),
+            Entry::Vacant(e) => {
+                let mut metric = M::default();
+                metric.merge(self);
+                e.insert(pending.series.len());
+                pending.series.push((capture.labels, metric));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The buffered series of a metric family, waiting to be written
+trait PendingFamily<E: Encoding> {
+    fn flush(self: Box<Self>, enc: &mut E) -> Result<(), E::Err>;
+    fn as_any(&mut self) -> &mut dyn Any;
+}
+
+struct Pending<M: MetricType> {
+    name: String,
+    metadata: M::Metadata,
+    index: HashMap<String, usize>,
+    series: Vec<(Vec<(LabelNameBuf, String)>, M)>,
+}
+
+impl<M: MetricEncoding<E> + 'static, E: Encoding> PendingFamily<E> for Pending<M> {
+    fn flush(self: Box<Self>, enc: &mut E) -> Result<(), E::Err> {
+        for (labels, metric) in &self.series {
+            metric.collect_into(
+                &self.metadata,
+                StoredLabels(labels),
+                RenderedName(&self.name),
+                enc,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn as_any(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Records the rendered labels of a series
+struct CaptureLabels {
+    key: String,
+    labels: Vec<(LabelNameBuf, String)>,
+}
+
+impl LabelGroupVisitor for CaptureLabels {
+    type Output = ();
+
+    fn write_value(&mut self, name: &LabelName, x: &impl LabelValue) {
+        let value = x.visit(RenderValue);
+        self.key.push_str(name.as_str());
+        self.key.push('\0');
+        self.key.push_str(&value);
+        self.key.push('\0');
+        self.labels.push((name.into(), value));
+    }
+}
+
+struct StoredLabels<'a>(&'a [(LabelNameBuf, String)]);
+
+impl LabelGroup for StoredLabels<'_> {
+    fn visit_values(&self, v: &mut impl LabelGroupVisitor) {
+        for (name, value) in self.0 {
+            v.write_value(name, value);
+        }
+    }
+}
+
+struct RenderedName<'a>(&'a str);
+
+impl MetricNameEncoder for RenderedName<'_> {
+    fn encode_utf8(&self, b: &mut impl Write) -> std::io::Result<()> {
+        b.write_all(self.0.as_bytes())
+    }
+
+    fn encode_len(&self) -> usize {
+        self.0.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use measured_derive::{FixedCardinalityLabel, LabelGroup, MetricGroup};
+
+    use crate::{
+        CounterVec, HistogramVec,
+        metric::{
+            MetricFamilyEncoding, group::MetricGroup, histogram::Thresholds, name::MetricName,
+        },
+        schema::SchemaEncoder,
+        text::BufferedTextEncoder,
+    };
+
+    use super::{AggregatingRelabelEncoder, Relabel, RelabelEncoder};
+
+    #[derive(Clone, Copy, PartialEq, Debug, LabelGroup)]
+    #[label(crate = crate, set = RequestLabelSet)]
+    struct RequestLabels {
+        method: Method,
+        code: StatusCode,
+    }
+
+    #[derive(Clone, Copy, PartialEq, Debug, FixedCardinalityLabel)]
+    #[label(crate = crate)]
+    enum Method {
+        Get,
+        Post,
+    }
+
+    #[derive(Clone, Copy, PartialEq, Debug, FixedCardinalityLabel)]
+    #[label(crate = crate)]
+    enum StatusCode {
+        Ok = 200,
+        NotFound = 404,
+    }
+
+    #[derive(MetricGroup)]
+    #[metric(crate = crate)]
+    struct Metrics {
+        /// total requests
+        requests: CounterVec<RequestLabelSet>,
+        /// request latency
+        latency: HistogramVec<RequestLabelSet, 2>,
+    }
+
+    fn metrics() -> Metrics {
+        let metrics = Metrics {
+            requests: CounterVec::new(),
+            latency: HistogramVec::with_metadata(Thresholds::with_buckets([0.1, 1.0])),
+        };
+
+        for (method, code, latency) in [
+            (Method::Get, StatusCode::Ok, 0.05),
+            (Method::Get, StatusCode::NotFound, 0.5),
+            (Method::Post, StatusCode::Ok, 2.0),
+            (Method::Get, StatusCode::Ok, 0.5),
+        ] {
+            let labels = RequestLabels { method, code };
+            metrics.requests.inc(labels);
+            metrics.latency.observe(labels, latency);
+        }
+
+        metrics
+    }
+
+    #[test]
+    fn rename() {
+        let mut enc = RelabelEncoder::new(
+            BufferedTextEncoder::new(),
+            Relabel::new()
+                .rename_label("code", "status")
+                .replace_value("code", |code| format!("{}xx", &code[..1])),
+        );
+        metrics()
+            .requests
+            .collect_family_into(MetricName::from_str("requests"), &mut enc)
+            .unwrap();
+
+        assert_eq!(
+            std::str::from_utf8(&enc.inner.finish()).unwrap(),
+            r#"# TYPE requests counter
+requests{method="get",status="2xx"} 2
+requests{method="get",status="4xx"} 1
+requests{method="post",status="2xx"} 1
+"#
+        );
+    }
+
+    #[test]
+    fn aggregate() {
+        let mut enc = AggregatingRelabelEncoder::new(
+            BufferedTextEncoder::new(),
+            Relabel::new().drop_label("method"),
+        );
+        metrics().collect_group_into(&mut enc).unwrap();
+        enc.flush().unwrap();
+
+        assert_eq!(
+            std::str::from_utf8(&enc.inner.finish()).unwrap(),
+            r#"# HELP requests total requests
+# TYPE requests counter
+requests{code="200"} 3
+requests{code="404"} 1
+
+# HELP latency request latency
+# TYPE latency histogram
+latency_bucket{code="200",le="0.1"} 1
+latency_bucket{code="200",le="1.0"} 2
+latency_bucket{code="200",le="+Inf"} 3
+latency_sum{code="200"} 2.55
+latency_count{code="200"} 3
+latency_bucket{code="404",le="0.1"} 0
+latency_bucket{code="404",le="1.0"} 1
+latency_bucket{code="404",le="+Inf"} 1
+latency_sum{code="404"} 0.5
+latency_count{code="404"} 1
+"#
+        );
+    }
+
+    #[test]
+    fn metadata() {
+        let mut enc = AggregatingRelabelEncoder::new(
+            SchemaEncoder::new(),
+            Relabel::new()
+                .drop_label("method")
+                .rename_label("code", "status")
+                .replace_value("code", |code| format!("{}xx", &code[..1])),
+        );
+        Metrics {
+            requests: CounterVec::new(),
+            latency: HistogramVec::with_metadata(Thresholds::with_buckets([0.1, 1.0])),
+        }
+        .collect_group_into(&mut enc)
+        .unwrap();
+        enc.flush().unwrap();
+
+        let schema = enc.inner.finish();
+        for name in ["requests", "latency"] {
+            let family = schema.family(name).unwrap();
+            assert_eq!(family.labels(), ["status"]);
+            assert_eq!(
+                family.label_values("status").unwrap(),
+                ["2xx".to_owned(), "4xx".to_owned()]
+            );
+        }
+    }
+}
@@ -0,0 +1,745 @@
+//! Export of the structure of a metric group, for detecting changes in CI
+//!
+//! A [`SchemaEncoder`] records the name, type, help text, label names, cardinality and
+//! histogram buckets of every metric family in a [`MetricGroup`], without recording any values.
+//! The resulting [`Schema`] can be written as JSON or TOML with a stable layout,
+//! and checked in to compare against in a unit test.
+//!
+//! The schema can also be rendered as a Markdown catalog with [`Schema::to_markdown`],
+//! to include in documentation or runbooks.
+//!
+//! ```
+//! use measured::{CounterVec, FixedCardinalityLabel, LabelGroup, MetricGroup, schema::schema};
+//!
+//! #[derive(LabelGroup)]
+//! #[label(set = RequestLabelSet)]
+//! struct RequestLabels {
+//!     method: Method,
+//! }
+//!
+//! #[derive(FixedCardinalityLabel, Clone, Copy)]
+//! enum Method { Get, Post }
+//!
+//! #[derive(MetricGroup)]
+//! #[metric(new())]
+//! struct MyMetrics {
+//!     /// total requests served
+//!     requests_total: CounterVec<RequestLabelSet>,
+//! }
+//!
+//! let schema = schema(&MyMetrics::new());
+//!
+//! assert_eq!(
+//!     schema.to_toml(),
+//!     concat!(
+//!         "[[families]]\n",
+//!         "name = \"requests_total\"\n",
+//!         "type = \"counter\"\n",
+//!         "help = \"total requests served\"\n",
+//!         "labels = [\"method\"]\n",
+//!         "cardinality = 2\n",
+//!     ),
+//! );
+//! ```
+
+use std::convert::Infallible;
+
+use crate::{
+    MetricGroup,
+    label::{
+        LabelGroup, LabelGroupSet, LabelGroupVisitor, LabelName, LabelValue, LabelVisitor,
+        RenderValue, value::format_float,
+    },
+    metric::{
+        MetricEncoding,
+        counter::CounterState,
+        gauge::{FloatGaugeState, GaugeState},
+        group::{DynMetricFamily, DynamicEncoding, DynamicFamilies, EncoderList, Encoding},
+        histogram::{HistogramState, Thresholds},
+        name::MetricNameEncoder,
+        timestamp::Timestamped,
+    },
+    text::{MetricType, write_str_literal},
+};
+
+/// Collect the metric group into a new [`SchemaEncoder`], returning the schema of all the metric families.
+pub fn schema<G: MetricGroup<SchemaEncoder> + ?Sized>(group: &G) -> Schema {
+    let mut enc = SchemaEncoder::new();
+    let Ok(()) = group.collect_group_into(&mut enc);
+    enc.finish()
+}
+
+/// The structure of all the metric families in a metric group. See [`SchemaEncoder`]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Schema {
+    families: Vec<FamilySchema>,
+}
+
+/// The structure of a single metric family
+#[derive(Clone, Debug, PartialEq)]
+pub struct FamilySchema {
+    name: String,
+    typ: MetricType,
+    help: Option<String>,
+    labels: Vec<String>,
+    /// The possible values of each label, if the label set has a fixed cardinality
+    label_values: Vec<Vec<String>>,
+    cardinality: Option<usize>,
+    buckets: Option<Vec<f64>>,
+}
+
+/// Label sets with a larger cardinality than this do not have their values recorded.
+const MAX_ENUMERATED_CARDINALITY: usize = 4096;
+
+impl Schema {
+    /// The metric families, sorted by name
+    pub fn families(&self) -> &[FamilySchema] {
+        &self.families
+    }
+
+    /// Get the metric family with this name
+    pub fn family(&self, name: &str) -> Option<&FamilySchema> {
+        self.families
+            .binary_search_by(|f| f.name.as_str().cmp(name))
+            .ok()
+            .map(|i| &self.families[i])
+    }
+
+    /// Write the schema as pretty printed JSON.
+    ///
+    /// The output only depends on the structure of the metrics, so it is suitable to be checked in and diffed.
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        out.push_str("{\n  \"families\": [");
+        for (i, family) in self.families.iter().enumerate() {
+            out.push_str(if i == 0 { "\n    {" } else { ",\n    {" });
+            family.write_fields(&mut out, true);
+            out.push_str("\n    }");
+        }
+        if !self.families.is_empty() {
+            out.push_str("\n  ");
+        }
+        out.push_str("]\n}\n");
+        out
+    }
+
+    /// Write the schema as TOML, with an array of tables named `families`.
+    ///
+    /// The output only depends on the structure of the metrics, so it is suitable to be checked in and diffed.
+    pub fn to_toml(&self) -> String {
+        let mut out = String::new();
+        for (i, family) in self.families.iter().enumerate() {
+            if i > 0 {
+                out.push('\n');
+            }
+            out.push_str("[[families]]");
+            family.write_fields(&mut out, false);
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Render the schema as a Markdown table, with a row for each metric family.
+    ///
+    /// Labels with a fixed set of values have their values listed.
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::new();
+        out.push_str("| Name | Type | Help | Labels | Buckets |\n");
+        out.push_str("| ---- | ---- | ---- | ------ | ------- |\n");
+        for family in &self.families {
+            out.push_str("| `");
+            out.push_str(&family.name);
+            out.push_str("` | ");
+            out.push_str(family.typ.as_str());
+            out.push_str(" | ");
+            write_markdown(&mut out, family.help.as_deref().unwrap_or_default());
+            out.push_str(" |");
+            for (i, label) in family.labels.iter().enumerate() {
+                out.push_str(if i == 0 { " `" } else { "<br>`" });
+                out.push_str(label);
+                out.push('`');
+                if let Some(values) = family.label_values.get(i) {
+                    for (j, value) in values.iter().enumerate() {
+                        out.push_str(if j == 0 { ": " } else { ", " });
+                        out.push('`');
+                        write_markdown(&mut out, value);
+                        out.push('`');
+                    }
+                }
+            }
+            out.push_str(" |");
+            if let Some(buckets) = &family.buckets {
+                for (i, &le) in buckets.iter().enumerate() {
+                    out.push_str(if i == 0 { " " } else { ", " });
+                    out.push_str(&RenderValue.write_float(le));
+                }
+            }
+            out.push_str(" |\n");
+        }
+        out
+    }
+}
+
+/// Table cells cannot contain newlines or unescaped pipes
+fn write_markdown(out: &mut String, s: &str) {
+    for c in s.chars() {
+        match c {
+            '|' => out.push_str("\\|"),
+            '\n' => out.push_str("<br>"),
+            c => out.push(c),
+        }
+    }
+}
+
+impl FamilySchema {
+    /// The full name of the metric family
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The type of the metric family
+    pub fn metric_type(&self) -> MetricType {
+        self.typ
+    }
+
+    /// The help text of the metric family, if any
+    pub fn help(&self) -> Option<&str> {
+        self.help.as_deref()
+    }
+
+    /// The label names of the metric family, in order
+    pub fn labels(&self) -> &[String] {
+        &self.labels
+    }
+
+    /// The possible values of the label, in order.
+    ///
+    /// Only available for label sets with a fixed cardinality, such as [`FixedCardinalityLabel`](crate::FixedCardinalityLabel)s.
+    pub fn label_values(&self, label: &str) -> Option<&[String]> {
+        let i = self.labels.iter().position(|l| l == label)?;
+        self.label_values.get(i).map(Vec::as_slice)
+    }
+
+    /// The cardinality of the label set, if it has a fixed cardinality. See [`LabelGroupSet::cardinality`]
+    pub fn cardinality(&self) -> Option<usize> {
+        self.cardinality
+    }
+
+    /// The bucket thresholds, if this is a histogram
+    pub fn buckets(&self) -> Option<&[f64]> {
+        self.buckets.as_deref()
+    }
+
+    /// JSON and TOML only differ in their separators, so this writes the fields for both
+    fn write_fields(&self, out: &mut String, json: bool) {
+        let (sep, comma, eq) = if json {
+            ("\n      ", ",", ": ")
+        } else {
+            ("\n", "", " = ")
+        };
+        let field = |out: &mut String, key: &str| {
+            out.push_str(sep);
+            write_str_literal(out, key, json);
+            out.push_str(eq);
+        };
+
+        field(out, "name");
+        write_str_literal(out, &self.name, true);
+        out.push_str(comma);
+        field(out, "type");
+        write_str_literal(out, self.typ.as_str(), true);
+        if let Some(help) = &self.help {
+            out.push_str(comma);
+            field(out, "help");
+            write_str_literal(out, help, true);
+        }
+        out.push_str(comma);
+        field(out, "labels");
+        out.push('[');
+        for (i, label) in self.labels.iter().enumerate() {
+            if i > 0 {
+                out.push_str(", ");
+            }
+            write_str_literal(out, label, true);
+        }
+        out.push(']');
+        if let Some(cardinality) = self.cardinality {
+            out.push_str(comma);
+            field(out, "cardinality");
+            out.push_str(itoa::Buffer::new().format(cardinality));
+        }
+        if let Some(buckets) = &self.buckets {
+            out.push_str(comma);
+            field(out, "buckets");
+            out.push('[');
+            for (i, &le) in buckets.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_float(out, le);
+            }
+            out.push(']');
+        }
+    }
+}
+
+/// JSON has no representation of infinity or NaN, so they are written as strings like in the text format
+fn write_float(out: &mut String, x: f64) {
+    let buf = &mut ryu::Buffer::new();
+    if x.is_finite() {
+        out.push_str(format_float(x, buf));
+    } else {
+        write_str_literal(out, format_float(x, buf), true);
+    }
+}
+
+/// Serializes the buckets the same way as [`write_float`]
+struct Buckets<'a>(&'a [f64]);
+
+impl serde::Serialize for Buckets<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeSeq;
+
+        let mut s = serializer.serialize_seq(Some(self.0.len()))?;
+        for &le in self.0 {
+            if le.is_finite() {
+                s.serialize_element(&le)?;
+            } else {
+                s.serialize_element(format_float(le, &mut ryu::Buffer::new()))?;
+            }
+        }
+        s.end()
+    }
+}
+
+impl serde::Serialize for Schema {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut s = serializer.serialize_struct("Schema", 1)?;
+        s.serialize_field("families", &self.families)?;
+        s.end()
+    }
+}
+
+impl serde::Serialize for FamilySchema {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut s = serializer.serialize_struct("FamilySchema", 6)?;
+        s.serialize_field("name", &self.name)?;
+        s.serialize_field("type", self.typ.as_str())?;
+        match &self.help {
+            Some(help) => s.serialize_field("help", help)?,
+            None => s.skip_field("help")?,
+        }
+        s.serialize_field("labels", &self.labels)?;
+        match self.cardinality {
+            Some(cardinality) => s.serialize_field("cardinality", &cardinality)?,
+            None => s.skip_field("cardinality")?,
+        }
+        match &self.buckets {
+            Some(buckets) => s.serialize_field("buckets", &Buckets(buckets))?,
+            None => s.skip_field("buckets")?,
+        }
+        s.end()
+    }
+}
+
+/// An [`Encoding`] which records the [`Schema`] of the metric families, ignoring all values.
+///
+/// Label names and cardinality are taken from the [`LabelGroupSet`] of each metric family.
+/// Metric families written without a label set only record their label names once a value is collected.
+#[derive(Default)]
+pub struct SchemaEncoder {
+    families: Vec<FamilySchema>,
+    name_buf: Vec<u8>,
+}
+
+impl SchemaEncoder {
+    /// Create a new schema encoder
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take the recorded schema, and reset the encoder for another collection.
+    ///
+    /// If a metric family was written more than once, only the first is kept.
+    pub fn finish(&mut self) -> Schema {
+        let mut families = std::mem::take(&mut self.families);
+        families.sort_by(|a, b| a.name.cmp(&b.name));
+        families.dedup_by(|a, b| a.name == b.name);
+        Schema { families }
+    }
+
+    /// Record the type of the metric family, as written by [`MetricEncoding::write_type`]
+    pub fn write_type(&mut self, name: &impl MetricNameEncoder, typ: MetricType) {
+        self.family(name).typ = typ;
+    }
+
+    /// Record the label set and histogram buckets of the metric family
+    pub fn write_label_set(
+        &mut self,
+        name: &impl MetricNameEncoder,
+        labels: &impl LabelGroupSet,
+        buckets: Option<&[f64]>,
+    ) {
+        // labels can be omitted from some groups, so the values are matched up by name
+        struct Values<'a>(&'a [String], &'a mut [Vec<String>]);
+        impl LabelGroupVisitor for Values<'_> {
+            type Output = ();
+            fn write_value(&mut self, name: &LabelName, x: &impl LabelValue) {
+                let value = x.visit(RenderValue);
+                if let Some(i) = self.0.iter().position(|n| n == name.as_str())
+                    && !self.1[i].contains(&value)
+                {
+                    self.1[i].push(value);
+                }
+            }
+        }
+
+        let family = self.family(name);
+        family.labels.clear();
+        labels.visit_label_names(&mut |name| family.labels.push(name.as_str().to_owned()));
+        family.cardinality = labels.cardinality();
+        family.buckets = buckets.map(<[f64]>::to_vec);
+
+        family.label_values.clear();
+        if let Some(cardinality) = family.cardinality
+            && cardinality <= MAX_ENUMERATED_CARDINALITY
+        {
+            family.label_values = vec![Vec::new(); family.labels.len()];
+            for i in 0..cardinality {
+                let group = labels.decode_dense(i);
+                group.visit_values(&mut Values(&family.labels, &mut family.label_values));
+            }
+        }
+    }
+
+    /// Record the label names of the metric family from a single sample, if they are not yet known
+    pub fn write_labels(&mut self, name: &impl MetricNameEncoder, labels: impl LabelGroup) {
+        struct Names<'a>(&'a mut Vec<String>);
+        impl LabelGroupVisitor for Names<'_> {
+            type Output = ();
+            fn write_value(&mut self, name: &LabelName, _x: &impl LabelValue) {
+                self.0.push(name.as_str().to_owned());
+            }
+        }
+
+        let family = self.family(name);
+        if family.labels.is_empty() {
+            labels.visit_values(&mut Names(&mut family.labels));
+        }
+    }
+
+    /// Get the current family, or start a new family if the name has changed
+    fn family(&mut self, name: &impl MetricNameEncoder) -> &mut FamilySchema {
+        self.name_buf.clear();
+        // writing into a vec cannot fail
+        let _ = name.encode_utf8(&mut self.name_buf);
+        let name = String::from_utf8_lossy(&self.name_buf);
+
+        if self.families.last().is_none_or(|f| f.name != name) {
+            self.families.push(FamilySchema {
+                name: name.into_owned(),
+                typ: MetricType::Untyped,
+                help: None,
+                labels: Vec::new(),
+                label_values: Vec::new(),
+                cardinality: None,
+                buckets: None,
+            });
+        }
+        self.families.last_mut().unwrap()
+    }
+}
+
+impl Encoding for SchemaEncoder {
+    type Err = Infallible;
+
+    const MIME_TYPE: &'static str = "application/json";
+
+    fn write_help(&mut self, name: impl MetricNameEncoder, help: &str) -> Result<(), Infallible> {
+        self.family(&name).help = Some(help.to_owned());
+        Ok(())
+    }
+}
+
+impl<L: EncoderList> DynamicEncoding<L> for SchemaEncoder
+where
+    L::Family: DynMetricFamily<Self>,
+{
+    fn collect_dynamic(&mut self, families: DynamicFamilies<'_, L>) -> Result<(), Self::Err> {
+        families.collect_into(self)
+    }
+}
+
+macro_rules! schema_metric {
+    ($state:ty, $typ:expr) => {
+        impl MetricEncoding<SchemaEncoder> for $state {
+            fn write_type(
+                name: impl MetricNameEncoder,
+                enc: &mut SchemaEncoder,
+            ) -> Result<(), Infallible> {
+                enc.write_type(&name, $typ);
+                Ok(())
+            }
+            fn write_metadata(
+                name: impl MetricNameEncoder,
+                _metadata: &(),
+                labels: &impl LabelGroupSet,
+                enc: &mut SchemaEncoder,
+            ) -> Result<(), Infallible> {
+                enc.write_label_set(&name, labels, None);
+                Ok(())
+            }
+            fn collect_into(
+                &self,
+                _metadata: &(),
+                labels: impl LabelGroup,
+                name: impl MetricNameEncoder,
+                enc: &mut SchemaEncoder,
+            ) -> Result<(), Infallible> {
+                enc.write_labels(&name, labels);
+                Ok(())
+            }
+        }
+    };
+}
+
+schema_metric!(CounterState, MetricType::Counter);
+schema_metric!(GaugeState, MetricType::Gauge);
+schema_metric!(FloatGaugeState, MetricType::Gauge);
+
+impl<const N: usize> MetricEncoding<SchemaEncoder> for HistogramState<N> {
+    fn write_type(name: impl MetricNameEncoder, enc: &mut SchemaEncoder) -> Result<(), Infallible> {
+        enc.write_type(&name, MetricType::Histogram);
+        Ok(())
+    }
+    fn write_metadata(
+        name: impl MetricNameEncoder,
+        metadata: &Thresholds<N>,
+        labels: &impl LabelGroupSet,
+        enc: &mut SchemaEncoder,
+    ) -> Result<(), Infallible> {
+        enc.write_label_set(&name, labels, Some(metadata.get()));
+        Ok(())
+    }
+    fn collect_into(
+        &self,
+        _metadata: &Thresholds<N>,
+        labels: impl LabelGroup,
+        name: impl MetricNameEncoder,
+        enc: &mut SchemaEncoder,
+    ) -> Result<(), Infallible> {
+        enc.write_labels(&name, labels);
+        Ok(())
+    }
+}
+
+impl<M: MetricEncoding<SchemaEncoder>> MetricEncoding<SchemaEncoder> for Timestamped<M> {
+    fn write_type(name: impl MetricNameEncoder, enc: &mut SchemaEncoder) -> Result<(), Infallible> {
+        M::write_type(name, enc)
+    }
+    fn write_metadata(
+        name: impl MetricNameEncoder,
+        metadata: &M::Metadata,
+        labels: &impl LabelGroupSet,
+        enc: &mut SchemaEncoder,
+    ) -> Result<(), Infallible> {
+        M::write_metadata(name, metadata, labels, enc)
+    }
+    fn collect_into(
+        &self,
+        metadata: &M::Metadata,
+        labels: impl LabelGroup,
+        name: impl MetricNameEncoder,
+        enc: &mut SchemaEncoder,
+    ) -> Result<(), Infallible> {
+        self.metric().collect_into(metadata, labels, name, enc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use measured_derive::{FixedCardinalityLabel, LabelGroup, MetricGroup};
+
+    use super::schema;
+    use crate::{CounterVec, Histogram, metric::histogram::Thresholds};
+    #[cfg(feature = "lasso")]
+    use crate::{Gauge, HistogramVec, text::MetricType};
+
+    #[derive(FixedCardinalityLabel, Clone, Copy)]
+    #[label(crate = crate)]
+    enum Method {
+        Get,
+        Post,
+        Put,
+    }
+
+    #[cfg(feature = "lasso")]
+    #[derive(LabelGroup)]
+    #[label(crate = crate, set = RequestLabelSet)]
+    struct RequestLabels<'a> {
+        method: Method,
+        #[label(dynamic_with = lasso::ThreadedRodeo, default)]
+        route: &'a str,
+    }
+
+    #[cfg(feature = "lasso")]
+    #[derive(MetricGroup)]
+    #[metric(crate = crate)]
+    struct Metrics {
+        #[metric(namespace = "http")]
+        http: HttpMetrics,
+        /// "in flight" requests
+        in_flight: Gauge,
+    }
+
+    #[cfg(feature = "lasso")]
+    #[derive(MetricGroup)]
+    #[metric(crate = crate)]
+    struct HttpMetrics {
+        /// request latency
+        duration_seconds: HistogramVec<RequestLabelSet, 2>,
+    }
+
+    #[cfg(feature = "lasso")]
+    #[test]
+    fn export() {
+        let metrics = Metrics {
+            http: HttpMetrics {
+                duration_seconds: HistogramVec::with_label_set_and_metadata(
+                    RequestLabelSet::default(),
+                    Thresholds::with_buckets([0.5, 1.0]),
+                ),
+            },
+            in_flight: Gauge::new(),
+        };
+
+        let schema = schema(&metrics);
+
+        let http = schema.family("http_duration_seconds").unwrap();
+        assert_eq!(http.metric_type(), MetricType::Histogram);
+        assert_eq!(http.labels(), ["method", "route"]);
+        assert_eq!(http.cardinality(), None);
+        assert_eq!(http.buckets(), Some(&[0.5, 1.0][..]));
+        // the values of unbounded label sets are not enumerated
+        assert_eq!(http.label_values("method"), None);
+
+        let json = r#"{
+  "families": [
+    {
+      "name": "http_duration_seconds",
+      "type": "histogram",
+      "help": "request latency",
+      "labels": ["method", "route"],
+      "buckets": [0.5, 1.0]
+    },
+    {
+      "name": "in_flight",
+      "type": "gauge",
+      "help": "\"in flight\" requests",
+      "labels": [],
+      "cardinality": 1
+    }
+  ]
+}
+"#;
+        assert_eq!(schema.to_json(), json);
+        assert_eq!(
+            serde_json::to_value(&schema).unwrap(),
+            serde_json::from_str::<serde_json::Value>(json).unwrap(),
+        );
+
+        let toml = r#"[[families]]
+name = "http_duration_seconds"
+type = "histogram"
+help = "request latency"
+labels = ["method", "route"]
+buckets = [0.5, 1.0]
+
+[[families]]
+name = "in_flight"
+type = "gauge"
+help = "\"in flight\" requests"
+labels = []
+cardinality = 1
+"#;
+        assert_eq!(schema.to_toml(), toml);
+    }
+
+    #[derive(LabelGroup)]
+    #[label(crate = crate, set = MethodLabelSet)]
+    struct MethodLabels {
+        method: Method,
+        #[label(rename = "ok")]
+        success: Method,
+    }
+
+    #[derive(MetricGroup)]
+    #[metric(crate = crate)]
+    struct CatalogMetrics {
+        /// request latency
+        duration_seconds: Histogram<2>,
+        /// total requests | by method
+        requests_total: CounterVec<MethodLabelSet>,
+    }
+
+    #[test]
+    fn markdown() {
+        let metrics = CatalogMetrics {
+            duration_seconds: Histogram::with_metadata(Thresholds::with_buckets([0.5, 1.0])),
+            requests_total: CounterVec::with_label_set(MethodLabelSet::new()),
+        };
+
+        let schema = schema(&metrics);
+
+        let requests = schema.family("requests_total").unwrap();
+        assert_eq!(requests.cardinality(), Some(9));
+        assert_eq!(
+            requests.label_values("method").unwrap(),
+            ["get", "post", "put"]
+        );
+
+        assert_eq!(
+            schema.to_markdown(),
+            r"| Name | Type | Help | Labels | Buckets |
+| ---- | ---- | ---- | ------ | ------- |
+| `duration_seconds` | histogram | request latency | | 0.5, 1.0 |
+| `requests_total` | counter | total requests \| by method | `method`: `get`, `post`, `put`<br>`ok`: `get`, `post`, `put` | |
+"
+        );
+    }
+
+    #[derive(MetricGroup)]
+    #[metric(crate = crate)]
+    struct Unbounded {
+        /// offsets
+        offset: Histogram<3>,
+    }
+
+    #[test]
+    fn infinite_buckets() {
+        let metrics = Unbounded {
+            offset: Histogram::with_metadata(Thresholds::with_buckets([
+                f64::NEG_INFINITY,
+                0.0,
+                f64::INFINITY,
+            ])),
+        };
+
+        let schema = schema(&metrics);
+        let json = schema.to_json();
+        assert!(json.contains(r#""buckets": ["-Inf", 0.0, "+Inf"]"#));
+        assert_eq!(
+            serde_json::to_value(&schema).unwrap(),
+            serde_json::from_str::<serde_json::Value>(&json).unwrap(),
+        );
+        assert!(
+            schema
+                .to_toml()
+                .contains(r#"buckets = ["-Inf", 0.0, "+Inf"]"#)
+        );
+    }
+}
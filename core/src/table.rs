@@ -0,0 +1,472 @@
+//! Human readable tables, for CLI and debug output
+//!
+//! The [`TableEncoder`] renders each metric family as an aligned table, with a row per series.
+//! Histograms are rendered with the count of each bucket (not cumulative), followed by a sparkline
+//! of the distribution.
+//!
+//! ```
+//! use measured::{CounterVec, FixedCardinalityLabel, LabelGroup, MetricGroup, table::TableEncoder};
+//!
+//! #[derive(LabelGroup)]
+//! #[label(set = RequestLabelSet)]
+//! struct RequestLabels {
+//!     method: Method,
+//! }
+//!
+//! #[derive(FixedCardinalityLabel, Clone, Copy)]
+//! enum Method { Get, Post }
+//!
+//! #[derive(MetricGroup)]
+//! #[metric(new())]
+//! struct MyMetrics {
+//!     /// total requests served
+//!     requests_total: CounterVec<RequestLabelSet>,
+//! }
+//!
+//! let metrics = MyMetrics::new();
+//! metrics.requests_total.inc_by(RequestLabels { method: Method::Get }, 12);
+//! metrics.requests_total.inc(RequestLabels { method: Method::Post });
+//!
+//! let mut enc = TableEncoder::new();
+//! metrics.collect_group_into(&mut enc).unwrap();
+//!
+//! assert_eq!(
+//!     enc.finish(),
+//!     concat!(
+//!         "requests_total (counter): total requests served\n",
+//!         "  method  value\n",
+//!         "  get     12\n",
+//!         "  post    1\n",
+//!     ),
+//! );
+//! ```
+
+use std::convert::Infallible;
+
+use crate::{
+    label::{LabelGroup, LabelGroupVisitor, LabelName, LabelValue, RenderValue},
+    metric::{
+        MetricEncoding,
+        counter::CounterState,
+        gauge::{FloatGaugeState, GaugeState},
+        group::{DynMetricFamily, DynamicEncoding, DynamicFamilies, EncoderList, Encoding},
+        histogram::{HistogramState, Thresholds},
+        name::MetricNameEncoder,
+        timestamp::Timestamped,
+    },
+    text::MetricType,
+};
+
+/// The levels of the histogram sparkline, from empty to the fullest bucket
+const SPARKLINE: &[u8] = b"_.:-=+*#%@";
+
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const GREEN: &str = "\x1b[32m";
+const RESET: &str = "\x1b[0m";
+
+/// An [`Encoding`] which renders metric families as aligned tables.
+///
+/// This should ideally be cached and re-used between collections to reduce re-allocating
+#[derive(Default)]
+pub struct TableEncoder {
+    out: String,
+    colour: bool,
+    family: Option<Family>,
+}
+
+/// The rows of a metric family are buffered until the family is complete, so the columns can be aligned
+struct Family {
+    name: String,
+    typ: MetricType,
+    help: Option<String>,
+    /// The label names seen so far. Labels might be omitted from some rows.
+    labels: Vec<String>,
+    /// The value columns which follow the labels
+    columns: Vec<String>,
+    /// The label cells (by position in `labels`) and value cells of each row
+    rows: Vec<(Vec<String>, Vec<String>)>,
+    /// Whether the last column holds a sparkline
+    sparkline: bool,
+}
+
+impl TableEncoder {
+    /// Create a new table encoder, without colour
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether to highlight the output with ANSI colour codes
+    #[must_use]
+    pub fn with_colour(mut self, colour: bool) -> Self {
+        self.colour = colour;
+        self
+    }
+
+    /// Finish the encoding and take the rendered tables.
+    pub fn finish(&mut self) -> String {
+        self.flush_family();
+        std::mem::take(&mut self.out)
+    }
+
+    fn family(&mut self, name: &impl MetricNameEncoder) -> &mut Family {
+        let mut buf = Vec::new();
+        // writing into a vec cannot fail
+        let _ = name.encode_utf8(&mut buf);
+        let name = String::from_utf8_lossy(&buf);
+
+        if self.family.as_ref().is_none_or(|f| f.name != name) {
+            self.flush_family();
+            self.family = Some(Family {
+                name: name.into_owned(),
+                typ: MetricType::Untyped,
+                help: None,
+                labels: Vec::new(),
+                columns: Vec::new(),
+                rows: Vec::new(),
+                sparkline: false,
+            });
+        }
+        self.family.as_mut().unwrap()
+    }
+
+    /// Render the current family
+    fn flush_family(&mut self) {
+        let Some(family) = self.family.take() else {
+            return;
+        };
+
+        let colour = self.colour;
+        let out = &mut self.out;
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        paint(out, colour.then_some(BOLD), &family.name);
+        let mut title = format!(" ({})", family.typ.as_str());
+        if let Some(help) = &family.help {
+            title.push_str(": ");
+            title.push_str(help);
+        }
+        paint(out, colour.then_some(DIM), &title);
+        out.push('\n');
+
+        if family.rows.is_empty() {
+            return;
+        }
+
+        let mut header = family.labels;
+        let label_columns = header.len();
+        header.extend(family.columns);
+        let sparkline = family.sparkline.then(|| header.len() - 1);
+        let rows: Vec<Vec<String>> = family
+            .rows
+            .into_iter()
+            .map(|(mut row, values)| {
+                row.resize(label_columns, String::new());
+                row.extend(values);
+                row
+            })
+            .collect();
+
+        let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+        let widths: Vec<usize> = (0..columns)
+            .map(|i| {
+                let header = header.get(i).map_or(0, |h| h.chars().count());
+                let cells = rows.iter().filter_map(|r| r.get(i));
+                cells.map(|c| c.chars().count()).fold(header, usize::max)
+            })
+            .collect();
+
+        let header = std::iter::once((&header, true));
+        let rows = rows.iter().map(|row| (row, false));
+        for (row, is_header) in header.chain(rows) {
+            out.push_str("  ");
+            for (i, cell) in row.iter().enumerate() {
+                if i > 0 {
+                    out.push_str("  ");
+                }
+                let highlight = if is_header {
+                    Some(DIM)
+                } else if sparkline == Some(i) {
+                    Some(GREEN)
+                } else {
+                    None
+                };
+                paint(out, highlight.filter(|_| colour), cell);
+                if i + 1 < row.len() {
+                    let pad = widths[i] - cell.chars().count();
+                    out.extend(std::iter::repeat_n(' ', pad));
+                }
+            }
+            out.push('\n');
+        }
+    }
+
+    /// Write the label values of a series as the first cells of a row,
+    /// adding any new label names to the header.
+    ///
+    /// If `sparkline` is set, the last column is highlighted as a sparkline.
+    fn write_row(
+        &mut self,
+        name: &impl MetricNameEncoder,
+        labels: impl LabelGroup,
+        columns: &[&str],
+        values: Vec<String>,
+        sparkline: bool,
+    ) {
+        struct Cells<'a> {
+            labels: &'a mut Vec<String>,
+            row: &'a mut Vec<String>,
+        }
+        impl LabelGroupVisitor for Cells<'_> {
+            type Output = ();
+            fn write_value(&mut self, name: &LabelName, x: &impl LabelValue) {
+                let i = match self.labels.iter().position(|n| n == name.as_str()) {
+                    Some(i) => i,
+                    None => {
+                        self.labels.push(name.as_str().to_owned());
+                        self.labels.len() - 1
+                    }
+                };
+                if self.row.len() <= i {
+                    self.row.resize(i + 1, String::new());
+                }
+                self.row[i] = x.visit(RenderValue);
+            }
+        }
+
+        let family = self.family(name);
+        if family.rows.is_empty() {
+            family.columns = columns.iter().map(|&c| c.to_owned()).collect();
+            family.sparkline = sparkline;
+        }
+        let mut row = Vec::new();
+        labels.visit_values(&mut Cells {
+            labels: &mut family.labels,
+            row: &mut row,
+        });
+        family.rows.push((row, values));
+    }
+}
+
+/// Write the text, surrounded by the ANSI escape code if given
+fn paint(out: &mut String, code: Option<&str>, text: &str) {
+    match code {
+        Some(code) => {
+            out.push_str(code);
+            out.push_str(text);
+            out.push_str(RESET);
+        }
+        None => out.push_str(text),
+    }
+}
+
+/// Render the distribution of the buckets, scaled so the fullest bucket is the highest level
+fn sparkline(buckets: &[u64]) -> String {
+    let max = buckets.iter().copied().max().unwrap_or(0);
+    buckets
+        .iter()
+        .map(|&count| {
+            let top = SPARKLINE.len() as u64 - 1;
+            let level = if count == 0 {
+                0
+            } else {
+                // any observations should be visible, so round up
+                (count * top).div_ceil(max)
+            };
+            SPARKLINE[level as usize] as char
+        })
+        .collect()
+}
+
+impl Encoding for TableEncoder {
+    type Err = Infallible;
+
+    const MIME_TYPE: &'static str = "text/plain; charset=utf-8";
+
+    fn write_help(&mut self, name: impl MetricNameEncoder, help: &str) -> Result<(), Infallible> {
+        self.family(&name).help = Some(help.to_owned());
+        Ok(())
+    }
+}
+
+impl<L: EncoderList> DynamicEncoding<L> for TableEncoder
+where
+    L::Family: DynMetricFamily<Self>,
+{
+    fn collect_dynamic(&mut self, families: DynamicFamilies<'_, L>) -> Result<(), Self::Err> {
+        families.collect_into(self)
+    }
+}
+
+macro_rules! table_metric {
+    ($state:ty, $typ:expr, |$this:ident| $value:expr) => {
+        impl MetricEncoding<TableEncoder> for $state {
+            fn write_type(
+                name: impl MetricNameEncoder,
+                enc: &mut TableEncoder,
+            ) -> Result<(), Infallible> {
+                enc.family(&name).typ = $typ;
+                Ok(())
+            }
+            fn collect_into(
+                &$this,
+                _metadata: &(),
+                labels: impl LabelGroup,
+                name: impl MetricNameEncoder,
+                enc: &mut TableEncoder,
+            ) -> Result<(), Infallible> {
+                enc.write_row(&name, labels, &["value"], vec![$value], false);
+                Ok(())
+            }
+        }
+    };
+}
+
+table_metric!(CounterState, MetricType::Counter, |self| {
+    itoa::Buffer::new()
+        .format(self.count.load(core::sync::atomic::Ordering::Relaxed))
+        .to_owned()
+});
+table_metric!(GaugeState, MetricType::Gauge, |self| {
+    itoa::Buffer::new()
+        .format(self.count.load(core::sync::atomic::Ordering::Relaxed))
+        .to_owned()
+});
+table_metric!(FloatGaugeState, MetricType::Gauge, |self| {
+    crate::label::LabelVisitor::write_float(RenderValue, self.count.get())
+});
+
+impl<const N: usize> MetricEncoding<TableEncoder> for HistogramState<N> {
+    fn write_type(name: impl MetricNameEncoder, enc: &mut TableEncoder) -> Result<(), Infallible> {
+        enc.family(&name).typ = MetricType::Histogram;
+        Ok(())
+    }
+    fn collect_into(
+        &self,
+        metadata: &Thresholds<N>,
+        labels: impl LabelGroup,
+        name: impl MetricNameEncoder,
+        enc: &mut TableEncoder,
+    ) -> Result<(), Infallible> {
+        use crate::label::LabelVisitor;
+
+        let (buckets, inf, sum) = self.inner.write().sample();
+
+        let mut columns: Vec<String> = metadata
+            .get()
+            .iter()
+            .map(|&le| RenderValue.write_float(le))
+            .collect();
+        columns.extend(["+Inf", "count", "sum", "dist"].map(String::from));
+
+        let mut values: Vec<String> = buckets
+            .iter()
+            .chain([&inf])
+            .map(|&count| itoa::Buffer::new().format(count).to_owned())
+            .collect();
+        let count = buckets.iter().sum::<u64>() + inf;
+        values.push(itoa::Buffer::new().format(count).to_owned());
+        values.push(RenderValue.write_float(sum));
+        let mut all = buckets.to_vec();
+        all.push(inf);
+        values.push(sparkline(&all));
+
+        let columns: Vec<&str> = columns.iter().map(String::as_str).collect();
+        enc.write_row(&name, labels, &columns, values, true);
+        Ok(())
+    }
+}
+
+impl<M: MetricEncoding<TableEncoder>> MetricEncoding<TableEncoder> for Timestamped<M> {
+    fn write_type(name: impl MetricNameEncoder, enc: &mut TableEncoder) -> Result<(), Infallible> {
+        M::write_type(name, enc)
+    }
+    fn collect_into(
+        &self,
+        metadata: &M::Metadata,
+        labels: impl LabelGroup,
+        name: impl MetricNameEncoder,
+        enc: &mut TableEncoder,
+    ) -> Result<(), Infallible> {
+        self.metric().collect_into(metadata, labels, name, enc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use measured_derive::{FixedCardinalityLabel, LabelGroup, MetricGroup};
+
+    use super::{TableEncoder, sparkline};
+    use crate::{
+        FloatGauge, HistogramVec,
+        metric::{group::MetricGroup, histogram::Thresholds},
+    };
+
+    #[derive(FixedCardinalityLabel, Clone, Copy)]
+    #[label(crate = crate)]
+    enum Method {
+        Get,
+        Post,
+    }
+
+    #[derive(LabelGroup, Clone, Copy)]
+    #[label(crate = crate, set = RequestLabelSet)]
+    struct RequestLabels {
+        method: Method,
+    }
+
+    #[derive(MetricGroup)]
+    #[metric(crate = crate)]
+    struct Metrics {
+        /// request latency
+        request_duration_seconds: HistogramVec<RequestLabelSet, 3>,
+        load: FloatGauge,
+    }
+
+    #[test]
+    fn histogram() {
+        let metrics = Metrics {
+            request_duration_seconds: HistogramVec::with_label_set_and_metadata(
+                RequestLabelSet::new(),
+                Thresholds::with_buckets([0.1, 0.5, 1.0]),
+            ),
+            load: FloatGauge::new(),
+        };
+        let get = RequestLabels {
+            method: Method::Get,
+        };
+        for x in [0.0625, 0.25, 0.25, 0.25, 0.25, 2.0] {
+            metrics.request_duration_seconds.observe(get, x);
+        }
+        metrics.load.get_metric().set(0.75);
+
+        let mut enc = TableEncoder::new();
+        metrics.collect_group_into(&mut enc).unwrap();
+
+        assert_eq!(
+            enc.finish(),
+            r"request_duration_seconds (histogram): request latency
+  method  0.1  0.5  1.0  +Inf  count  sum     dist
+  get     1    4    0    1     6      3.0625  -@_-
+
+load (gauge)
+  value
+  0.75
+"
+        );
+
+        let mut enc = TableEncoder::new().with_colour(true);
+        metrics.collect_group_into(&mut enc).unwrap();
+        assert!(
+            enc.finish()
+                .starts_with("\x1b[1mrequest_duration_seconds\x1b[0m\x1b[2m (histogram)")
+        );
+    }
+
+    #[test]
+    fn sparklines() {
+        assert_eq!(sparkline(&[0, 1, 12, 100]), "_.:@");
+        assert_eq!(sparkline(&[0, 0]), "__");
+    }
+}
@@ -9,20 +9,29 @@ use bytes::{BufMut, Bytes, BytesMut};
 use memchr::memchr3_iter;
 
 use crate::{
-    label::{LabelGroup, LabelGroupVisitor, LabelName, LabelValue, LabelVisitor},
+    compress::{CompressWriter, Compressor},
+    label::{
+        LabelGroup, LabelGroupSet, LabelGroupVisitor, LabelName, LabelValue, LabelVisitor,
+        value::format_float,
+    },
     metric::{
         MetricEncoding,
         counter::CounterState,
         gauge::{FloatGaugeState, GaugeState},
-        group::{Encoding, MetricValue},
+        group::{
+            DynMetricFamily, DynamicEncoding, DynamicFamilies, EncoderList, Encoding, MetricValue,
+        },
         histogram::{HistogramState, Thresholds},
         name::{Bucket, Count, MetricNameEncoder, Sum},
+        timestamp::Timestamped,
     },
 };
 
 /// The prometheus text encoder helper
 pub struct TextEncoder<W> {
     state: State,
+    /// The timestamp of the samples currently being written
+    timestamp: Option<i64>,
     /// The inner writer for this text encoder.
     pub writer: W,
 }
@@ -34,7 +43,7 @@ enum State {
 }
 
 /// Prometheus only supports these 5 types of metrics
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum MetricType {
     /// Corresponds to [`Counter`](crate::Counter)
     Counter,
@@ -48,6 +57,52 @@ pub enum MetricType {
     Untyped,
 }
 
+impl MetricType {
+    /// The name of this type, as written in the `TYPE` line of the text format
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MetricType::Counter => "counter",
+            MetricType::Histogram => "histogram",
+            MetricType::Gauge => "gauge",
+            MetricType::Summary => "summary",
+            MetricType::Untyped => "untyped",
+        }
+    }
+}
+
+impl core::fmt::Display for MetricType {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl core::str::FromStr for MetricType {
+    type Err = ParseMetricTypeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "counter" => Ok(MetricType::Counter),
+            "histogram" => Ok(MetricType::Histogram),
+            "gauge" => Ok(MetricType::Gauge),
+            "summary" => Ok(MetricType::Summary),
+            "untyped" => Ok(MetricType::Untyped),
+            _ => Err(ParseMetricTypeError),
+        }
+    }
+}
+
+/// The error returned when parsing a string which is not one of the [`MetricType`] names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseMetricTypeError;
+
+impl core::fmt::Display for ParseMetricTypeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("unknown metric type")
+    }
+}
+
+impl std::error::Error for ParseMetricTypeError {}
+
 impl<W: Write> Encoding for TextEncoder<W> {
     type Err = std::io::Error;
 
@@ -73,6 +128,15 @@ impl<W: Write> Encoding for TextEncoder<W> {
     }
 }
 
+impl<L: EncoderList, W: Write> DynamicEncoding<L> for TextEncoder<W>
+where
+    L::Family: DynMetricFamily<Self>,
+{
+    fn collect_dynamic(&mut self, families: DynamicFamilies<'_, L>) -> Result<(), Self::Err> {
+        families.collect_into(self)
+    }
+}
+
 impl<W: Write> TextEncoder<W> {
     /// Create a new text encoder.
     ///
@@ -80,6 +144,7 @@ impl<W: Write> TextEncoder<W> {
     pub fn new(w: W) -> Self {
         Self {
             state: State::Info,
+            timestamp: None,
             writer: w,
         }
     }
@@ -107,13 +172,9 @@ impl<W: Write> TextEncoder<W> {
 
         self.writer.write_all(b"# TYPE ")?;
         name.encode_utf8(&mut self.writer)?;
-        match typ {
-            MetricType::Counter => self.writer.write_all(b" counter\n"),
-            MetricType::Histogram => self.writer.write_all(b" histogram\n"),
-            MetricType::Gauge => self.writer.write_all(b" gauge\n"),
-            MetricType::Summary => self.writer.write_all(b" summary\n"),
-            MetricType::Untyped => self.writer.write_all(b" untyped\n"),
-        }
+        self.writer.write_all(b" ")?;
+        self.writer.write_all(typ.as_str().as_bytes())?;
+        self.writer.write_all(b"\n")
     }
 
     /// Write the metric data
@@ -133,21 +194,16 @@ impl<W: Write> TextEncoder<W> {
             }
 
             fn write_float(self, x: f64) -> Result<(), std::io::Error> {
-                if x.is_infinite() {
-                    if x.is_sign_positive() {
-                        self.write_str("+Inf")
-                    } else {
-                        self.write_str("-Inf")
-                    }
-                } else if x.is_nan() {
-                    self.write_str("NaN")
-                } else {
-                    self.write_str(ryu::Buffer::new().format(x))
-                }
+                self.write_str(format_float(x, &mut ryu::Buffer::new()))
             }
 
             fn write_str(self, x: &str) -> Result<(), std::io::Error> {
+                self.write_prefixed_str("", x)
+            }
+
+            fn write_prefixed_str(self, prefix: &str, x: &str) -> Result<(), std::io::Error> {
                 self.writer.write_all(b"=\"")?;
+                write_label_str_value(prefix, &mut *self.writer)?;
                 write_label_str_value(x, &mut *self.writer)?;
                 self.writer.write_all(b"\"")?;
                 Ok(())
@@ -198,6 +254,11 @@ impl<W: Write> TextEncoder<W> {
                 .writer
                 .write_all(ryu::Buffer::new().format(x).as_bytes())?,
         }
+        if let Some(ts) = self.timestamp {
+            self.writer.write_all(b" ")?;
+            self.writer
+                .write_all(itoa::Buffer::new().format(ts).as_bytes())?;
+        }
         self.writer.write_all(b"\n")?;
         Ok(())
     }
@@ -332,6 +393,37 @@ impl<W: Write> MetricEncoding<TextEncoder<W>> for FloatGaugeState {
     }
 }
 
+impl<W: Write, M: MetricEncoding<TextEncoder<W>>> MetricEncoding<TextEncoder<W>>
+    for Timestamped<M>
+{
+    fn write_type(
+        name: impl MetricNameEncoder,
+        enc: &mut TextEncoder<W>,
+    ) -> Result<(), std::io::Error> {
+        M::write_type(name, enc)
+    }
+    fn write_metadata(
+        name: impl MetricNameEncoder,
+        metadata: &M::Metadata,
+        labels: &impl LabelGroupSet,
+        enc: &mut TextEncoder<W>,
+    ) -> Result<(), std::io::Error> {
+        M::write_metadata(name, metadata, labels, enc)
+    }
+    fn collect_into(
+        &self,
+        metadata: &M::Metadata,
+        labels: impl LabelGroup,
+        name: impl MetricNameEncoder,
+        enc: &mut TextEncoder<W>,
+    ) -> Result<(), std::io::Error> {
+        let prev = enc.timestamp.replace(self.timestamp_ms());
+        let res = self.metric().collect_into(metadata, labels, name, enc);
+        enc.timestamp = prev;
+        res
+    }
+}
+
 /// The prometheus text encoder helper
 pub struct BufferedTextEncoder {
     inner: TextEncoder<BytesWriter>,
@@ -368,6 +460,15 @@ impl Encoding for BufferedTextEncoder {
     }
 }
 
+impl<L: EncoderList> DynamicEncoding<L> for BufferedTextEncoder
+where
+    L::Family: DynMetricFamily<Self>,
+{
+    fn collect_dynamic(&mut self, families: DynamicFamilies<'_, L>) -> Result<(), Self::Err> {
+        families.collect_into(self)
+    }
+}
+
 impl BufferedTextEncoder {
     /// Create a new text encoder.
     ///
@@ -395,6 +496,15 @@ impl<T: MetricEncoding<TextEncoder<BytesWriter>>> MetricEncoding<BufferedTextEnc
         Self::write_type(name, &mut enc.inner).unreachable();
         Ok(())
     }
+    fn write_metadata(
+        name: impl MetricNameEncoder,
+        metadata: &T::Metadata,
+        labels: &impl LabelGroupSet,
+        enc: &mut BufferedTextEncoder,
+    ) -> Result<(), Infallible> {
+        Self::write_metadata(name, metadata, labels, &mut enc.inner).unreachable();
+        Ok(())
+    }
     fn collect_into(
         &self,
         metadata: &T::Metadata,
@@ -408,6 +518,95 @@ impl<T: MetricEncoding<TextEncoder<BytesWriter>>> MetricEncoding<BufferedTextEnc
     }
 }
 
+/// A prometheus text encoder which compresses the text while encoding it.
+///
+/// The response should have the `Content-Encoding` header set to [`Compressor::CONTENT_ENCODING`].
+/// The [`Negotiator`](crate::negotiate::Negotiator) does not negotiate the `Content-Encoding`,
+/// so compressed encoders cannot be registered with it.
+///
+/// This should ideally be cached and re-used between collections to reduce re-allocating
+pub struct CompressedTextEncoder<C> {
+    inner: TextEncoder<CompressWriter<BytesWriter, C>>,
+}
+
+impl<C: Compressor> CompressedTextEncoder<C> {
+    /// Create a new compressed text encoder.
+    pub fn new(compressor: C) -> Self {
+        Self {
+            inner: TextEncoder::new(CompressWriter::new(
+                BytesWriter {
+                    buf: BytesMut::new(),
+                },
+                compressor,
+            )),
+        }
+    }
+
+    /// Finish the compressed stream and extract the bytes to send in a HTTP response.
+    ///
+    /// The compressor is re-used for the next collection.
+    ///
+    /// # Errors
+    /// Will error if the compressor encountered an internal error.
+    /// The encoder should not be re-used after an error.
+    pub fn finish(&mut self) -> io::Result<Bytes> {
+        self.inner.state = State::Info;
+        let res = self.inner.writer.finish();
+        let buf = self.inner.writer.get_mut().buf.split().freeze();
+        res.map(|()| buf)
+    }
+}
+
+impl<C: Compressor> Encoding for CompressedTextEncoder<C> {
+    type Err = io::Error;
+
+    const MIME_TYPE: &'static str = TextEncoder::<BytesWriter>::MIME_TYPE;
+
+    /// Write the help line for a metric
+    fn write_help(&mut self, name: impl MetricNameEncoder, help: &str) -> io::Result<()> {
+        self.inner.write_help(name, help)
+    }
+}
+
+impl<L: EncoderList, C: Compressor> DynamicEncoding<L> for CompressedTextEncoder<C>
+where
+    L::Family: DynMetricFamily<Self>,
+{
+    fn collect_dynamic(&mut self, families: DynamicFamilies<'_, L>) -> Result<(), Self::Err> {
+        families.collect_into(self)
+    }
+}
+
+impl<C, T> MetricEncoding<CompressedTextEncoder<C>> for T
+where
+    C: Compressor,
+    T: MetricEncoding<TextEncoder<CompressWriter<BytesWriter, C>>>,
+{
+    fn write_type(
+        name: impl MetricNameEncoder,
+        enc: &mut CompressedTextEncoder<C>,
+    ) -> io::Result<()> {
+        Self::write_type(name, &mut enc.inner)
+    }
+    fn write_metadata(
+        name: impl MetricNameEncoder,
+        metadata: &T::Metadata,
+        labels: &impl LabelGroupSet,
+        enc: &mut CompressedTextEncoder<C>,
+    ) -> io::Result<()> {
+        Self::write_metadata(name, metadata, labels, &mut enc.inner)
+    }
+    fn collect_into(
+        &self,
+        metadata: &T::Metadata,
+        labels: impl LabelGroup,
+        name: impl MetricNameEncoder,
+        enc: &mut CompressedTextEncoder<C>,
+    ) -> io::Result<()> {
+        self.collect_into(metadata, labels, name, &mut enc.inner)
+    }
+}
+
 pub(crate) fn write_label_str_value(s: &str, b: &mut impl Write) -> io::Result<()> {
     let mut i = 0;
     for j in memchr3_iter(b'\\', b'"', b'\n', s.as_bytes()) {
@@ -423,6 +622,31 @@ pub(crate) fn write_label_str_value(s: &str, b: &mut impl Write) -> io::Result<(
     b.write_all(&s.as_bytes()[i..])
 }
 
+/// Writes a string, or a bare key if `quoted` is false.
+/// The escapes are valid in both JSON and TOML basic strings.
+pub(crate) fn write_str_literal(out: &mut String, s: &str, quoted: bool) {
+    use std::fmt::Write;
+
+    if !quoted {
+        out.push_str(s);
+        return;
+    }
+
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
 struct BytesWriter {
     buf: BytesMut,
 }
@@ -450,18 +674,21 @@ impl Write for BytesWriter {
 mod tests {
     use bytes::{BufMut, BytesMut};
 
+    use std::time::{Duration, UNIX_EPOCH};
+
     use crate::{
         CounterVec, Histogram,
-        label::StaticLabelSet,
+        label::{NoLabels, StaticLabelSet},
         metric::{
             MetricFamilyEncoding,
+            counter::{write_counter, write_counter_at},
             group::Encoding,
             histogram::Thresholds,
             name::{MetricName, Total},
         },
     };
 
-    use super::{BufferedTextEncoder, write_label_str_value};
+    use super::{BufferedTextEncoder, MetricType, ParseMetricTypeError, write_label_str_value};
 
     #[test]
     fn write_encoded_str() {
@@ -479,6 +706,20 @@ This is on a new line"#,
         );
     }
 
+    #[test]
+    fn metric_type_names() {
+        for typ in [
+            MetricType::Counter,
+            MetricType::Histogram,
+            MetricType::Gauge,
+            MetricType::Summary,
+            MetricType::Untyped,
+        ] {
+            assert_eq!(typ.as_str().parse(), Ok(typ));
+        }
+        assert_eq!("timer".parse::<MetricType>(), Err(ParseMetricTypeError));
+    }
+
     #[derive(Clone, Copy, PartialEq, Debug, measured_derive::LabelGroup)]
     #[label(crate = crate, set = RequestLabelSet)]
     struct RequestLabels {
@@ -576,6 +817,29 @@ http_request_duration_seconds_count 4
         );
     }
 
+    #[test]
+    fn text_timestamps() {
+        let mut encoder = BufferedTextEncoder::default();
+
+        let name = MetricName::from_str("device_reads").with_suffix(Total);
+        encoder
+            .write_help(&name, "The total number of reads from the device.")
+            .unwrap();
+
+        let time = UNIX_EPOCH + Duration::from_millis(1_700_000_000_123);
+        write_counter_at(&mut encoder, &name, NoLabels, 7, time).unwrap();
+        write_counter(&mut encoder, &name, NoLabels, 8).unwrap();
+
+        let s = String::from_utf8(encoder.finish().to_vec()).unwrap();
+        assert_eq!(
+            s,
+            r#"# HELP device_reads_total The total number of reads from the device.
+device_reads_total 7 1700000000123
+device_reads_total 8
+"#
+        );
+    }
+
     /// See <https://github.com/conradludgate/measured/issues/8>
     #[test]
     fn text_encoding_rename() {
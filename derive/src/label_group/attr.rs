@@ -45,6 +45,7 @@ pub struct LabelGroupFieldAttrs {
     pub kind: LabelGroupFieldAttrsKind,
     pub default: bool,
     pub rename: Option<LitStr>,
+    pub omit_none: bool,
 }
 
 #[derive(Clone)]
@@ -52,6 +53,8 @@ pub enum LabelGroupFieldAttrsKind {
     Fixed,
     FixedWith(Path),
     DynamicWith(Path),
+    /// The field is a nested label group, encoded with this label group set
+    Flatten(Path),
 }
 
 impl LabelGroupFieldAttrs {
@@ -60,6 +63,7 @@ impl LabelGroupFieldAttrs {
             LabelGroupFieldAttrsKind::Fixed => LabelGroupFieldAttrsSortKey::Fixed,
             LabelGroupFieldAttrsKind::FixedWith(_) => LabelGroupFieldAttrsSortKey::Fixed,
             LabelGroupFieldAttrsKind::DynamicWith(_) => LabelGroupFieldAttrsSortKey::Dynamic,
+            LabelGroupFieldAttrsKind::Flatten(_) => LabelGroupFieldAttrsSortKey::Fixed,
         }
     }
 }
@@ -75,6 +79,7 @@ impl LabelGroupFieldAttrs {
         let mut kind = None;
         let mut default = None;
         let mut rename = None;
+        let mut omit_none = None;
 
         for attr in attrs {
             if attr.path().is_ident(LABEL_ATTR) {
@@ -105,6 +110,19 @@ impl LabelGroupFieldAttrs {
                                 return Err(meta.error("duplicate `label(dynamic_with)` arg"));
                             }
                         }
+                        () if meta.path.is_ident("flatten") => {
+                            if !meta.input.peek(syn::Token![=]) {
+                                return Err(meta.error(
+                                    "`label(flatten)` requires the nested label group set, eg `label(flatten = RequestSet)`",
+                                ));
+                            }
+                            if kind
+                                .replace(LabelGroupFieldAttrsKind::Flatten(meta.value()?.parse()?))
+                                .is_some()
+                            {
+                                return Err(meta.error("duplicate `label(flatten)` arg"));
+                            }
+                        }
                         () if meta.path.is_ident("default") => {
                             if default.replace(()).is_some() {
                                 return Err(meta.error("duplicate `label(default)` arg"));
@@ -115,6 +133,11 @@ impl LabelGroupFieldAttrs {
                                 return Err(meta.error("duplicate `label(rename)` arg"));
                             }
                         }
+                        () if meta.path.is_ident("omit_none") => {
+                            if omit_none.replace(()).is_some() {
+                                return Err(meta.error("duplicate `label(omit_none)` arg"));
+                            }
+                        }
                         () => return Err(meta.error("unknown argument found")),
                     }
 
@@ -124,6 +147,14 @@ impl LabelGroupFieldAttrs {
         }
 
         let kind = kind.unwrap_or(LabelGroupFieldAttrsKind::Fixed);
+        if let LabelGroupFieldAttrsKind::Flatten(path) = &kind
+            && (rename.is_some() || omit_none.is_some())
+        {
+            return Err(syn::Error::new_spanned(
+                path,
+                "`label(flatten)` cannot be used with `label(rename)` or `label(omit_none)`",
+            ));
+        }
         let default = default.is_some();
 
         // fixed implies default
@@ -132,6 +163,7 @@ impl LabelGroupFieldAttrs {
             kind,
             default,
             rename,
+            omit_none: omit_none.is_some(),
         })
     }
 }
@@ -23,4 +23,6 @@ struct LabelGroupField {
     name: Ident,
     attrs: LabelGroupFieldAttrs,
     ty: Type,
+    /// Whether the field is an `Option`, which needs an extra slot in custom label sets.
+    optional: bool,
 }
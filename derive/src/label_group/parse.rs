@@ -1,24 +1,51 @@
-use syn::{Data, DeriveInput, Field, Fields, spanned::Spanned};
+use syn::{Data, DeriveInput, Field, Fields, Type, spanned::Spanned};
 
 use crate::Krate;
 
-use super::attr::{ContainerAttrs, LabelGroupFieldAttrs};
+use super::attr::{ContainerAttrs, LabelGroupFieldAttrs, LabelGroupFieldAttrsKind};
 use super::{LabelGroup, LabelGroupField};
 
 impl TryFrom<Field> for LabelGroupField {
     type Error = syn::Error;
     fn try_from(input: Field) -> syn::Result<Self> {
         let attrs = LabelGroupFieldAttrs::parse_attrs(&input.attrs)?;
+        let optional = is_option(&input.ty);
+        if attrs.omit_none && !optional {
+            return Err(syn::Error::new(
+                input.ty.span(),
+                "`label(omit_none)` can only be used on `Option` fields",
+            ));
+        }
+        if optional && matches!(attrs.kind, LabelGroupFieldAttrsKind::Flatten(_)) {
+            return Err(syn::Error::new(
+                input.ty.span(),
+                "`label(flatten)` cannot be used on `Option` fields",
+            ));
+        }
         Ok(LabelGroupField {
             span: input.span(),
             vis: input.vis,
             name: input.ident.unwrap(),
             ty: input.ty,
             attrs,
+            optional,
         })
     }
 }
 
+/// Whether the type looks like an `Option<T>`
+fn is_option(ty: &Type) -> bool {
+    let Type::Path(path) = ty else {
+        return false;
+    };
+    path.qself.is_none()
+        && path
+            .path
+            .segments
+            .last()
+            .is_some_and(|s| s.ident == "Option" && !s.arguments.is_empty())
+}
+
 impl TryFrom<DeriveInput> for LabelGroup {
     type Error = syn::Error;
     fn try_from(input: DeriveInput) -> syn::Result<Self> {
@@ -21,9 +21,22 @@ impl ToTokens for LabelGroup {
             let LabelGroupField { name, attrs, .. } = x;
             let name_string = attrs.rename.as_ref().map_or_else(|| name.to_string(), |r| r.value());
             let ident = format_ident!("{}", name_string.to_shouty_snake_case(), span = x.span);
-            quote_spanned! { x.span =>
-                const #ident: &#krate::label::LabelName = #krate::label::LabelName::from_str(#name_string);
-                #krate::label::LabelGroupVisitor::write_value(v, #ident, &self.#name);
+            if let LabelGroupFieldAttrsKind::Flatten(_) = attrs.kind {
+                quote_spanned! { x.span =>
+                    #krate::label::LabelGroup::visit_values(&self.#name, v);
+                }
+            } else if attrs.omit_none {
+                quote_spanned! { x.span =>
+                    const #ident: &#krate::label::LabelName = #krate::label::LabelName::from_str(#name_string);
+                    if let ::core::option::Option::Some(value) = &self.#name {
+                        #krate::label::LabelGroupVisitor::write_value(v, #ident, value);
+                    }
+                }
+            } else {
+                quote_spanned! { x.span =>
+                    const #ident: &#krate::label::LabelName = #krate::label::LabelName::from_str(#name_string);
+                    #krate::label::LabelGroupVisitor::write_value(v, #ident, &self.#name);
+                }
             }
         });
 
@@ -75,7 +88,8 @@ impl ToTokens for Set<'_> {
                 LabelGroupFieldAttrsKind::FixedWith(ty) => {
                     quote_spanned!( x.span => #vis #name: #ty, )
                 }
-                LabelGroupFieldAttrsKind::DynamicWith(ty) => {
+                LabelGroupFieldAttrsKind::DynamicWith(ty)
+                | LabelGroupFieldAttrsKind::Flatten(ty) => {
                     quote_spanned!( x.span => #vis #name: #ty, )
                 }
             }
@@ -98,12 +112,25 @@ impl ToTokens for Set<'_> {
                 } = x;
                 match &attrs.kind {
                     LabelGroupFieldAttrsKind::Fixed => quote_spanned!( x.span => <#krate::label::StaticLabelSet<#ty> as #krate::label::FixedCardinalitySet>::cardinality(&self.#name)),
+                    // `None` takes the slot after the values of the set
+                    LabelGroupFieldAttrsKind::FixedWith(ty) if x.optional => quote_spanned!( x.span => (<#ty as #krate::label::FixedCardinalitySet>::cardinality(&self.#name) + 1)),
                     LabelGroupFieldAttrsKind::FixedWith(ty) => quote_spanned!( x.span => <#ty as #krate::label::FixedCardinalitySet>::cardinality(&self.#name)),
+                    // nested sets are checked to be `FixedCardinalityGroupSet`s when encoding
+                    LabelGroupFieldAttrsKind::Flatten(ty) => quote_spanned!( x.span => <#ty as #krate::label::LabelGroupSet>::cardinality(&self.#name).unwrap_or(0)),
                     LabelGroupFieldAttrsKind::DynamicWith(_) => unreachable!(),
                 }
             })
             .collect();
 
+        // the total cardinality is only known if all the nested sets have a fixed cardinality
+        let checked_cardinalities = fixed.iter().zip(&cardinalities).map(|(x, card)| {
+            let name = &x.name;
+            match &x.attrs.kind {
+                LabelGroupFieldAttrsKind::Flatten(ty) => quote_spanned!( x.span => <#ty as #krate::label::LabelGroupSet>::cardinality(&self.#name)?),
+                _ => card.clone(),
+            }
+        });
+
         let defaults = sorted_fields.iter().map(|x| {
             let name = &x.name;
             if x.attrs.default {
@@ -112,7 +139,8 @@ impl ToTokens for Set<'_> {
                         quote_spanned!(x.span => #name: #krate::label::StaticLabelSet::new(),)
                     }
                     LabelGroupFieldAttrsKind::FixedWith(path)
-                    | LabelGroupFieldAttrsKind::DynamicWith(path) => {
+                    | LabelGroupFieldAttrsKind::DynamicWith(path)
+                    | LabelGroupFieldAttrsKind::Flatten(path) => {
                         quote_spanned!(x.span => #name: <#path as ::core::default::Default>::default(),)
                     }
                 }
@@ -141,7 +169,8 @@ impl ToTokens for Set<'_> {
                 match &x.attrs.kind {
                     LabelGroupFieldAttrsKind::Fixed => unreachable!("fixed is always default"),
                     LabelGroupFieldAttrsKind::FixedWith(path)
-                    | LabelGroupFieldAttrsKind::DynamicWith(path) => {
+                    | LabelGroupFieldAttrsKind::DynamicWith(path)
+                    | LabelGroupFieldAttrsKind::Flatten(path) => {
                         quote_spanned!(x.span => #name: #path)
                     }
                 }
@@ -157,11 +186,18 @@ impl ToTokens for Set<'_> {
             }
         };
 
+        let fixed_cardinality_impl = dynamics.is_empty().then(|| {
+            quote! {
+                #[automatically_derived]
+                impl #krate::label::FixedCardinalityGroupSet for #set_ident {}
+            }
+        });
+
         let cardinality_fns = if dynamics.is_empty() {
             quote!(
                 fn cardinality(&self) -> Option<usize> {
                     Some(1usize)
-                        #( .and_then(|x| x.checked_mul(#cardinalities)) )*
+                        #( .and_then(|x| x.checked_mul(#checked_cardinalities)) )*
                 }
                 type Unique = usize;
                 fn encode_dense(&self, value: Self::Unique) -> Option<usize> {
@@ -172,6 +208,14 @@ impl ToTokens for Set<'_> {
                 }
             )
         } else {
+            let folded_into_fn = SetFoldedInto {
+                group: self.0,
+                dynamics,
+            };
+            let evicted_fn = SetEvicted {
+                group: self.0,
+                dynamics,
+            };
             let dynamics = dynamics.iter().map(|_| quote!(usize));
             quote!(
                 fn cardinality(&self) -> Option<usize> {
@@ -185,9 +229,29 @@ impl ToTokens for Set<'_> {
                 fn decode_dense(&self, _value: usize) -> Self::Group<'_> {
                     unreachable!("does not have a dense encoding")
                 }
+
+                #folded_into_fn
+                #evicted_fn
             )
         };
 
+        // label names are visited in declaration order, to match `visit_values`.
+        let label_names = fields.iter().map(|x| {
+            let LabelGroupField { name, attrs, .. } = x;
+            if let LabelGroupFieldAttrsKind::Flatten(ty) = &attrs.kind {
+                return quote_spanned! { x.span =>
+                    <#ty as #krate::label::LabelGroupSet>::visit_label_names(&self.#name, v);
+                };
+            }
+            let name_string = attrs.rename.as_ref().map_or_else(|| name.to_string(), |r| r.value());
+            quote_spanned! { x.span => {
+                const NAME: &#krate::label::LabelName = #krate::label::LabelName::from_str(#name_string);
+                v(NAME);
+            }}
+        });
+
+        let try_parse_fn = SetTryParse { group: self.0 };
+
         let encode_fn = SetEncode {
             group: self.0,
             fixed,
@@ -219,6 +283,111 @@ impl ToTokens for Set<'_> {
                 #encode_fn
 
                 #decode_fn
+
+                fn visit_label_names(&self, v: &mut impl FnMut(&#krate::label::LabelName)) {
+                    #(#label_names)*
+                }
+
+                #try_parse_fn
+            }
+
+            #fixed_cardinality_impl
+        });
+    }
+}
+
+struct SetTryParse<'a> {
+    group: &'a LabelGroup,
+}
+
+impl ToTokens for SetTryParse<'_> {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        let LabelGroup { krate, fields, .. } = self.group;
+
+        let parses = fields.iter().map(|x| {
+            let LabelGroupField {
+                name, attrs, ty, ..
+            } = x;
+
+            let set_ty = match &attrs.kind {
+                LabelGroupFieldAttrsKind::Fixed => {
+                    quote_spanned!(x.span => #krate::label::StaticLabelSet<#ty>)
+                }
+                LabelGroupFieldAttrsKind::FixedWith(ty)
+                | LabelGroupFieldAttrsKind::DynamicWith(ty) => quote_spanned!(x.span => #ty),
+                LabelGroupFieldAttrsKind::Flatten(ty) => {
+                    return quote_spanned!(x.span =>
+                        let #name = {
+                            let labels = #krate::label::__labels_for(&self.#name, labels);
+                            matched += labels.len();
+                            <#ty as #krate::label::LabelGroupSet>::try_parse(&self.#name, &labels)?
+                        };
+                    );
+                }
+            };
+
+            let name_string = attrs
+                .rename
+                .as_ref()
+                .map_or_else(|| name.to_string(), |r| r.value());
+            // a missing label is the same as `None`, which is rendered as an empty string
+            let missing = if attrs.omit_none {
+                quote!("")
+            } else {
+                quote!(return ::core::option::Option::None)
+            };
+            let parse = quote_spanned!(x.span =>
+                <#set_ty as #krate::label::LabelSet>::decode(
+                    &self.#name,
+                    <#set_ty as #krate::label::LabelSet>::parse(&self.#name, value)?,
+                )
+            );
+            // fixed labels parse `None` themselves
+            let parse = match &attrs.kind {
+                LabelGroupFieldAttrsKind::FixedWith(_)
+                | LabelGroupFieldAttrsKind::DynamicWith(_)
+                    if x.optional =>
+                {
+                    quote_spanned!(x.span =>
+                        if value.is_empty() {
+                            ::core::option::Option::None
+                        } else {
+                            ::core::option::Option::Some(#parse)
+                        }
+                    )
+                }
+                _ => parse,
+            };
+
+            quote_spanned!(x.span =>
+                let #name = {
+                    let value = match labels.iter().find(|(name, _)| *name == #name_string) {
+                        ::core::option::Option::Some((_, value)) => {
+                            matched += 1;
+                            *value
+                        }
+                        ::core::option::Option::None => #missing,
+                    };
+                    #parse
+                };
+            )
+        });
+
+        let field_names = fields.iter().map(|x| &x.name);
+
+        tokens.extend(quote! {
+            fn try_parse(&self, labels: &[(&str, &str)]) -> Option<Self::Group<'_>> {
+                let mut matched = 0usize;
+
+                #(#parses)*
+
+                if matched != labels.len() {
+                    return ::core::option::Option::None;
+                }
+
+                ::core::option::Option::Some(Self::Group {
+                    #(#field_names,)*
+                })
             }
         });
     }
@@ -251,9 +420,22 @@ impl ToTokens for SetEncode<'_> {
                     LabelGroupFieldAttrsKind::Fixed => {
                         quote_spanned!(x.span => <#krate::label::StaticLabelSet<#ty> as #krate::label::LabelSet>::encode(&self.#name, value.#name)?)
                     }
+                    LabelGroupFieldAttrsKind::FixedWith(ty) if x.optional => {
+                        quote_spanned!(x.span => match value.#name {
+                            ::core::option::Option::Some(value) => <#ty as #krate::label::LabelSet>::encode(&self.#name, value)?,
+                            ::core::option::Option::None => <#ty as #krate::label::FixedCardinalitySet>::cardinality(&self.#name),
+                        })
+                    }
                     LabelGroupFieldAttrsKind::FixedWith(ty) => {
                         quote_spanned!(x.span => <#ty as #krate::label::LabelSet>::encode(&self.#name, value.#name)?)
                     }
+                    LabelGroupFieldAttrsKind::Flatten(ty) => {
+                        quote_spanned!(x.span => {
+                            <#ty as #krate::label::FixedCardinalityGroupSet>::__private_check_fixed();
+                            let unique = <#ty as #krate::label::LabelGroupSet>::encode(&self.#name, value.#name)?;
+                            <#ty as #krate::label::LabelGroupSet>::encode_dense(&self.#name, unique)?
+                        })
+                    }
                     LabelGroupFieldAttrsKind::DynamicWith(_) => unreachable!(),
                 }
             })
@@ -265,6 +447,16 @@ impl ToTokens for SetEncode<'_> {
                 let LabelGroupField { name, attrs, .. } = x;
 
                 match &attrs.kind {
+                    // `None` is encoded as 0, so the values of the set are shifted by 1
+                    LabelGroupFieldAttrsKind::DynamicWith(ty) if x.optional => {
+                        quote_spanned!(x.span => {
+                            <#ty as #krate::label::DynamicLabelSet>::__private_check_dynamic();
+                            match value.#name {
+                                ::core::option::Option::Some(value) => <#ty as #krate::label::LabelSet>::encode(&self.#name, value)? + 1,
+                                ::core::option::Option::None => 0,
+                            }
+                        })
+                    }
                     LabelGroupFieldAttrsKind::DynamicWith(ty) => {
                         quote_spanned!(x.span => {
                             <#ty as #krate::label::DynamicLabelSet>::__private_check_dynamic();
@@ -320,7 +512,9 @@ impl ToTokens for SetDecode<'_> {
 
                 match &attrs.kind {
                     LabelGroupFieldAttrsKind::Fixed => quote_spanned!(x.span => let #name = <#krate::label::StaticLabelSet<#ty> as #krate::label::LabelSet>::decode(&self.#name, index1);),
+                    LabelGroupFieldAttrsKind::FixedWith(ty) if x.optional => quote_spanned!(x.span => let #name = (index1 + 1 < card).then(|| <#ty as #krate::label::LabelSet>::decode(&self.#name, index1));),
                     LabelGroupFieldAttrsKind::FixedWith(ty) => quote_spanned!(x.span => let #name = <#ty as #krate::label::LabelSet>::decode(&self.#name, index1);),
+                    LabelGroupFieldAttrsKind::Flatten(ty) => quote_spanned!(x.span => let #name = <#ty as #krate::label::LabelGroupSet>::decode_dense(&self.#name, index1);),
                     LabelGroupFieldAttrsKind::DynamicWith(_) =>unreachable!(),
                 }
             })
@@ -342,6 +536,7 @@ impl ToTokens for SetDecode<'_> {
 
                 let index = &dynamic_indices[i];
                 match &attrs.kind {
+                    LabelGroupFieldAttrsKind::DynamicWith(ty) if x.optional => quote_spanned!(x.span => let #name = #index.checked_sub(1).map(|index| <#ty as #krate::label::LabelSet>::decode(&self.#name, index));),
                     LabelGroupFieldAttrsKind::DynamicWith(ty) => quote_spanned!(x.span => let #name = <#ty as #krate::label::LabelSet>::decode(&self.#name, #index);),
                     _ => unreachable!(),
                 }
@@ -373,3 +568,115 @@ impl ToTokens for SetDecode<'_> {
         });
     }
 }
+
+struct SetEvicted<'a> {
+    group: &'a LabelGroup,
+    dynamics: &'a [LabelGroupField],
+}
+
+impl ToTokens for SetEvicted<'_> {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        let Self {
+            group: LabelGroup { krate, .. },
+            dynamics,
+        } = *self;
+
+        let dynamic_indices = dynamics
+            .iter()
+            .enumerate()
+            .map(|(i, _)| format_ident!("dynamic_index{i}"))
+            .collect::<Vec<_>>();
+
+        // only the dynamic label sets can evict their values.
+        let dynamic_evicted: Vec<TokenStream> = dynamics
+            .iter()
+            .enumerate()
+            .map(|(i, x)| {
+                let LabelGroupField { name, attrs, .. } = x;
+
+                let index = &dynamic_indices[i];
+                match &attrs.kind {
+                    // `None` is encoded as 0, so the values of the set are shifted by 1
+                    LabelGroupFieldAttrsKind::DynamicWith(ty) if x.optional => quote_spanned!(x.span =>
+                        #index.checked_sub(1).is_some_and(|index| <#ty as #krate::label::LabelSet>::evicted(&self.#name, index))
+                    ),
+                    LabelGroupFieldAttrsKind::DynamicWith(ty) => quote_spanned!(x.span =>
+                        <#ty as #krate::label::LabelSet>::evicted(&self.#name, #index)
+                    ),
+                    _ => unreachable!(),
+                }
+            })
+            .collect();
+
+        tokens.extend(quote! {
+            fn evicted(&self, value: &Self::Unique) -> bool {
+                let (_ #(, #dynamic_indices)*) = *value;
+                false #(|| #dynamic_evicted)*
+            }
+        });
+    }
+}
+
+struct SetFoldedInto<'a> {
+    group: &'a LabelGroup,
+    dynamics: &'a [LabelGroupField],
+}
+
+impl ToTokens for SetFoldedInto<'_> {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        let Self {
+            group: LabelGroup { krate, .. },
+            dynamics,
+        } = *self;
+
+        let dynamic_indices = dynamics
+            .iter()
+            .enumerate()
+            .map(|(i, _)| format_ident!("dynamic_index{i}"))
+            .collect::<Vec<_>>();
+
+        // only the dynamic label sets can fold their values.
+        let dynamic_folds: Vec<TokenStream> = dynamics
+            .iter()
+            .enumerate()
+            .map(|(i, x)| {
+                let LabelGroupField { name, attrs, .. } = x;
+
+                let index = &dynamic_indices[i];
+                match &attrs.kind {
+                    // `None` is encoded as 0, so the values of the set are shifted by 1
+                    LabelGroupFieldAttrsKind::DynamicWith(ty) if x.optional => quote_spanned!(x.span =>
+                        let #index = match #index.checked_sub(1).and_then(|index| <#ty as #krate::label::LabelSet>::folded_into(&self.#name, index)) {
+                            ::core::option::Option::Some(index) => {
+                                folded = true;
+                                index + 1
+                            }
+                            ::core::option::Option::None => #index,
+                        };
+                    ),
+                    LabelGroupFieldAttrsKind::DynamicWith(ty) => quote_spanned!(x.span =>
+                        let #index = match <#ty as #krate::label::LabelSet>::folded_into(&self.#name, #index) {
+                            ::core::option::Option::Some(index) => {
+                                folded = true;
+                                index
+                            }
+                            ::core::option::Option::None => #index,
+                        };
+                    ),
+                    _ => unreachable!(),
+                }
+            })
+            .collect();
+
+        tokens.extend(quote! {
+            fn folded_into(&self, value: &Self::Unique) -> Option<Self::Unique> {
+                let (index #(, #dynamic_indices)*) = *value;
+                let mut folded = false;
+
+                #(#dynamic_folds)*
+
+                folded.then_some((index #(, #dynamic_indices)*))
+            }
+        });
+    }
+}
@@ -56,8 +56,13 @@ pub struct MetricGroupFieldAttrs {
 
 #[derive(Clone)]
 pub enum MetricGroupFieldAttrsKind {
-    Metric { rename: Option<LitStr> },
-    Group { namespace: Option<LitStr> },
+    Metric {
+        rename: Option<LitStr>,
+    },
+    Group {
+        namespace: Option<LitStr>,
+        labels: Option<Expr>,
+    },
 }
 
 #[derive(Clone)]
@@ -72,6 +77,7 @@ pub enum MetricGroupFieldAttrsInit {
 impl MetricGroupFieldAttrs {
     pub fn parse_attrs(attrs: &[Attribute]) -> syn::Result<Self> {
         let mut args = None;
+        let mut labels = None;
         let mut docs = None;
         let mut init = None;
 
@@ -90,17 +96,27 @@ impl MetricGroupFieldAttrs {
                         () if meta.path.is_ident("namespace") => {
                             let arg = MetricGroupFieldAttrsKind::Group {
                                 namespace: Some(meta.value()?.parse()?),
+                                labels: None,
                             };
                             if args.replace(arg).is_some() {
                                 return Err(meta.error("duplicate `metric(namespace)` attr"));
                             }
                         }
                         () if meta.path.is_ident("flatten") => {
-                            let arg = MetricGroupFieldAttrsKind::Group { namespace: None };
+                            let arg = MetricGroupFieldAttrsKind::Group {
+                                namespace: None,
+                                labels: None,
+                            };
                             if args.replace(arg).is_some() {
                                 return Err(meta.error("duplicate `metric(flatten)` attr"));
                             }
                         }
+                        () if meta.path.is_ident("labels") => {
+                            let expr: Expr = meta.value()?.parse()?;
+                            if labels.replace(expr).is_some() {
+                                return Err(meta.error("duplicate `metric(labels)` attr"));
+                            }
+                        }
                         () if meta.path.is_ident("init") => {
                             if init
                                 .replace(MetricGroupFieldAttrsInit::Raw(meta.value()?.parse()?))
@@ -171,10 +187,24 @@ impl MetricGroupFieldAttrs {
                 docs.get_or_insert_with(String::new).push_str(&s.value());
             }
         }
-        Ok(Self {
-            kind: args.unwrap_or(MetricGroupFieldAttrsKind::Metric { rename: None }),
-            docs,
-            init,
-        })
+        let kind = match (args, labels) {
+            (Some(MetricGroupFieldAttrsKind::Metric { .. }), Some(labels)) => {
+                return Err(syn::Error::new(
+                    labels.span(),
+                    "`metric(labels)` can only be used on nested metric groups",
+                ));
+            }
+            (Some(MetricGroupFieldAttrsKind::Group { namespace, .. }), labels) => {
+                MetricGroupFieldAttrsKind::Group { namespace, labels }
+            }
+            (None, Some(labels)) => MetricGroupFieldAttrsKind::Group {
+                namespace: None,
+                labels: Some(labels),
+            },
+            (Some(kind), None) => kind,
+            (None, None) => MetricGroupFieldAttrsKind::Metric { rename: None },
+        };
+
+        Ok(Self { kind, docs, init })
     }
 }
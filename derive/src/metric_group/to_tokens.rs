@@ -33,16 +33,48 @@ impl ToTokens for MetricGroup {
                 MetricGroupFieldAttrsKind::Metric { .. } => {
                     wc.predicates.push(parse_quote_spanned!(field.span => #ty: #krate::metric::MetricFamilyEncoding<#enc> ));
                 }
-                MetricGroupFieldAttrsKind::Group { namespace: None } => {
+                MetricGroupFieldAttrsKind::Group {
+                    namespace: None,
+                    labels: None,
+                } => {
                     wc.predicates.push(parse_quote_spanned!(field.span => #ty: #krate::metric::group::MetricGroup<#enc> ));
                 }
-                MetricGroupFieldAttrsKind::Group { namespace: Some(_) } => {
+                MetricGroupFieldAttrsKind::Group {
+                    namespace: Some(_),
+                    labels: None,
+                } => {
                     wc.predicates.push(parse_quote_spanned!(field.span =>
                         #ty: for<'__enc_tmp_lt> #krate::metric::group::MetricGroup<
                             #krate::metric::name::WithNamespace<&'__enc_tmp_lt mut #enc>,
                         >
                     ));
                 }
+                MetricGroupFieldAttrsKind::Group {
+                    namespace: None,
+                    labels: Some(_),
+                } => {
+                    wc.predicates.push(parse_quote_spanned!(field.span =>
+                        #ty: for<'__enc_tmp_lt> #krate::metric::group::MetricGroup<
+                            #krate::metric::group::WithLabels<
+                                #krate::metric::group::ConstLabels<'__enc_tmp_lt>,
+                                &'__enc_tmp_lt mut #enc,
+                            >,
+                        >
+                    ));
+                }
+                MetricGroupFieldAttrsKind::Group {
+                    namespace: Some(_),
+                    labels: Some(_),
+                } => {
+                    wc.predicates.push(parse_quote_spanned!(field.span =>
+                        #ty: for<'__enc_tmp_lt, '__enc_tmp_lt2> #krate::metric::group::MetricGroup<
+                            #krate::metric::group::WithLabels<
+                                #krate::metric::group::ConstLabels<'__enc_tmp_lt2>,
+                                &'__enc_tmp_lt2 mut #krate::metric::name::WithNamespace<&'__enc_tmp_lt mut #enc>,
+                            >,
+                        >
+                    ));
+                }
             }
         }
 
@@ -68,12 +100,12 @@ impl ToTokens for MetricGroup {
                         <#ty as #krate::metric::MetricFamilyEncoding<#enc>>::collect_family_into(&self.#name, #ident, enc)?;
                     }
                 },
-                MetricGroupFieldAttrsKind::Group { namespace: None } => {
+                MetricGroupFieldAttrsKind::Group { namespace: None, labels: None } => {
                     quote_spanned! { x.span =>
                         <#ty as #krate::metric::group::MetricGroup<#enc>>::collect_group_into(&self.#name, enc)?;
                     }
                 },
-                MetricGroupFieldAttrsKind::Group { namespace: Some(ns) } => {
+                MetricGroupFieldAttrsKind::Group { namespace: Some(ns), labels: None } => {
                     quote_spanned! { x.span =>
                         <#krate::metric::name::WithNamespace<&#ty> as #krate::metric::group::MetricGroup<#enc>>::collect_group_into(
                             &#krate::metric::name::WithNamespace::new(#ns, &self.#name),
@@ -81,6 +113,22 @@ impl ToTokens for MetricGroup {
                         )?;
                     }
                 },
+                MetricGroupFieldAttrsKind::Group { namespace: None, labels: Some(labels) } => {
+                    quote_spanned! { x.span =>
+                        <#krate::metric::group::WithLabels<_, &#ty> as #krate::metric::group::MetricGroup<#enc>>::collect_group_into(
+                            &#krate::metric::group::WithLabels::new(#labels, &self.#name),
+                            enc,
+                        )?;
+                    }
+                },
+                MetricGroupFieldAttrsKind::Group { namespace: Some(ns), labels: Some(labels) } => {
+                    quote_spanned! { x.span =>
+                        <#krate::metric::name::WithNamespace<#krate::metric::group::WithLabels<_, &#ty>> as #krate::metric::group::MetricGroup<#enc>>::collect_group_into(
+                            &#krate::metric::name::WithNamespace::new(#ns, #krate::metric::group::WithLabels::new(#labels, &self.#name)),
+                            enc,
+                        )?;
+                    }
+                },
             }
         });
 
@@ -1,5 +1,5 @@
 use syn::{
-    Attribute, LitStr, Token,
+    Attribute, Ident, LitStr, Token,
     parse::{Parse, ParseStream},
 };
 
@@ -13,6 +13,8 @@ pub struct ContainerAttrs {
     pub krate: Option<Krate>,
     pub rename_all: Option<RenameAll>,
     pub singleton: Option<LitStr>,
+    pub kind: Option<Ident>,
+    pub from_str: bool,
 }
 
 impl ContainerAttrs {
@@ -59,6 +61,17 @@ impl ContainerAttrs {
                                 return Err(input.error("duplicate `singleton` arg"));
                             }
                         }
+                        "from_str" => {
+                            if std::mem::replace(&mut self.from_str, true) {
+                                return Err(input.error("duplicate `from_str` arg"));
+                            }
+                        }
+                        "kind" => {
+                            let _: Token![=] = input.parse()?;
+                            if self.kind.replace(input.parse()?).is_some() {
+                                return Err(input.error("duplicate `kind` arg"));
+                            }
+                        }
                         _ => return Err(input.error("unknown argument found")),
                     }
                 }
@@ -72,11 +85,19 @@ impl ContainerAttrs {
 #[derive(Clone)]
 pub struct VariantAttrs {
     pub rename: Option<LitStr>,
+    pub flatten: bool,
+    pub ignore_fields: bool,
+    pub other: bool,
 }
 
 impl VariantAttrs {
     pub fn parse_attrs(attrs: &[Attribute]) -> syn::Result<Self> {
-        let mut args = VariantAttrs { rename: None };
+        let mut args = VariantAttrs {
+            rename: None,
+            flatten: false,
+            ignore_fields: false,
+            other: false,
+        };
         for attr in attrs {
             if attr.path().is_ident(LABEL_ATTR) {
                 attr.meta.require_list()?.parse_nested_meta(|meta| {
@@ -86,6 +107,21 @@ impl VariantAttrs {
                                 return Err(meta.error("duplicate `label(rename)` arg"));
                             }
                         }
+                        () if meta.path.is_ident("flatten") => {
+                            if std::mem::replace(&mut args.flatten, true) {
+                                return Err(meta.error("duplicate `label(flatten)` arg"));
+                            }
+                        }
+                        () if meta.path.is_ident("other") => {
+                            if std::mem::replace(&mut args.other, true) {
+                                return Err(meta.error("duplicate `label(other)` arg"));
+                            }
+                        }
+                        () if meta.path.is_ident("ignore_fields") => {
+                            if std::mem::replace(&mut args.ignore_fields, true) {
+                                return Err(meta.error("duplicate `label(ignore_fields)` arg"));
+                            }
+                        }
                         () => return Err(meta.error("unknown argument found")),
                     }
 
@@ -109,6 +145,16 @@ pub enum RenameAll {
 }
 
 impl RenameAll {
+    /// The separator between words in this case style
+    pub fn separator(&self) -> &'static str {
+        match self {
+            RenameAll::UpperCamel | RenameAll::LowerCamel => "",
+            RenameAll::Snake | RenameAll::ShoutySnake => "_",
+            RenameAll::Kebab | RenameAll::ShoutyKebab | RenameAll::Train => "-",
+            RenameAll::Title => " ",
+        }
+    }
+
     pub fn apply(&self, s: &str) -> String {
         use heck::{
             ToKebabCase, ToLowerCamelCase, ToShoutyKebabCase, ToShoutySnakeCase, ToSnakeCase,
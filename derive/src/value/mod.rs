@@ -1,5 +1,5 @@
 use proc_macro2::{Ident, Span};
-use syn::{LitInt, LitStr, Path};
+use syn::{Fields, LitInt, LitStr, Path, Type, Visibility};
 
 use self::attr::{RenameAll, VariantAttrs};
 
@@ -9,10 +9,15 @@ mod to_tokens;
 
 pub struct FixedCardinalityLabel {
     krate: Path,
+    vis: Visibility,
     rename_all: RenameAll,
     ident: Ident,
     variants: Vec<FixedCardinalityLabelVariant>,
     singleton: Option<LitStr>,
+    /// The companion label type to generate, if the enum cannot be a label itself
+    kind: Option<Ident>,
+    /// Whether to generate `FromStr` and `TryFrom<&str>`
+    from_str: bool,
 }
 
 pub struct FixedCardinalityLabelVariant {
@@ -20,4 +25,13 @@ pub struct FixedCardinalityLabelVariant {
     attrs: VariantAttrs,
     ident: Ident,
     value: Option<LitInt>,
+    fields: VariantFields,
+}
+
+pub enum VariantFields {
+    Unit,
+    /// `label(flatten)`: the single field is a `FixedCardinalityLabel`
+    Flatten(Type),
+    /// `label(ignore_fields)`: the fields do not contribute to the label value
+    Ignored(Fields),
 }
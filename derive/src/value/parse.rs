@@ -3,7 +3,7 @@ use syn::{Data, DeriveInput, Fields, Lit, Variant, spanned::Spanned};
 use crate::Krate;
 
 use super::{
-    FixedCardinalityLabel, FixedCardinalityLabelVariant,
+    FixedCardinalityLabel, FixedCardinalityLabelVariant, VariantFields,
     attr::{ContainerAttrs, VariantAttrs},
 };
 
@@ -13,17 +13,53 @@ impl TryFrom<Variant> for FixedCardinalityLabelVariant {
         let span = input.span();
         let attrs = VariantAttrs::parse_attrs(&input.attrs)?;
 
-        match input.fields {
+        if attrs.flatten && attrs.ignore_fields {
+            return Err(syn::Error::new(
+                span,
+                "`label(flatten)` and `label(ignore_fields)` are not compatible",
+            ));
+        }
+
+        let fields = match input.fields {
+            Fields::Unit if attrs.flatten || attrs.ignore_fields => {
+                return Err(syn::Error::new(span, "variant has no fields"));
+            }
+            Fields::Unit => VariantFields::Unit,
+            fields if attrs.ignore_fields => VariantFields::Ignored(fields),
+            Fields::Unnamed(fields) if attrs.flatten && fields.unnamed.len() == 1 => {
+                VariantFields::Flatten(fields.unnamed.into_iter().next().unwrap().ty)
+            }
+            _ if attrs.flatten => {
+                return Err(syn::Error::new(
+                    span,
+                    "`label(flatten)` variants must have a single unnamed field",
+                ));
+            }
             Fields::Named(_) | Fields::Unnamed(_) => {
-                return Err(syn::Error::new(span, "variants with values not supported"));
+                return Err(syn::Error::new(
+                    span,
+                    "variants with values not supported. use `label(flatten)` or `label(ignore_fields)`",
+                ));
             }
-            Fields::Unit => {}
+        };
+        if attrs.flatten && attrs.other {
+            return Err(syn::Error::new(
+                span,
+                "`label(other)` cannot be used on `label(flatten)` variants",
+            ));
+        }
+        if attrs.flatten && input.discriminant.is_some() {
+            return Err(syn::Error::new(
+                span,
+                "`label(flatten)` variants cannot have a discriminant",
+            ));
         }
 
         Ok(FixedCardinalityLabelVariant {
             span,
             attrs,
             ident: input.ident,
+            fields,
             value: input
                 .discriminant
                 .map(|(_, expr)| match expr {
@@ -43,7 +79,11 @@ impl TryFrom<DeriveInput> for FixedCardinalityLabel {
     fn try_from(input: DeriveInput) -> syn::Result<Self> {
         let span = input.span();
         let DeriveInput {
-            ident, data, attrs, ..
+            ident,
+            data,
+            attrs,
+            vis,
+            ..
         } = input;
 
         let args = ContainerAttrs::parse_attrs(&attrs)?;
@@ -63,12 +103,30 @@ impl TryFrom<DeriveInput> for FixedCardinalityLabel {
             Data::Struct(_) => return Err(syn::Error::new(span, "structs not supported")),
         };
 
+        if let Some(var) = variants.iter().filter(|x| x.attrs.other).nth(1) {
+            return Err(syn::Error::new(
+                var.span,
+                "duplicate `label(other)` variant",
+            ));
+        }
+        if let Some(var) = variants.iter().find(|x| x.attrs.other)
+            && !args.from_str
+        {
+            return Err(syn::Error::new(
+                var.span,
+                "`label(other)` requires the `label(from_str)` container attribute",
+            ));
+        }
+
         Ok(Self {
             krate,
+            vis,
             rename_all,
             ident,
             variants,
             singleton: args.singleton,
+            kind: args.kind,
+            from_str: args.from_str,
         })
     }
 }
@@ -1,27 +1,38 @@
 use proc_macro2::TokenStream;
 use quote::{ToTokens, quote, quote_spanned};
 
-use super::FixedCardinalityLabel;
+use super::{FixedCardinalityLabel, FixedCardinalityLabelVariant, VariantFields};
 
 impl ToTokens for FixedCardinalityLabel {
     fn to_tokens(&self, tokens: &mut TokenStream) {
         let Self {
             krate,
+            vis,
             ident,
             rename_all,
             variants,
             singleton,
+            kind,
+            from_str,
         } = self;
 
-        let cardinality = variants.len();
-        let var_idents1 = variants.iter().map(|x| &x.ident);
-        let var_idents2 = variants.iter().map(|x| &x.ident);
-        let count1 = 0..cardinality;
-        let count2 = 0..cardinality;
+        // the type that the label traits are implemented for
+        let target = kind.as_ref().unwrap_or(ident);
 
         let visits = variants.iter().map(|var| {
             let var_ident = &var.ident;
 
+            if let VariantFields::Flatten(_) = &var.fields {
+                let prefix = var.attrs.rename.as_ref().map_or_else(
+                    || rename_all.apply(&var.ident.to_string()),
+                    |r| r.value(),
+                );
+                let prefix = format!("{prefix}{}", rename_all.separator());
+                return quote_spanned!(var.span => #target :: #var_ident(inner) => {
+                    #krate::label::LabelValue::visit(inner, #krate::label::__PrefixVisitor::new(#prefix, v))
+                });
+            }
+
             let write = var.attrs.rename.as_ref().map_or_else(
                 || {
                     if let Some(int) = &var.value {
@@ -34,12 +45,13 @@ impl ToTokens for FixedCardinalityLabel {
                 |r| quote_spanned!(var.span => v.write_str(#r)),
             );
 
-            quote_spanned!(var.span => #ident :: #var_ident => #write,)
+            let pattern = pattern(target, var, kind.is_some());
+            quote_spanned!(var.span => #pattern => #write,)
         });
 
         let singleton = singleton.as_ref().map(|s| {
             quote!{
-                impl #krate::label::LabelGroup for #ident {
+                impl #krate::label::LabelGroup for #target {
                     fn visit_values(&self, v: &mut impl #krate::label::LabelGroupVisitor) {
                         const NAME: &#krate::label::LabelName = #krate::label::LabelName::from_str(#s);
                         v.write_value(NAME, self);
@@ -48,29 +60,266 @@ impl ToTokens for FixedCardinalityLabel {
             }
         });
 
-        tokens.extend(quote! {
-            #[automatically_derived]
-            impl #krate::label::FixedCardinalityLabel for #ident {
+        let fixed_cardinality_label = if variants
+            .iter()
+            .all(|x| matches!(x.fields, VariantFields::Unit))
+        {
+            let cardinality = variants.len();
+            let var_idents1 = variants.iter().map(|x| &x.ident);
+            let var_idents2 = variants.iter().map(|x| &x.ident);
+            let count1 = 0..cardinality;
+            let count2 = 0..cardinality;
+
+            quote! {
                 fn cardinality() -> usize {
                     #cardinality
                 }
 
                 fn encode(&self) -> usize {
                     match self {
-                        #(#ident :: #var_idents1 => #count1,)*
+                        #(#target :: #var_idents1 => #count1,)*
                     }
                 }
 
                 fn decode(value: usize) -> Self {
                     match value {
-                        #(#count2 => #ident :: #var_idents2,)*
+                        #(#count2 => #target :: #var_idents2,)*
                         _ => panic!("invalid value"),
                     }
                 }
             }
+        } else {
+            // flattened variants take up a range of values, so the values are offset
+            // by the cardinality of all the previous variants.
+            let cardinalities: Vec<TokenStream> = variants
+                .iter()
+                .map(|var| match &var.fields {
+                    VariantFields::Flatten(ty) => quote_spanned!(var.span => <#ty as #krate::label::FixedCardinalityLabel>::cardinality()),
+                    VariantFields::Unit | VariantFields::Ignored(_) => quote!(1usize),
+                })
+                .collect();
+
+            let encodes = variants.iter().enumerate().map(|(i, var)| {
+                let offset = &cardinalities[..i];
+                let offset = quote!(0usize #(+ #offset)*);
+                let pattern = pattern(target, var, kind.is_some());
+                match &var.fields {
+                    VariantFields::Flatten(ty) => quote_spanned!(var.span =>
+                        #pattern => #offset + <#ty as #krate::label::FixedCardinalityLabel>::encode(inner),
+                    ),
+                    VariantFields::Unit | VariantFields::Ignored(_) => {
+                        quote_spanned!(var.span => #pattern => #offset,)
+                    }
+                }
+            });
+
+            let decodes = variants.iter().enumerate().map(|(i, var)| {
+                let var_ident = &var.ident;
+                let offset = &cardinalities[..i];
+                let card = &cardinalities[i];
+                let offset = quote!(0usize #(+ #offset)*);
+                let decode = match &var.fields {
+                    VariantFields::Flatten(ty) => quote_spanned!(var.span =>
+                        #target :: #var_ident(<#ty as #krate::label::FixedCardinalityLabel>::decode(value - (#offset)))
+                    ),
+                    VariantFields::Unit | VariantFields::Ignored(_) => {
+                        constructor(target, var, kind.is_some())
+                    }
+                };
+                quote_spanned!(var.span =>
+                    if value < #offset + #card {
+                        return #decode;
+                    }
+                )
+            });
+
+            quote! {
+                fn cardinality() -> usize {
+                    0usize #(+ #cardinalities)*
+                }
+
+                fn encode(&self) -> usize {
+                    match self {
+                        #(#encodes)*
+                    }
+                }
+
+                fn decode(value: usize) -> Self {
+                    #(#decodes)*
+                    panic!("invalid value")
+                }
+            }
+        };
+
+        let kind = kind.as_ref().map(|kind| {
+            let doc = format!("The label value of a [`{ident}`]");
+            let kind_variants = variants.iter().map(|var| {
+                let var_ident = &var.ident;
+                match &var.fields {
+                    VariantFields::Flatten(ty) => quote_spanned!(var.span => #var_ident(#ty),),
+                    VariantFields::Unit | VariantFields::Ignored(_) => {
+                        quote_spanned!(var.span => #var_ident,)
+                    }
+                }
+            });
+            let conversions = variants.iter().map(|var| {
+                let var_ident = &var.ident;
+                let pattern = pattern(ident, var, false);
+                match &var.fields {
+                    VariantFields::Flatten(_) => {
+                        quote_spanned!(var.span => #pattern => #kind :: #var_ident(*inner),)
+                    }
+                    VariantFields::Unit | VariantFields::Ignored(_) => {
+                        quote_spanned!(var.span => #pattern => #kind :: #var_ident,)
+                    }
+                }
+            });
+
+            let kind_name = kind.to_string();
+
+            // the encoding is unique, so it's used for the comparisons.
+            // this saves requiring extra traits on the flattened label types.
+            quote! {
+                #[doc = #doc]
+                #[derive(Clone, Copy)]
+                #vis enum #kind {
+                    #(#kind_variants)*
+                }
+
+                #[automatically_derived]
+                impl ::core::cmp::PartialEq for #kind {
+                    fn eq(&self, other: &Self) -> bool {
+                        #krate::label::FixedCardinalityLabel::encode(self) == #krate::label::FixedCardinalityLabel::encode(other)
+                    }
+                }
+
+                #[automatically_derived]
+                impl ::core::cmp::Eq for #kind {}
+
+                #[automatically_derived]
+                impl ::core::hash::Hash for #kind {
+                    fn hash<H: ::core::hash::Hasher>(&self, state: &mut H) {
+                        ::core::hash::Hash::hash(&#krate::label::FixedCardinalityLabel::encode(self), state);
+                    }
+                }
+
+                #[automatically_derived]
+                impl ::core::fmt::Debug for #kind {
+                    fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                        let value = #krate::label::LabelValue::visit(self, #krate::label::LabelTestVisitor);
+                        f.debug_tuple(#kind_name).field(&value).finish()
+                    }
+                }
+
+                #[automatically_derived]
+                impl ::core::convert::From<&#ident> for #kind {
+                    fn from(value: &#ident) -> Self {
+                        match value {
+                            #(#conversions)*
+                        }
+                    }
+                }
+
+                #[automatically_derived]
+                impl ::core::convert::From<#ident> for #kind {
+                    fn from(value: #ident) -> Self {
+                        Self::from(&value)
+                    }
+                }
+            }
+        });
+
+        // the generated `FromStr` is a direct match, instead of comparing every value
+        let parse = from_str.then(|| {
+            quote! {
+                fn parse(value: &str) -> ::core::option::Option<Self> {
+                    ::core::str::FromStr::from_str(value).ok()
+                }
+            }
+        });
+
+        let from_str = from_str.then(|| {
+            let mut other = None;
+            let mut arms = vec![];
+            let mut prefixed = vec![];
+            for var in variants {
+                let var_ident = &var.ident;
+                if let VariantFields::Flatten(ty) = &var.fields {
+                    let prefix = var.attrs.rename.as_ref().map_or_else(
+                        || rename_all.apply(&var.ident.to_string()),
+                        |r| r.value(),
+                    );
+                    let prefix = format!("{prefix}{}", rename_all.separator());
+                    prefixed.push(quote_spanned!(var.span =>
+                        // nested, as let-chains are not available in older editions
+                        if let ::core::option::Option::Some(rest) = s.strip_prefix(#prefix) {
+                            if let ::core::option::Option::Some(inner) = <#ty as #krate::label::FixedCardinalityLabel>::parse(rest) {
+                                return ::core::result::Result::Ok(#target :: #var_ident(inner));
+                            }
+                        }
+                    ));
+                    continue;
+                }
+
+                let name = var.attrs.rename.as_ref().map_or_else(
+                    || match &var.value {
+                        Some(int) => int.base10_digits().to_owned(),
+                        None => rename_all.apply(&var.ident.to_string()),
+                    },
+                    |r| r.value(),
+                );
+                let ctor = constructor(target, var, kind.is_some());
+                arms.push(quote_spanned!(var.span => #name => return ::core::result::Result::Ok(#ctor),));
+                if var.attrs.other {
+                    other = Some(ctor);
+                }
+            }
+
+            let fallback = match other {
+                Some(ctor) => quote!(::core::result::Result::Ok(#ctor)),
+                None => quote!(::core::result::Result::Err(#krate::label::ParseLabelValueError::new(s))),
+            };
+
+            quote! {
+                #[automatically_derived]
+                impl ::core::str::FromStr for #target {
+                    type Err = #krate::label::ParseLabelValueError;
+
+                    fn from_str(s: &str) -> ::core::result::Result<Self, Self::Err> {
+                        match s {
+                            #(#arms)*
+                            _ => {}
+                        }
+                        #(#prefixed)*
+                        #fallback
+                    }
+                }
+
+                #[automatically_derived]
+                impl ::core::convert::TryFrom<&str> for #target {
+                    type Error = #krate::label::ParseLabelValueError;
+
+                    fn try_from(s: &str) -> ::core::result::Result<Self, Self::Error> {
+                        ::core::str::FromStr::from_str(s)
+                    }
+                }
+            }
+        });
+
+        tokens.extend(quote! {
+            #kind
+
+            #from_str
 
             #[automatically_derived]
-            impl #krate::label::LabelValue for #ident {
+            impl #krate::label::FixedCardinalityLabel for #target {
+                #fixed_cardinality_label
+
+                #parse
+            }
+
+            #[automatically_derived]
+            impl #krate::label::LabelValue for #target {
                 fn visit<V: #krate::label::LabelVisitor>(&self, v: V) -> V::Output {
                     match self {
                         #(#visits)*
@@ -82,3 +331,37 @@ impl ToTokens for FixedCardinalityLabel {
         });
     }
 }
+
+/// The match pattern for the variant. Flattened variants bind their field as `inner`.
+///
+/// Variants of a generated `kind` type have no ignored fields.
+fn pattern(ty: &syn::Ident, var: &FixedCardinalityLabelVariant, is_kind: bool) -> TokenStream {
+    let var_ident = &var.ident;
+    match &var.fields {
+        VariantFields::Flatten(_) => quote_spanned!(var.span => #ty :: #var_ident(inner)),
+        VariantFields::Unit => quote_spanned!(var.span => #ty :: #var_ident),
+        VariantFields::Ignored(_) if is_kind => quote_spanned!(var.span => #ty :: #var_ident),
+        VariantFields::Ignored(_) => quote_spanned!(var.span => #ty :: #var_ident { .. }),
+    }
+}
+
+/// Construct a unit or `label(ignore_fields)` variant. Ignored fields are defaulted.
+fn constructor(ty: &syn::Ident, var: &FixedCardinalityLabelVariant, is_kind: bool) -> TokenStream {
+    let var_ident = &var.ident;
+    match &var.fields {
+        VariantFields::Ignored(fields) if !is_kind => {
+            let fields = fields.iter().enumerate().map(|(i, field)| {
+                let default = quote!(::core::default::Default::default());
+                match &field.ident {
+                    Some(name) => quote!(#name: #default),
+                    None => {
+                        let index = syn::Index::from(i);
+                        quote!(#index: #default)
+                    }
+                }
+            });
+            quote_spanned!(var.span => #ty :: #var_ident { #(#fields),* })
+        }
+        _ => quote_spanned!(var.span => #ty :: #var_ident),
+    }
+}
@@ -2,22 +2,26 @@ use std::sync::Arc;
 
 use axum::{
     extract::{MatchedPath, Request, State},
+    http::{header, HeaderMap},
     middleware::Next,
-    response::Response,
+    response::{IntoResponse, Response},
     RequestExt,
 };
+use bytes::{BufMut, BytesMut};
 use measured::{
-    label::{self, LabelValue},
+    label::BoundedInt,
     metric::histogram::Thresholds,
+    negotiate::{NegotiationError, Negotiator},
     text::BufferedTextEncoder,
     CounterVec, FixedCardinalityLabel, HistogramVec, LabelGroup, MetricGroup,
 };
+use measured_prometheus_protobuf::ProtoEncoder;
 use tokio::sync::Mutex;
 
-/// Defines both the metrics and the metrics encoder.
+/// Defines both the metrics and the metrics encoders.
 /// Will be stored in the axum state.
 pub struct AppMetricsEncoder {
-    encoder: Mutex<BufferedTextEncoder>,
+    encoder: Mutex<Negotiator<AppMetrics>>,
     pub metrics: AppMetrics,
 }
 
@@ -44,8 +48,13 @@ pub struct AppMetrics {
 
 impl AppMetricsEncoder {
     pub fn new(metrics: AppMetrics) -> Self {
+        // text is registered first, so it is the default if the scraper has no preference
+        let encoder = Negotiator::new()
+            .with_encoder(BufferedTextEncoder::new())
+            .with_encoder(ProtoEncoder::new(BytesMut::new().writer()));
+
         Self {
-            encoder: Mutex::default(),
+            encoder: Mutex::new(encoder),
             metrics,
         }
     }
@@ -87,19 +96,32 @@ pub async fn middleware(
     http_responses.inc(HttpResponses {
         path,
         method,
-        status: StatusCode(response.status()),
+        status: BoundedInt::saturating(response.status().as_u16().into()),
     });
 
     response
 }
 
-/// sample and export the metrics
-pub async fn handler(s: State<Arc<AppMetricsEncoder>>) -> Response {
+/// sample and export the metrics in the format requested by the scraper
+pub async fn handler(s: State<Arc<AppMetricsEncoder>>, headers: HeaderMap) -> Response {
     let AppMetricsEncoder { encoder, metrics } = &*s.0;
 
+    let accept = headers
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok());
+
     let mut encoder = encoder.lock().await;
-    metrics.collect_group_into(&mut *encoder).unwrap();
-    Response::new(encoder.finish().into())
+    match encoder.encode(accept, metrics) {
+        Ok(encoded) => {
+            ([(header::CONTENT_TYPE, encoded.content_type)], encoded.body).into_response()
+        }
+        Err(NegotiationError::NotAcceptable) => {
+            axum::http::StatusCode::NOT_ACCEPTABLE.into_response()
+        }
+        Err(NegotiationError::Encoding(e)) => {
+            (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+        }
+    }
 }
 
 #[derive(LabelGroup)]
@@ -116,7 +138,8 @@ pub struct HttpResponses<'a> {
     #[label(dynamic_with = Arc<lasso::ThreadedRodeo>)]
     path: &'a str,
     method: Method,
-    status: StatusCode,
+    /// Status code values in the range 100-999 (inclusive) are supported by `http::StatusCode`
+    status: BoundedInt<100, 999>,
 }
 
 // Some wrappers for http types to turn into metric label values
@@ -139,27 +162,3 @@ impl From<&axum::http::Method> for Method {
         }
     }
 }
-
-#[derive(Clone, Copy)]
-struct StatusCode(axum::http::StatusCode);
-
-impl LabelValue for StatusCode {
-    fn visit<V: label::LabelVisitor>(&self, v: V) -> V::Output {
-        v.write_int(self.0.as_u16() as i64)
-    }
-}
-
-impl FixedCardinalityLabel for StatusCode {
-    fn cardinality() -> usize {
-        // Status code values in the range 100-999 (inclusive) are supported by this type
-        (100..1000).len()
-    }
-
-    fn encode(&self) -> usize {
-        self.0.as_u16() as usize - 100
-    }
-
-    fn decode(value: usize) -> Self {
-        Self(axum::http::StatusCode::from_u16(u16::try_from(value).unwrap() + 100).unwrap())
-    }
-}
@@ -72,6 +72,19 @@ where
     encode_varint(value as u64, buf);
 }
 
+pub fn encode_u64<B>(tag: u32, value: u64, buf: &mut B)
+where
+    B: BufMut,
+{
+    encode_key(tag, WireType::Varint, buf);
+    encode_varint(value, buf);
+}
+
+#[inline]
+pub fn encoded_len_u64(tag: u32, value: u64) -> usize {
+    key_len(tag) + encoded_len_varint(value)
+}
+
 pub fn encode_f64<B>(tag: u32, value: f64, buf: &mut B)
 where
     B: BufMut,
@@ -98,3 +111,18 @@ where
 pub fn encoded_len_str(tag: u32, value: &str) -> usize {
     key_len(tag) + encoded_len_varint(value.len() as u64) + value.len()
 }
+
+/// Decodes a LEB128-encoded variable length integer from the buffer, advancing it.
+#[inline]
+pub fn decode_varint(buf: &mut &[u8]) -> Option<u64> {
+    let mut value = 0;
+    for count in 0..10 {
+        let (&byte, rest) = buf.split_first()?;
+        *buf = rest;
+        value |= u64::from(byte & 0x7F) << (count * 7);
+        if byte < 0x80 {
+            return Some(value);
+        }
+    }
+    None
+}
@@ -2,26 +2,33 @@
 
 use std::io::Write;
 
+use bytes::{buf::Writer, Bytes, BytesMut};
 use encoding::{encode_key, encode_varint, encoded_len_varint, key_len, WireType::LengthDelimited};
 use measured::{
-    label::{LabelGroupVisitor, LabelName, LabelValue, LabelVisitor},
+    label::{LabelGroupSet, LabelGroupVisitor, LabelName, LabelValue, LabelVisitor},
     metric::{
         counter::CounterState,
         gauge::{FloatGaugeState, GaugeState},
-        group::Encoding,
+        group::{DynMetricFamily, DynamicEncoding, DynamicFamilies, EncoderList, Encoding},
+        histogram::{HistogramState, Thresholds},
         name::MetricNameEncoder,
+        timestamp::Timestamped,
         MetricEncoding,
     },
+    negotiate::BufferedEncoding,
     LabelGroup,
 };
 
 mod encoding;
+pub mod parse;
 
 /// The prometheus text encoder helper
 pub struct ProtoEncoder<W> {
     state: State,
     pub writer: W,
     buf: Vec<u8>,
+    /// The timestamp of the samples currently being written
+    timestamp: Option<i64>,
 }
 
 impl<W: Write> ProtoEncoder<W> {
@@ -33,6 +40,7 @@ impl<W: Write> ProtoEncoder<W> {
             state: State::Init,
             writer: w,
             buf: Vec::new(),
+            timestamp: None,
         }
     }
 
@@ -110,6 +118,15 @@ impl<W: Write> Encoding for ProtoEncoder<W> {
     }
 }
 
+impl<L: EncoderList, W: Write> DynamicEncoding<L> for ProtoEncoder<W>
+where
+    L::Family: DynMetricFamily<Self>,
+{
+    fn collect_dynamic(&mut self, families: DynamicFamilies<'_, L>) -> Result<(), Self::Err> {
+        families.collect_into(self)
+    }
+}
+
 struct LenVisitor {}
 impl LabelVisitor for LenVisitor {
     type Output = usize;
@@ -134,6 +151,11 @@ impl LabelVisitor for LenVisitor {
     fn write_str(self, x: &str) -> usize {
         encoding::encoded_len_str(2, x)
     }
+
+    fn write_prefixed_str(self, prefix: &str, x: &str) -> usize {
+        let len = prefix.len() + x.len();
+        key_len(2) + encoded_len_varint(len as u64) + len
+    }
 }
 
 struct GroupLenVisitor {
@@ -178,6 +200,14 @@ impl LabelVisitor for Visitor<'_> {
         // optional string value = 2;
         encoding::encode_str(2, x, self.buf);
     }
+
+    fn write_prefixed_str(self, prefix: &str, x: &str) {
+        // optional string value = 2;
+        encode_key(2, LengthDelimited, self.buf);
+        encode_varint((prefix.len() + x.len()) as u64, self.buf);
+        self.buf.extend_from_slice(prefix.as_bytes());
+        self.buf.extend_from_slice(x.as_bytes());
+    }
 }
 
 fn encode_message(
@@ -200,6 +230,17 @@ fn message_len(tag: u32, len: usize) -> usize {
     key_len(tag) + encoded_len_varint(len as u64) + len
 }
 
+fn timestamp_len(timestamp: Option<i64>) -> usize {
+    timestamp.map_or(0, |ts| encoding::encoded_len_u64(6, ts as u64))
+}
+
+fn encode_timestamp(timestamp: Option<i64>, buf: &mut Vec<u8>) {
+    if let Some(ts) = timestamp {
+        // optional int64     timestamp_ms = 6;
+        encoding::encode_u64(6, ts as u64, buf);
+    }
+}
+
 struct GroupVisitor<'a> {
     buf: &'a mut Vec<u8>,
 }
@@ -259,6 +300,7 @@ impl<W: Write> MetricEncoding<ProtoEncoder<W>> for CounterState {
         let count = self.count.load(std::sync::atomic::Ordering::Relaxed) as f64;
         let count_len = encoding::encoded_len_f64(1, count);
         metric_len += message_len(3, count_len);
+        metric_len += timestamp_len(enc.timestamp);
 
         // repeated Metric     metric = 4;
         encode_message(4, metric_len, &mut enc.buf, |buf| {
@@ -269,6 +311,8 @@ impl<W: Write> MetricEncoding<ProtoEncoder<W>> for CounterState {
                 // optional double   value    = 1;
                 encoding::encode_f64(1, count, buf);
             });
+
+            encode_timestamp(enc.timestamp, buf);
         });
 
         Ok(())
@@ -314,6 +358,7 @@ impl<W: Write> MetricEncoding<ProtoEncoder<W>> for GaugeState {
         let gauge = self.count.load(std::sync::atomic::Ordering::Relaxed) as f64;
         let gauge_len = encoding::encoded_len_f64(1, gauge);
         metric_len += message_len(3, gauge_len);
+        metric_len += timestamp_len(enc.timestamp);
 
         // repeated Metric     metric = 4;
         encode_message(4, metric_len, &mut enc.buf, |buf| {
@@ -324,6 +369,8 @@ impl<W: Write> MetricEncoding<ProtoEncoder<W>> for GaugeState {
                 // optional double   value    = 1;
                 encoding::encode_f64(1, gauge, buf);
             });
+
+            encode_timestamp(enc.timestamp, buf);
         });
 
         Ok(())
@@ -369,6 +416,7 @@ impl<W: Write> MetricEncoding<ProtoEncoder<W>> for FloatGaugeState {
         let gauge = self.count.get();
         let gauge_len = encoding::encoded_len_f64(1, gauge);
         metric_len += message_len(3, gauge_len);
+        metric_len += timestamp_len(enc.timestamp);
 
         // repeated Metric     metric = 4;
         encode_message(4, metric_len, &mut enc.buf, |buf| {
@@ -379,32 +427,185 @@ impl<W: Write> MetricEncoding<ProtoEncoder<W>> for FloatGaugeState {
                 // optional double   value    = 1;
                 encoding::encode_f64(1, gauge, buf);
             });
+
+            encode_timestamp(enc.timestamp, buf);
+        });
+
+        Ok(())
+    }
+}
+
+impl<W: Write, const N: usize> MetricEncoding<ProtoEncoder<W>> for HistogramState<N> {
+    fn write_type(
+        name: impl MetricNameEncoder,
+        enc: &mut ProtoEncoder<W>,
+    ) -> Result<(), std::io::Error> {
+        enc.flush_buf()?;
+
+        if enc.state == State::Init {
+            // optional string     name   = 1;
+            encode_key(1, LengthDelimited, &mut enc.buf);
+            encode_varint(name.encode_len() as u64, &mut enc.buf);
+            name.encode_utf8(&mut enc.buf)?;
+        }
+
+        // optional MetricType type   = 3;
+        // HISTOGRAM = 4;
+        encoding::encode_i32(3, 4, &mut enc.buf);
+
+        Ok(())
+    }
+
+    fn collect_into(
+        &self,
+        metadata: &Thresholds<N>,
+        labels: impl LabelGroup,
+        _name: impl MetricNameEncoder,
+        enc: &mut ProtoEncoder<W>,
+    ) -> Result<(), std::io::Error> {
+        enc.state = State::Metrics;
+
+        let (buckets, inf, sum) = self.inner.write().sample();
+
+        let mut cumulative = [0; N];
+        let mut val = 0;
+        for (c, b) in cumulative.iter_mut().zip(buckets) {
+            val += b;
+            *c = val;
+        }
+        let count = val + inf;
+
+        let bucket_len =
+            |c: u64, le: f64| encoding::encoded_len_u64(1, c) + encoding::encoded_len_f64(2, le);
+
+        let mut histogram_len = 0;
+        histogram_len += encoding::encoded_len_u64(1, count);
+        histogram_len += encoding::encoded_len_f64(2, sum);
+        for (&c, &le) in cumulative.iter().zip(metadata.get()) {
+            histogram_len += message_len(3, bucket_len(c, le));
+        }
+
+        let mut metric_len = 0;
+
+        let mut label_pairs_len = GroupLenVisitor { len: 0 };
+        labels.visit_values(&mut label_pairs_len);
+        metric_len += label_pairs_len.len;
+        metric_len += timestamp_len(enc.timestamp);
+        metric_len += message_len(7, histogram_len);
+
+        // repeated Metric     metric = 4;
+        encode_message(4, metric_len, &mut enc.buf, |buf| {
+            labels.visit_values(&mut GroupVisitor { buf });
+
+            encode_timestamp(enc.timestamp, buf);
+
+            // optional Histogram histogram    = 7;
+            encode_message(7, histogram_len, buf, |buf| {
+                // optional uint64 sample_count = 1;
+                encoding::encode_u64(1, count, buf);
+                // optional double sample_sum   = 2;
+                encoding::encode_f64(2, sum, buf);
+
+                // repeated Bucket bucket       = 3;
+                // the +Inf bucket is implied by the sample count.
+                for (&c, &le) in cumulative.iter().zip(metadata.get()) {
+                    encode_message(3, bucket_len(c, le), buf, |buf| {
+                        // optional uint64 cumulative_count = 1;
+                        encoding::encode_u64(1, c, buf);
+                        // optional double upper_bound = 2;
+                        encoding::encode_f64(2, le, buf);
+                    });
+                }
+            });
         });
 
         Ok(())
     }
 }
 
+impl<W: Write, M: MetricEncoding<ProtoEncoder<W>>> MetricEncoding<ProtoEncoder<W>>
+    for Timestamped<M>
+{
+    fn write_type(
+        name: impl MetricNameEncoder,
+        enc: &mut ProtoEncoder<W>,
+    ) -> Result<(), std::io::Error> {
+        M::write_type(name, enc)
+    }
+
+    fn write_metadata(
+        name: impl MetricNameEncoder,
+        metadata: &M::Metadata,
+        labels: &impl LabelGroupSet,
+        enc: &mut ProtoEncoder<W>,
+    ) -> Result<(), std::io::Error> {
+        M::write_metadata(name, metadata, labels, enc)
+    }
+
+    fn collect_into(
+        &self,
+        metadata: &M::Metadata,
+        labels: impl LabelGroup,
+        name: impl MetricNameEncoder,
+        enc: &mut ProtoEncoder<W>,
+    ) -> Result<(), std::io::Error> {
+        let prev = enc.timestamp.replace(self.timestamp_ms());
+        let res = self.metric().collect_into(metadata, labels, name, enc);
+        enc.timestamp = prev;
+        res
+    }
+}
+
+impl BufferedEncoding for ProtoEncoder<Writer<BytesMut>> {
+    fn finish(&mut self) -> Result<Bytes, std::io::Error> {
+        self.flush()?;
+        Ok(self.writer.get_mut().split().freeze())
+    }
+
+    fn reset(&mut self) {
+        // the default would flush a partially written family into the next collection
+        self.buf.clear();
+        self.buf.resize(10, 0);
+        self.state = State::Init;
+        self.timestamp = None;
+        self.writer.get_mut().clear();
+    }
+}
+
 #[cfg(test)]
 mod generated;
 
 #[cfg(test)]
 mod tests {
-    use std::vec;
+    use std::{
+        time::{Duration, UNIX_EPOCH},
+        vec,
+    };
 
     use bytes::{BufMut, BytesMut};
     use measured::{
+        label::NoLabels,
         metric::{
+            gauge::{write_float_gauge, write_float_gauge_at, FloatGaugeState},
             group::Encoding,
+            histogram::Thresholds,
             name::{MetricName, Total},
-            MetricFamilyEncoding,
+            timestamp::Timestamped,
+            MetricEncoding, MetricFamilyEncoding,
         },
-        CounterVec, GaugeVec,
+        negotiate::BufferedEncoding,
+        parse::parse_text,
+        text::BufferedTextEncoder,
+        CounterVec, GaugeVec, Histogram,
     };
     use prost::Message;
 
     use crate::{
-        generated::{Counter, Gauge, LabelPair, Metric, MetricFamily, MetricType},
+        generated::{
+            Bucket, Counter, Gauge, Histogram as ProtoHistogram, LabelPair, Metric, MetricFamily,
+            MetricType,
+        },
+        parse::parse_delimited,
         ProtoEncoder,
     };
 
@@ -423,11 +624,31 @@ mod tests {
     }
 
     #[derive(Clone, Copy, PartialEq, Debug, measured::FixedCardinalityLabel)]
+    #[label(from_str)]
     enum StatusCode {
         Ok = 200,
         BadRequest = 400,
     }
 
+    // the generated code should also compile in edition 2021 crates, like this one
+    #[derive(Clone, Copy, PartialEq, Debug, measured::FixedCardinalityLabel)]
+    #[label(rename_all = "snake_case", from_str)]
+    enum RequestError {
+        Timeout,
+        #[label(flatten)]
+        Status(StatusCode),
+    }
+
+    #[test]
+    fn parse_label_values() {
+        assert_eq!("timeout".parse(), Ok(RequestError::Timeout));
+        assert_eq!(
+            "status_400".parse(),
+            Ok(RequestError::Status(StatusCode::BadRequest))
+        );
+        assert!("status_404".parse::<RequestError>().is_err());
+    }
+
     #[test]
     fn counters() {
         let requests = CounterVec::<RequestLabelSet>::new();
@@ -593,4 +814,247 @@ mod tests {
         let actual = MetricFamily::decode_length_delimited(actual_msg).unwrap();
         assert_eq!(actual, expected);
     }
+
+    #[test]
+    fn timestamps() {
+        let time = UNIX_EPOCH + Duration::from_millis(1_700_000_000_123);
+        let name = MetricName::from_str("device_temperature");
+
+        let mut proto = ProtoEncoder::new(BytesMut::new().writer());
+        let mut text = BufferedTextEncoder::new();
+
+        proto.write_help(name, "The device temperature.").unwrap();
+        <Timestamped<FloatGaugeState>>::write_type(name, &mut proto).unwrap();
+        write_float_gauge_at(&mut proto, name, NoLabels, 21.5, time).unwrap();
+        write_float_gauge(&mut proto, name, NoLabels, 22.0).unwrap();
+
+        text.write_help(name, "The device temperature.").unwrap();
+        <Timestamped<FloatGaugeState>>::write_type(name, &mut text).unwrap();
+        write_float_gauge_at(&mut text, name, NoLabels, 21.5, time).unwrap();
+        write_float_gauge(&mut text, name, NoLabels, 22.0).unwrap();
+
+        let actual_msg = proto.finish().unwrap();
+
+        let expected = MetricFamily {
+            name: Some("device_temperature".to_owned()),
+            help: Some("The device temperature.".to_owned()),
+            r#type: Some(MetricType::Gauge as i32),
+            metric: vec![
+                Metric {
+                    label: vec![],
+                    gauge: Some(Gauge { value: Some(21.5) }),
+                    counter: None,
+                    summary: None,
+                    untyped: None,
+                    histogram: None,
+                    timestamp_ms: Some(1_700_000_000_123),
+                },
+                Metric {
+                    label: vec![],
+                    gauge: Some(Gauge { value: Some(22.0) }),
+                    counter: None,
+                    summary: None,
+                    untyped: None,
+                    histogram: None,
+                    timestamp_ms: None,
+                },
+            ],
+            unit: None,
+        };
+        let mut expected_msg = BytesMut::new();
+        expected.encode_length_delimited(&mut expected_msg).unwrap();
+        assert_eq!(actual_msg, expected_msg);
+
+        let from_proto = parse_delimited(&actual_msg).unwrap();
+        let from_text = parse_text(std::str::from_utf8(&text.finish()).unwrap()).unwrap();
+        assert_eq!(from_proto, from_text);
+    }
+
+    #[test]
+    fn parse_round_trip() {
+        let requests = CounterVec::<RequestLabelSet>::new();
+        requests.inc_by(
+            RequestLabels {
+                method: Method::Post,
+                code: StatusCode::Ok,
+            },
+            1027,
+        );
+        let inflight = GaugeVec::<RequestLabelSet>::new();
+        inflight.set(
+            RequestLabels {
+                method: Method::Get,
+                code: StatusCode::BadRequest,
+            },
+            -3,
+        );
+
+        let mut proto = ProtoEncoder::new(BytesMut::new().writer());
+        let mut text = BufferedTextEncoder::new();
+
+        let requests_name = MetricName::from_str("http_request").with_suffix(Total);
+        let inflight_name = MetricName::from_str("http_inflight");
+
+        proto
+            .write_help(&requests_name, "The total number of HTTP requests.")
+            .unwrap();
+        requests
+            .collect_family_into(&requests_name, &mut proto)
+            .unwrap();
+        proto
+            .write_help(inflight_name, "The number of in-flight HTTP requests.")
+            .unwrap();
+        inflight
+            .collect_family_into(inflight_name, &mut proto)
+            .unwrap();
+        proto.flush().unwrap();
+
+        text.write_help(&requests_name, "The total number of HTTP requests.")
+            .unwrap();
+        requests
+            .collect_family_into(&requests_name, &mut text)
+            .unwrap();
+        text.write_help(inflight_name, "The number of in-flight HTTP requests.")
+            .unwrap();
+        inflight
+            .collect_family_into(inflight_name, &mut text)
+            .unwrap();
+
+        let from_proto = parse_delimited(&proto.writer.into_inner()).unwrap();
+        let from_text = parse_text(std::str::from_utf8(&text.finish()).unwrap()).unwrap();
+
+        assert_eq!(from_proto.len(), 2);
+        assert_eq!(from_proto, from_text);
+    }
+
+    #[test]
+    fn parse_histogram() {
+        let histogram = Histogram::with_metadata(Thresholds::<3>::linear_buckets(1.0, 1.0));
+        histogram.get_metric().observe(0.5);
+        histogram.get_metric().observe(2.5);
+        histogram.get_metric().observe(10.0);
+
+        let name = MetricName::from_str("duration_seconds");
+        let mut text = BufferedTextEncoder::new();
+        text.write_help(name, "Request durations").unwrap();
+        histogram.collect_family_into(name, &mut text).unwrap();
+        let from_text = parse_text(std::str::from_utf8(&text.finish()).unwrap()).unwrap();
+
+        let bucket = |le: f64, count: u64| Bucket {
+            cumulative_count: Some(count),
+            upper_bound: Some(le),
+            ..Bucket::default()
+        };
+        let family = MetricFamily {
+            name: Some("duration_seconds".to_owned()),
+            help: Some("Request durations".to_owned()),
+            r#type: Some(MetricType::Histogram as i32),
+            metric: vec![Metric {
+                histogram: Some(ProtoHistogram {
+                    sample_count: Some(3),
+                    sample_sum: Some(13.0),
+                    bucket: vec![bucket(1.0, 1), bucket(2.0, 1), bucket(3.0, 2)],
+                    ..ProtoHistogram::default()
+                }),
+                ..Metric::default()
+            }],
+            unit: None,
+        };
+        let mut msg = BytesMut::new();
+        family.encode_length_delimited(&mut msg).unwrap();
+        let from_proto = parse_delimited(&msg).unwrap();
+
+        assert_eq!(from_proto, from_text);
+
+        let mut enc = ProtoEncoder::new(BytesMut::new().writer());
+        enc.write_help(name, "Request durations").unwrap();
+        histogram.collect_family_into(name, &mut enc).unwrap();
+        let encoded = enc.finish().unwrap();
+
+        assert_eq!(encoded, msg);
+        assert_eq!(parse_delimited(&encoded).unwrap(), from_text);
+
+        let invalid = MetricFamily {
+            metric: vec![Metric {
+                histogram: Some(ProtoHistogram {
+                    sample_count: Some(3),
+                    bucket: vec![bucket(1.0, 2), bucket(2.0, 1)],
+                    ..ProtoHistogram::default()
+                }),
+                ..Metric::default()
+            }],
+            ..family
+        };
+        let mut msg = BytesMut::new();
+        invalid.encode_length_delimited(&mut msg).unwrap();
+        assert_eq!(
+            parse_delimited(&msg).unwrap_err(),
+            crate::parse::DecodeError::Invalid(
+                measured::parse::ParseErrorKind::NonMonotonicBuckets
+            )
+        );
+    }
+
+    #[test]
+    fn dynamic_group() {
+        use bytes::buf::Writer;
+        use measured::{
+            metric::{group::DynamicMetricGroup, name::MetricNameBuf},
+            negotiate::Negotiator,
+            MetricGroup,
+        };
+
+        type Proto = ProtoEncoder<Writer<BytesMut>>;
+
+        let group = DynamicMetricGroup::<(BufferedTextEncoder, Proto)>::new();
+        let requests = CounterVec::<RequestLabelSet>::new();
+        requests.inc(RequestLabels {
+            method: Method::Get,
+            code: StatusCode::Ok,
+        });
+        group.insert(MetricNameBuf::try_from("http_requests").unwrap(), requests);
+
+        let mut negotiator = Negotiator::new()
+            .with_encoder(BufferedTextEncoder::new())
+            .with_encoder(ProtoEncoder::new(BytesMut::new().writer()));
+
+        let text = negotiator.encode(None, &group).unwrap();
+        let proto = negotiator.encode(Some(Proto::MIME_TYPE), &group).unwrap();
+        assert_eq!(proto.content_type, Proto::MIME_TYPE);
+
+        let from_text = parse_text(std::str::from_utf8(&text.body).unwrap()).unwrap();
+        let from_proto = parse_delimited(&proto.body).unwrap();
+        assert_eq!(from_proto.len(), 1);
+        assert_eq!(from_proto, from_text);
+
+        let mut enc = ProtoEncoder::new(BytesMut::new().writer());
+        group.collect_group_into(&mut enc).unwrap();
+        assert_eq!(enc.finish().unwrap(), proto.body);
+    }
+
+    #[test]
+    fn reset_after_help() {
+        let requests = CounterVec::<RequestLabelSet>::new();
+        requests.inc(RequestLabels {
+            method: Method::Get,
+            code: StatusCode::Ok,
+        });
+        let name = MetricName::from_str("http_requests");
+
+        let mut expected = ProtoEncoder::new(BytesMut::new().writer());
+        requests.collect_family_into(name, &mut expected).unwrap();
+        let expected = expected.finish().unwrap();
+
+        // the collection fails after the help of another family was written
+        let mut enc = ProtoEncoder::new(BytesMut::new().writer());
+        enc.write_help(
+            MetricName::from_str("broken"),
+            "this family failed to collect",
+        )
+        .unwrap();
+        enc.reset();
+
+        requests.collect_family_into(name, &mut enc).unwrap();
+        assert_eq!(enc.finish().unwrap(), expected);
+    }
 }
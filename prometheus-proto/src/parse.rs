@@ -0,0 +1,336 @@
+//! Decoder for the delimited protobuf exposition format
+//!
+//! This decodes the output of [`ProtoEncoder`](crate::ProtoEncoder) (or any other prometheus client)
+//! into the same [`MetricFamily`] representation that [`measured::parse::parse_text`] produces.
+//! Histograms and summaries are flattened into their `_bucket`, `_sum` and `_count` samples.
+
+use measured::{
+    label::{LabelVisitor, RenderValue},
+    parse::{MetricFamily, ParseErrorKind, Sample},
+    text::MetricType,
+};
+
+use crate::encoding::decode_varint;
+
+/// Error returned by [`parse_delimited`]
+#[derive(Clone, Debug, PartialEq)]
+pub enum DecodeError {
+    /// The input ended in the middle of a message
+    Truncated,
+    /// A field used an unsupported wire type
+    InvalidWireType(u8),
+    /// A string field was not valid UTF-8
+    InvalidUtf8,
+    /// A metric family had an unknown metric type
+    InvalidMetricType(i32),
+    /// A metric did not contain a value for the type of its family
+    MissingValue,
+    /// The decoded metric family was not valid
+    Invalid(ParseErrorKind),
+}
+
+impl core::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            DecodeError::Truncated => f.write_str("message was truncated"),
+            DecodeError::InvalidWireType(w) => write!(f, "invalid wire type {w}"),
+            DecodeError::InvalidUtf8 => f.write_str("string was not valid utf8"),
+            DecodeError::InvalidMetricType(t) => write!(f, "invalid metric type {t}"),
+            DecodeError::MissingValue => f.write_str("metric is missing a value for its type"),
+            DecodeError::Invalid(kind) => kind.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Decode a stream of length-delimited `MetricFamily` messages.
+///
+/// # Errors
+/// Will error if the input is not valid protobuf, or if any decoded family
+/// fails [`MetricFamily::validate`].
+pub fn parse_delimited(mut buf: &[u8]) -> Result<Vec<MetricFamily>, DecodeError> {
+    let mut families: Vec<MetricFamily> = Vec::new();
+    while !buf.is_empty() {
+        let len = decode_varint(&mut buf).ok_or(DecodeError::Truncated)? as usize;
+        if buf.len() < len {
+            return Err(DecodeError::Truncated);
+        }
+        let (message, rest) = buf.split_at(len);
+        buf = rest;
+
+        let family = decode_family(message)?;
+        family.validate().map_err(DecodeError::Invalid)?;
+        if families.iter().any(|f| f.name == family.name) {
+            return Err(DecodeError::Invalid(ParseErrorKind::DuplicateFamily(
+                family.name,
+            )));
+        }
+        families.push(family);
+    }
+    Ok(families)
+}
+
+enum Field<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    Bytes(&'a [u8]),
+    Fixed32,
+}
+
+impl Field<'_> {
+    fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Field::Fixed64(x) => Some(f64::from_bits(x)),
+            _ => None,
+        }
+    }
+
+    fn as_u64(&self) -> Option<u64> {
+        match *self {
+            Field::Varint(x) => Some(x),
+            _ => None,
+        }
+    }
+}
+
+/// Iterates over the `(tag, value)` pairs of a protobuf message
+struct Fields<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Iterator for Fields<'a> {
+    type Item = Result<(u32, Field<'a>), DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.buf.is_empty() {
+            return None;
+        }
+        Some(self.read_field())
+    }
+}
+
+impl<'a> Fields<'a> {
+    fn read_field(&mut self) -> Result<(u32, Field<'a>), DecodeError> {
+        let key = decode_varint(&mut self.buf).ok_or(DecodeError::Truncated)?;
+        let tag = (key >> 3) as u32;
+        let field = match key & 0x7 {
+            0 => Field::Varint(decode_varint(&mut self.buf).ok_or(DecodeError::Truncated)?),
+            1 => Field::Fixed64(u64::from_le_bytes(self.take(8)?.try_into().unwrap())),
+            2 => {
+                let len = decode_varint(&mut self.buf).ok_or(DecodeError::Truncated)?;
+                Field::Bytes(self.take(len as usize)?)
+            }
+            5 => {
+                self.take(4)?;
+                Field::Fixed32
+            }
+            w => return Err(DecodeError::InvalidWireType(w as u8)),
+        };
+        Ok((tag, field))
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], DecodeError> {
+        if self.buf.len() < n {
+            return Err(DecodeError::Truncated);
+        }
+        let (taken, rest) = self.buf.split_at(n);
+        self.buf = rest;
+        Ok(taken)
+    }
+}
+
+fn fields(buf: &[u8]) -> Fields<'_> {
+    Fields { buf }
+}
+
+fn decode_str(field: &Field<'_>) -> Result<String, DecodeError> {
+    match *field {
+        Field::Bytes(b) => std::str::from_utf8(b)
+            .map(str::to_owned)
+            .map_err(|_| DecodeError::InvalidUtf8),
+        Field::Varint(_) => Err(DecodeError::InvalidWireType(0)),
+        Field::Fixed64(_) => Err(DecodeError::InvalidWireType(1)),
+        Field::Fixed32 => Err(DecodeError::InvalidWireType(5)),
+    }
+}
+
+fn decode_family(buf: &[u8]) -> Result<MetricFamily, DecodeError> {
+    let mut name = String::new();
+    let mut help = None;
+    let mut typ = 0;
+    let mut metrics = Vec::new();
+
+    for field in fields(buf) {
+        match field? {
+            // optional string     name   = 1;
+            (1, f) => name = decode_str(&f)?,
+            // optional string     help   = 2;
+            (2, f) => help = Some(decode_str(&f)?),
+            // optional MetricType type   = 3;
+            (3, Field::Varint(t)) => typ = t as i32,
+            // repeated Metric     metric = 4;
+            (4, Field::Bytes(m)) => metrics.push(m),
+            _ => {}
+        }
+    }
+
+    let metric_type = match typ {
+        0 => MetricType::Counter,
+        1 => MetricType::Gauge,
+        2 => MetricType::Summary,
+        3 => MetricType::Untyped,
+        4 => MetricType::Histogram,
+        t => return Err(DecodeError::InvalidMetricType(t)),
+    };
+
+    let mut family = MetricFamily {
+        name,
+        help,
+        metric_type,
+        samples: Vec::new(),
+    };
+    for metric in metrics {
+        decode_metric(metric, &mut family)?;
+    }
+    Ok(family)
+}
+
+fn decode_metric(buf: &[u8], family: &mut MetricFamily) -> Result<(), DecodeError> {
+    let mut labels = Vec::new();
+    let mut timestamp_ms = None;
+    let mut value = None;
+
+    for field in fields(buf) {
+        match field? {
+            // repeated LabelPair label        = 1;
+            (1, Field::Bytes(pair)) => {
+                let mut name = String::new();
+                let mut value = String::new();
+                for field in fields(pair) {
+                    match field? {
+                        (1, f) => name = decode_str(&f)?,
+                        (2, f) => value = decode_str(&f)?,
+                        _ => {}
+                    }
+                }
+                labels.push((name, value));
+            }
+            // optional Gauge     gauge        = 2;
+            (2, Field::Bytes(m)) if family.metric_type == MetricType::Gauge => value = Some(m),
+            // optional Counter   counter      = 3;
+            (3, Field::Bytes(m)) if family.metric_type == MetricType::Counter => value = Some(m),
+            // optional Summary   summary      = 4;
+            (4, Field::Bytes(m)) if family.metric_type == MetricType::Summary => value = Some(m),
+            // optional Untyped   untyped      = 5;
+            (5, Field::Bytes(m)) if family.metric_type == MetricType::Untyped => value = Some(m),
+            // optional Histogram histogram    = 7;
+            (7, Field::Bytes(m)) if family.metric_type == MetricType::Histogram => value = Some(m),
+            // optional int64     timestamp_ms = 6;
+            (6, Field::Varint(ts)) => timestamp_ms = Some(ts as i64),
+            _ => {}
+        }
+    }
+
+    let value = value.ok_or(DecodeError::MissingValue)?;
+    let metric_type = family.metric_type;
+    let mut push = |suffix: &str, extra: Option<(&str, f64)>, value: f64| {
+        let mut labels = labels.clone();
+        if let Some((name, x)) = extra {
+            labels.push((name.to_owned(), RenderValue.write_float(x)));
+        }
+        family.samples.push(Sample {
+            name: format!("{}{suffix}", family.name),
+            labels,
+            value,
+            timestamp_ms,
+        });
+    };
+
+    match metric_type {
+        MetricType::Counter | MetricType::Gauge | MetricType::Untyped => {
+            let mut x = 0.0;
+            for field in fields(value) {
+                // optional double value = 1;
+                if let (1, f) = field? {
+                    x = f.as_f64().ok_or(DecodeError::InvalidWireType(1))?;
+                }
+            }
+            push("", None, x);
+        }
+        MetricType::Summary => {
+            let mut count = 0.0;
+            let mut sum = 0.0;
+            for field in fields(value) {
+                match field? {
+                    // optional uint64   sample_count = 1;
+                    (1, f) => count = f.as_u64().ok_or(DecodeError::InvalidWireType(0))? as f64,
+                    // optional double   sample_sum   = 2;
+                    (2, f) => sum = f.as_f64().ok_or(DecodeError::InvalidWireType(1))?,
+                    // repeated Quantile quantile     = 3;
+                    (3, Field::Bytes(q)) => {
+                        let mut quantile = 0.0;
+                        let mut x = 0.0;
+                        for field in fields(q) {
+                            match field? {
+                                (1, f) => quantile = f.as_f64().unwrap_or_default(),
+                                (2, f) => x = f.as_f64().unwrap_or_default(),
+                                _ => {}
+                            }
+                        }
+                        push("", Some(("quantile", quantile)), x);
+                    }
+                    _ => {}
+                }
+            }
+            push("_sum", None, sum);
+            push("_count", None, count);
+        }
+        MetricType::Histogram => {
+            let mut count = 0.0;
+            let mut count_float = None;
+            let mut sum = 0.0;
+            let mut last_le = None;
+            for field in fields(value) {
+                match field? {
+                    // optional uint64 sample_count = 1;
+                    (1, f) => count = f.as_u64().ok_or(DecodeError::InvalidWireType(0))? as f64,
+                    // optional double sample_count_float = 4;
+                    (4, f) => count_float = f.as_f64().filter(|c| *c > 0.0),
+                    // optional double sample_sum = 2;
+                    (2, f) => sum = f.as_f64().ok_or(DecodeError::InvalidWireType(1))?,
+                    // repeated Bucket bucket = 3;
+                    (3, Field::Bytes(b)) => {
+                        let mut cumulative = 0.0;
+                        let mut cumulative_float = None;
+                        let mut le = 0.0;
+                        for field in fields(b) {
+                            match field? {
+                                (1, f) => cumulative = f.as_u64().unwrap_or_default() as f64,
+                                (4, f) => cumulative_float = f.as_f64().filter(|c| *c > 0.0),
+                                (2, f) => le = f.as_f64().unwrap_or_default(),
+                                _ => {}
+                            }
+                        }
+                        last_le = Some(le);
+                        push(
+                            "_bucket",
+                            Some(("le", le)),
+                            cumulative_float.unwrap_or(cumulative),
+                        );
+                    }
+                    _ => {}
+                }
+            }
+            let count = count_float.unwrap_or(count);
+            // the +Inf bucket is optional in the protobuf format
+            if last_le != Some(f64::INFINITY) {
+                push("_bucket", Some(("le", f64::INFINITY)), count);
+            }
+            push("_sum", None, sum);
+            push("_count", None, count);
+        }
+    }
+
+    Ok(())
+}
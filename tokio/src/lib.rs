@@ -41,6 +41,9 @@ use measured::{
 };
 use tokio::runtime::RuntimeMetrics;
 
+#[cfg(feature = "stream")]
+pub mod stream;
+
 /// A collector which contains multiple named tokio runtimes
 pub struct NamedRuntimesCollector {
     runtimes: RwLock<Vec<RuntimeCollector>>,
@@ -0,0 +1,335 @@
+//! Stream the text encoding of a metric group in bounded-size chunks.
+//!
+//! [`TextEncoder`] is synchronous, so encoding a large metric group would either
+//! block the executor, or need the entire output to be buffered in memory.
+//! [`text_stream`] instead collects the group on the blocking thread pool,
+//! sending the output back in chunks over a bounded channel. At most a few chunks are
+//! held in memory at once, and the collection is paused while the consumer is slow.
+//!
+//! ```
+//! use std::sync::Arc;
+//!
+//! use measured::{Counter, MetricGroup};
+//!
+//! #[derive(MetricGroup)]
+//! struct MyAppMetrics {
+//!     /// total requests served
+//!     requests: Counter,
+//! }
+//!
+//! # tokio::runtime::Runtime::new().unwrap().block_on(async {
+//! let metrics = Arc::new(MyAppMetrics { requests: Counter::new() });
+//!
+//! let mut output = Vec::new();
+//! measured_tokio::stream::text_stream(metrics, 64 * 1024)
+//!     .write_to(&mut output)
+//!     .await
+//!     .unwrap();
+//!
+//! assert_eq!(
+//!     std::str::from_utf8(&output).unwrap(),
+//!     "# HELP requests total requests served\n# TYPE requests counter\nrequests 0\n"
+//! );
+//! # });
+//! ```
+
+use std::{
+    io::{self, Write},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, ready},
+};
+
+use bytes::{Bytes, BytesMut};
+use measured::{MetricGroup, text::TextEncoder};
+use tokio::{
+    io::{AsyncWrite, AsyncWriteExt},
+    sync::mpsc,
+    task::JoinHandle,
+};
+
+/// The [`io::Write`] used by the [`TextEncoder`] in [`text_stream`].
+///
+/// Buffers the encoded output, and sends it to the [`TextStream`] once a chunk is full.
+pub struct ChunkWriter {
+    buf: BytesMut,
+    chunk_size: usize,
+    tx: mpsc::Sender<io::Result<Bytes>>,
+}
+
+impl ChunkWriter {
+    fn send(&mut self, chunk: io::Result<Bytes>) -> io::Result<()> {
+        self.tx
+            .blocking_send(chunk)
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "text stream was dropped"))
+    }
+
+    fn send_buf(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let chunk = self.buf.split().freeze();
+        self.send(Ok(chunk))
+    }
+}
+
+impl Write for ChunkWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        if self.buf.len() >= self.chunk_size {
+            self.send_buf()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.send_buf()
+    }
+}
+
+/// Collect the metric group in the prometheus text format, as a stream of chunks.
+///
+/// Collection runs on the blocking thread pool using [`tokio::task::spawn_blocking`].
+/// Chunks are roughly `chunk_size` bytes, and only a single chunk is buffered ahead
+/// of the consumer. Dropping the [`TextStream`] cancels the collection.
+///
+/// # Panics
+///
+/// This will panic if called outside the context of a Tokio runtime.
+pub fn text_stream<G>(group: Arc<G>, chunk_size: usize) -> TextStream
+where
+    G: MetricGroup<TextEncoder<ChunkWriter>> + Send + Sync + 'static + ?Sized,
+{
+    let (tx, rx) = mpsc::channel(1);
+
+    let task = tokio::task::spawn_blocking(move || {
+        let mut enc = TextEncoder::new(ChunkWriter {
+            buf: BytesMut::with_capacity(chunk_size),
+            chunk_size,
+            tx,
+        });
+
+        let res = group
+            .collect_group_into(&mut enc)
+            .and_then(|()| enc.flush());
+
+        // report the error to the stream, if it is still listening.
+        if let Err(e) = res {
+            let _ = enc.writer.send(Err(e));
+        }
+    });
+
+    TextStream {
+        rx,
+        task: Some(task),
+    }
+}
+
+/// A stream of prometheus text encoded chunks, created by [`text_stream`].
+///
+/// Implements [`futures_core::Stream`], so it can be used directly as a streaming response body.
+pub struct TextStream {
+    rx: mpsc::Receiver<io::Result<Bytes>>,
+    task: Option<JoinHandle<()>>,
+}
+
+impl TextStream {
+    /// Receive the next chunk of encoded metrics.
+    ///
+    /// Returns `None` once the collection is complete.
+    pub async fn next_chunk(&mut self) -> Option<io::Result<Bytes>> {
+        std::future::poll_fn(|cx| self.poll_chunk(cx)).await
+    }
+
+    /// Write all the encoded metrics to the writer.
+    ///
+    /// # Errors
+    ///
+    /// Will error if the collection failed, or if the writer errored.
+    pub async fn write_to<W: AsyncWrite + Unpin + ?Sized>(mut self, w: &mut W) -> io::Result<()> {
+        while let Some(chunk) = self.next_chunk().await {
+            w.write_all(&chunk?).await?;
+        }
+        w.flush().await
+    }
+
+    fn poll_chunk(&mut self, cx: &mut Context<'_>) -> Poll<Option<io::Result<Bytes>>> {
+        if let Some(chunk) = ready!(self.rx.poll_recv(cx)) {
+            return Poll::Ready(Some(chunk));
+        }
+
+        // the channel is closed. make sure the collection did not panic.
+        let Some(task) = &mut self.task else {
+            return Poll::Ready(None);
+        };
+        let res = ready!(Pin::new(task).poll(cx));
+        self.task = None;
+        Poll::Ready(res.err().map(|e| Err(io::Error::other(e))))
+    }
+}
+
+impl futures_core::Stream for TextStream {
+    type Item = io::Result<Bytes>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_chunk(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering},
+        },
+        time::{Duration, Instant},
+    };
+
+    use measured::{
+        MetricGroup,
+        metric::{group::Encoding, name::MetricName},
+        text::TextEncoder,
+    };
+
+    use super::{ChunkWriter, text_stream};
+
+    /// Writes a help line for each family, counting the families written.
+    struct Families {
+        count: usize,
+        fail_at: Option<usize>,
+        written: AtomicUsize,
+    }
+
+    impl Families {
+        fn new(count: usize) -> Arc<Self> {
+            Arc::new(Self {
+                count,
+                fail_at: None,
+                written: AtomicUsize::new(0),
+            })
+        }
+
+        fn written(&self) -> usize {
+            self.written.load(Ordering::SeqCst)
+        }
+
+        fn expected(&self) -> String {
+            (0..self.count)
+                .map(|i| format!("# HELP metric {i}\n"))
+                .collect()
+        }
+    }
+
+    impl MetricGroup<TextEncoder<ChunkWriter>> for Families {
+        fn collect_group_into(&self, enc: &mut TextEncoder<ChunkWriter>) -> io::Result<()> {
+            for i in 0..self.count {
+                if self.fail_at == Some(i) {
+                    return Err(io::Error::other("scrape failed"));
+                }
+                enc.write_help(MetricName::from_str("metric"), &i.to_string())?;
+                self.written.fetch_add(1, Ordering::SeqCst);
+            }
+            Ok(())
+        }
+    }
+
+    fn wait_until(mut f: impl FnMut() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !f() {
+            assert!(Instant::now() < deadline, "timed out");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    fn block_on<F: Future>(f: F) -> F::Output {
+        tokio::runtime::Runtime::new().unwrap().block_on(f)
+    }
+
+    #[test]
+    fn chunks() {
+        let group = Families::new(100);
+
+        let chunks = block_on(async {
+            let mut stream = text_stream(group.clone(), 64);
+            let mut chunks = vec![];
+            while let Some(chunk) = stream.next_chunk().await {
+                chunks.push(chunk.unwrap());
+            }
+            chunks
+        });
+
+        assert!(chunks.len() > 1);
+        let (last, full) = chunks.split_last().unwrap();
+        assert!(full.iter().all(|chunk| chunk.len() >= 64));
+        assert!(!last.is_empty());
+
+        assert_eq!(chunks.concat(), group.expected().as_bytes());
+    }
+
+    #[test]
+    fn backpressure() {
+        let group = Families::new(10);
+
+        block_on(async {
+            // every line is sent as its own chunk
+            let mut stream = text_stream(group.clone(), 16);
+
+            // one chunk is buffered in the channel, and the collection is blocked sending the next
+            wait_until(|| group.written() == 1);
+            std::thread::sleep(Duration::from_millis(50));
+            assert_eq!(group.written(), 1);
+
+            let chunk = stream.next_chunk().await.unwrap().unwrap();
+            assert_eq!(chunk, "# HELP metric 0\n");
+
+            wait_until(|| group.written() == 2);
+            std::thread::sleep(Duration::from_millis(50));
+            assert_eq!(group.written(), 2);
+        });
+    }
+
+    #[test]
+    fn cancel() {
+        let group = Families::new(100);
+
+        block_on(async {
+            let mut stream = text_stream(group.clone(), 1);
+            stream.next_chunk().await.unwrap().unwrap();
+            drop(stream);
+        });
+
+        // the collection stops once it fails to send the next chunk, releasing the group
+        wait_until(|| Arc::strong_count(&group) == 1);
+        assert!(group.written() < 100);
+    }
+
+    #[test]
+    fn error() {
+        let group = Arc::new(Families {
+            count: 100,
+            fail_at: Some(3),
+            written: AtomicUsize::new(0),
+        });
+
+        let (output, err) = block_on(async {
+            let mut stream = text_stream(group, 1);
+            let mut output = vec![];
+            let err = loop {
+                match stream.next_chunk().await.unwrap() {
+                    Ok(chunk) => output.extend_from_slice(&chunk),
+                    Err(e) => break e,
+                }
+            };
+            assert!(stream.next_chunk().await.is_none());
+            (output, err)
+        });
+
+        assert_eq!(
+            output,
+            b"# HELP metric 0\n# HELP metric 1\n# HELP metric 2\n"
+        );
+        assert_eq!(err.to_string(), "scrape failed");
+    }
+}